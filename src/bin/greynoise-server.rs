@@ -0,0 +1,358 @@
+//! `greynoise-server`: a local REST API in front of the GreyNoise API
+//!
+//! Holds the one shared API key, caches responses, coalesces concurrent lookups of the same
+//! IP and rate limits each caller, so teams can get verdicts over plain HTTP without
+//! managing keys or quotas themselves.
+//!
+//! Routes:
+//!
+//! - `GET /ip/{ip}`: merged verdict (Quick Check, then IP Context for noise and RIOT for
+//!   RIOT hits)
+//! - `POST /bulk`: merged verdicts for a JSON array of IPs (or `{"ips": [...]}`)
+//! - `GET /gnql?query=...&size=...&scroll=...`: one page of GNQL results
+//! - `GET /stats`: cache statistics
+//!
+//! Errors are returned as `{"error": "..."}`. Upstream authentication and server errors
+//! become `502`, upstream rate limiting `503`, and callers over their own rate limit get
+//! `429` with a `Retry-After` header. Rate limits count IPs looked up, so a `/bulk` request
+//! costs one token per IP.
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::{ConnectInfo, Path as UrlPath, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use clap::Parser;
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+
+use greynoise::cache::Cache;
+use greynoise::client::Client;
+use greynoise::enrich::{parse_ip, Enricher};
+use greynoise::error::Error;
+use greynoise::gn;
+use greynoise::rate_limit::RateLimiter;
+
+/// Most IPs accepted by one `/bulk` request
+const BULK_LIMIT: usize = 10_000;
+
+#[derive(Parser)]
+#[command(name = "greynoise-server", version, about = "Serve GreyNoise verdicts over a local REST API")]
+struct Cli {
+  /// Address to listen on
+  #[arg(short, long, default_value = "127.0.0.1:8080")]
+  listen: SocketAddr,
+
+  /// GreyNoise API key (defaults to the one in the config file)
+  #[arg(short, long)]
+  key: Option<String>,
+
+  /// Path to the GreyNoise config file
+  #[arg(short, long, default_value = "~/.config/greynoise/config")]
+  config: String,
+
+  /// Number of responses kept in the cache
+  #[arg(long, default_value_t = 100_000)]
+  cache_size: usize,
+
+  /// Requests per second allowed per caller
+  #[arg(long, default_value_t = 10.0, value_parser = positive_rate)]
+  rate: f64,
+
+  /// Burst of requests allowed per caller
+  #[arg(long, default_value_t = 50)]
+  burst: u32,
+
+  /// Rate limit by this request header (e.g. X-Team) instead of the caller's address
+  #[arg(long)]
+  rate_key_header: Option<String>,
+}
+
+/// Parse `--rate`, which must be a positive number
+fn positive_rate(s: &str) -> Result<f64, String> {
+  match s.parse::<f64>() {
+    Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+    _ => Err(format!("'{}' is not a positive number", s)),
+  }
+}
+
+struct AppState {
+  client: Client,
+  limiter: RateLimiter,
+  rate_key_header: Option<String>,
+}
+
+type Shared = Arc<AppState>;
+
+fn error(status: StatusCode, message: impl ToString) -> Response {
+  (status, Json(json!({ "error": message.to_string() }))).into_response()
+}
+
+/// Map a failed GreyNoise call onto the response sent to our caller
+fn upstream_error(e: impl Into<Error>) -> Response {
+  match e.into() {
+    Error::Api(status) => {
+      let ours = match status.as_u16() {
+        404 => StatusCode::NOT_FOUND,
+        429 => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::BAD_GATEWAY,
+      };
+      error(ours, format!("GreyNoise API returned {}", status))
+    }
+    refused => error(StatusCode::FORBIDDEN, refused),
+  }
+}
+
+/// Take `cost` rate limit tokens for the caller (one per IP looked up), returning the `429`
+/// response if there are not enough
+fn limited(state: &AppState, addr: &SocketAddr, headers: &HeaderMap, cost: usize) -> Option<Response> {
+
+  let key = state.rate_key_header.as_ref()
+    .and_then(|h| headers.get(h.as_str()))
+    .and_then(|v| v.to_str().ok())
+    .map(String::from)
+    .unwrap_or_else(|| addr.ip().to_string());
+
+  let cost = u32::try_from(cost.max(1)).unwrap_or(u32::MAX);
+  let wait = state.limiter.check_n(&key, cost).err()?;
+
+  let mut res = error(StatusCode::TOO_MANY_REQUESTS, "rate limit exceeded");
+  if let Ok(v) = (wait.as_secs_f64().ceil() as u64).max(1).to_string().parse() {
+    res.headers_mut().insert(header::RETRY_AFTER, v);
+  }
+
+  Some(res)
+
+}
+
+/// Merged verdicts for `ips`, in the order given
+async fn verdicts(state: &AppState, ips: Vec<String>) -> Result<Vec<Value>, Response> {
+
+  let mut valid = Vec::with_capacity(ips.len());
+  for ip in ips {
+    match parse_ip(&ip) {
+      Some(parsed) => valid.push(parsed.to_string()),
+      None => return Err(error(StatusCode::BAD_REQUEST, format!("'{}' is not an IP address", ip))),
+    }
+  }
+
+  let mut unique = valid.clone();
+  unique.sort();
+  unique.dedup();
+
+  let enricher = Enricher::new(&state.client).context(true).riot(true);
+  let found = enricher.verdicts(&unique).await.map_err(upstream_error)?;
+
+  Ok(valid.iter()
+    .map(|ip| found.get(ip).map(|v| json!(v)).unwrap_or_else(|| json!({ "ip": ip, "noise": false, "riot": false })))
+    .collect())
+
+}
+
+async fn ip(State(state): State<Shared>, ConnectInfo(addr): ConnectInfo<SocketAddr>, headers: HeaderMap, UrlPath(ip): UrlPath<String>) -> Response {
+
+  if let Some(res) = limited(&state, &addr, &headers, 1) {
+    return res;
+  }
+
+  match verdicts(&state, vec![ip]).await {
+    Ok(mut v) => Json(v.remove(0)).into_response(),
+    Err(res) => res,
+  }
+
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BulkRequest {
+  List(Vec<String>),
+  Object { ips: Vec<String> },
+}
+
+async fn bulk(State(state): State<Shared>, ConnectInfo(addr): ConnectInfo<SocketAddr>, headers: HeaderMap, Json(req): Json<BulkRequest>) -> Response {
+
+  let ips = match req {
+    BulkRequest::List(ips) | BulkRequest::Object { ips } => ips,
+  };
+
+  if ips.len() > BULK_LIMIT {
+    return error(StatusCode::PAYLOAD_TOO_LARGE, format!("at most {} IPs per request", BULK_LIMIT));
+  }
+
+  if let Some(res) = limited(&state, &addr, &headers, ips.len()) {
+    return res;
+  }
+
+  match verdicts(&state, ips).await {
+    Ok(v) => Json(v).into_response(),
+    Err(res) => res,
+  }
+
+}
+
+#[derive(Deserialize)]
+struct GnqlParams {
+  query: String,
+  size: Option<i64>,
+  scroll: Option<String>,
+}
+
+async fn gnql(State(state): State<Shared>, ConnectInfo(addr): ConnectInfo<SocketAddr>, headers: HeaderMap, Query(params): Query<GnqlParams>) -> Response {
+
+  if let Some(res) = limited(&state, &addr, &headers, 1) {
+    return res;
+  }
+
+  match state.client.gnql_query(&params.query, params.size, params.scroll.as_deref()).await {
+    Ok(res) => Json(res).into_response(),
+    Err(status) => upstream_error(status),
+  }
+
+}
+
+async fn stats(State(state): State<Shared>) -> Response {
+  let stats = state.client.cache_stats().unwrap_or_default();
+  Json(json!({
+    "hits": stats.hits,
+    "misses": stats.misses,
+    "stale": stats.stale,
+    "hit_ratio": stats.hit_ratio(),
+  })).into_response()
+}
+
+fn app(state: Shared) -> Router {
+  Router::new()
+    .route("/ip/:ip", get(ip))
+    .route("/bulk", post(bulk))
+    .route("/gnql", get(gnql))
+    .route("/stats", get(stats))
+    .with_state(state)
+}
+
+#[tokio::main]
+async fn main() -> std::process::ExitCode {
+
+  let cli = Cli::parse();
+
+  let key = cli.key.clone().or_else(|| {
+    Path::new(shellexpand::tilde(&cli.config).as_ref()).exists().then(|| gn::api_key(Some(cli.config.clone())))
+  });
+
+  if key.is_none() {
+    eprintln!("no API key: pass --key or create {}", cli.config);
+    return std::process::ExitCode::from(2);
+  }
+
+  let limiter = match RateLimiter::new(cli.rate, cli.burst) {
+    Ok(limiter) => limiter,
+    Err(e) => {
+      eprintln!("{}", e);
+      return std::process::ExitCode::from(2);
+    }
+  };
+
+  let state = Arc::new(AppState {
+    client: Client::new(key.as_deref()).with_cache(Cache::memory(cli.cache_size).stale_while_revalidate(Duration::from_secs(300))),
+    limiter,
+    rate_key_header: cli.rate_key_header.clone(),
+  });
+
+  let app = app(state);
+
+  let listener = match tokio::net::TcpListener::bind(cli.listen).await {
+    Ok(listener) => listener,
+    Err(e) => {
+      eprintln!("could not listen on {}: {}", cli.listen, e);
+      return std::process::ExitCode::from(2);
+    }
+  };
+
+  eprintln!("greynoise-server listening on http://{}", cli.listen);
+
+  if let Err(e) = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await {
+    eprintln!("server error: {}", e);
+    return std::process::ExitCode::from(3);
+  }
+
+  std::process::ExitCode::SUCCESS
+
+}
+
+#[cfg(test)]
+mod tests {
+
+  use super::*;
+
+  #[tokio::test]
+  async fn bulk_requests_are_charged_per_ip() {
+
+    let state = Arc::new(AppState {
+      client: Client::new(Some("k")).with_base_url("http://127.0.0.1:9"),
+      limiter: RateLimiter::new(0.5, 2).unwrap(),
+      rate_key_header: None,
+    });
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let service = app(state).into_make_service_with_connect_info::<SocketAddr>();
+    tokio::spawn(async move { axum::serve(listener, service).await });
+
+    let http = reqwest::Client::new();
+
+    // Invalid IPs are refused before any upstream call, but still cost their tokens
+    let res = http.post(format!("{}/bulk", url)).json(&["a", "b", "c"]).send().await.unwrap();
+    assert_eq!(res.status().as_u16(), StatusCode::BAD_REQUEST.as_u16());
+
+    let res = http.get(format!("{}/ip/a", url)).send().await.unwrap();
+    assert_eq!(res.status().as_u16(), StatusCode::TOO_MANY_REQUESTS.as_u16());
+    let retry_after: u64 = res.headers()[header::RETRY_AFTER.as_str()].to_str().unwrap().parse().unwrap();
+    assert!((3..=4).contains(&retry_after));
+
+  }
+
+  #[cfg(feature = "mock")]
+  #[tokio::test]
+  async fn concurrent_lookups_share_upstream_requests() {
+    use greynoise::mock::{Fixtures, MockServer, Route};
+
+    let upstream = MockServer::start(Fixtures::sample()).unwrap();
+    upstream.latency(Duration::from_millis(50));
+
+    let state = Arc::new(AppState {
+      client: Client::new(Some("k")).with_base_url(&upstream.url()).with_cache(Cache::memory(100)),
+      limiter: RateLimiter::new(1000.0, 1000).unwrap(),
+      rate_key_header: None,
+    });
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let service = app(state).into_make_service_with_connect_info::<SocketAddr>();
+    tokio::spawn(async move { axum::serve(listener, service).await });
+
+    let http = reqwest::Client::new();
+    let mut tasks = Vec::new();
+    for n in 0..10 {
+      let request = if n % 2 == 0 {
+        http.get(format!("{}/ip/45.83.66.65", url))
+      } else {
+        http.post(format!("{}/bulk", url)).json(&["8.8.8.8", "45.83.66.65"])
+      };
+      tasks.push(tokio::spawn(request.send()));
+    }
+    for t in tasks {
+      assert_eq!(t.await.unwrap().unwrap().status().as_u16(), StatusCode::OK.as_u16());
+    }
+
+    let count = |route: Route| upstream.requests().iter().filter(|(r, _)| *r == route).count();
+    assert_eq!(count(Route::Quick), 1);
+    assert_eq!(count(Route::MultiQuick), 1);
+    assert_eq!(count(Route::Context), 1);
+    assert_eq!(count(Route::Riot), 1);
+
+  }
+
+}
@@ -0,0 +1,296 @@
+//! Build firewall blocklists from GNQL results
+//!
+//! A [`Blocklist`] is the set of IPs matched by a GNQL query (e.g.
+//! `classification:malicious last_seen:1d`), collected across every page of results. IPs
+//! that are in RIOT, non-routable or covered by an [`Allowlist`] are always left out, so a
+//! list never blocks common business services or your own networks. The list renders as
+//! an ipset restore file, iptables-restore and ip6tables-restore rulesets, an nftables
+//! ruleset, a pf table, a plain list or a Palo Alto / Fortinet style external dynamic list
+//! (EDL).
+use std::fmt::Write as _;
+use std::io;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::client::Client;
+use crate::error::Error;
+use crate::enrich::is_routable;
+use crate::gnql::Datum;
+use crate::riot_dataset::{addr_bits, parse_cidr};
+
+/// GNQL results requested per page when building a blocklist
+#[doc(hidden)]
+const PAGE_SIZE: i64 = 1000;
+
+/// Firewall list formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlocklistFormat {
+  /// One IP per line, with a `#` comment header
+  Plain,
+
+  /// `ipset restore` input with one set per address family
+  Ipset,
+
+  /// `iptables-restore --noflush` input dropping each IPv4 address in its own chain. The
+  /// chain is not jumped to: hook it up once with `iptables -I INPUT -j <name>` (and
+  /// `FORWARD` on a router). IPv6 addresses go in an [`BlocklistFormat::Ip6tables`] ruleset.
+  Iptables,
+
+  /// `ip6tables-restore --noflush` input, like [`BlocklistFormat::Iptables`] for the IPv6
+  /// addresses
+  Ip6tables,
+
+  /// `nft -f` input with a `table inet` holding address sets and a drop chain
+  Nftables,
+
+  /// pf table file, for `table <name> persist file "..."`
+  Pf,
+
+  /// External dynamic list: IPs only, no comments
+  Edl,
+}
+
+impl FromStr for BlocklistFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<BlocklistFormat, String> {
+    match s.to_ascii_lowercase().as_str() {
+      "plain" | "txt" => Ok(BlocklistFormat::Plain),
+      "ipset" => Ok(BlocklistFormat::Ipset),
+      "iptables" => Ok(BlocklistFormat::Iptables),
+      "ip6tables" => Ok(BlocklistFormat::Ip6tables),
+      "nftables" | "nft" => Ok(BlocklistFormat::Nftables),
+      "pf" => Ok(BlocklistFormat::Pf),
+      "edl" => Ok(BlocklistFormat::Edl),
+      other => Err(format!("unknown blocklist format '{}' (expected plain, ipset, iptables, ip6tables, nftables, pf or edl)", other)),
+    }
+  }
+}
+
+/// Networks that must never be blocked
+#[derive(Debug, Clone, Default)]
+pub struct Allowlist {
+  ranges: Vec<(u128, u8, u8)>,
+}
+
+impl Allowlist {
+
+  /// Build an allowlist from CIDRs or bare addresses. Blank entries and `#` comments are
+  /// skipped, so the lines of an allowlist file can be passed as-is.
+  ///
+  /// # Errors
+  /// Returns an `InvalidData` error naming the first entry that is not a valid CIDR.
+  pub fn new<I, S>(cidrs: I) -> io::Result<Allowlist>
+    where I: IntoIterator<Item = S>, S: AsRef<str>, {
+
+    let mut ranges = Vec::new();
+
+    for cidr in cidrs {
+      let cidr = cidr.as_ref().split('#').next().unwrap_or("").trim();
+      if cidr.is_empty() {
+        continue;
+      }
+      let (ip, bits, len) = parse_cidr(cidr)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("invalid allowlist CIDR '{}'", cidr)))?;
+      ranges.push((bits, len, addr_bits(ip).1));
+    }
+
+    Ok(Allowlist { ranges })
+
+  }
+
+  /// Returns `true` if `ip` falls in one of the allowlisted networks
+  pub fn contains(&self, ip: &IpAddr) -> bool {
+
+    let (bits, width) = addr_bits(*ip);
+
+    self.ranges.iter().any(|(net, len, family)| {
+      let mask = if *len == 0 { 0 } else { u128::MAX << (128 - *len as u32) };
+      *family == width && (bits & mask) == (net & mask)
+    })
+
+  }
+
+}
+
+/// IPs to block, with a record of what was left out
+#[derive(Debug, Clone, Default)]
+pub struct Blocklist {
+  /// The GNQL query the list was built from
+  pub query: String,
+
+  /// Sorted, de-duplicated addresses to block
+  pub ips: Vec<IpAddr>,
+
+  /// Number of results left out because they are in RIOT
+  pub excluded_riot: usize,
+
+  /// Number of results left out because they are allowlisted or not routable
+  pub excluded_allowlist: usize,
+}
+
+impl Blocklist {
+
+  /// Build a blocklist from GNQL results, leaving out RIOT, allowlisted and non-routable IPs
+  pub fn from_data(query: &str, data: &[Datum], allowlist: &Allowlist) -> Blocklist {
+
+    let mut list = Blocklist { query: query.to_string(), ..Blocklist::default() };
+
+    for datum in data {
+
+      let ip: IpAddr = match datum.ip.parse() {
+        Ok(ip) => ip,
+        Err(_) => continue,
+      };
+
+      if datum.riot == Some(true) {
+        list.excluded_riot += 1;
+      } else if !is_routable(&ip) || allowlist.contains(&ip) {
+        list.excluded_allowlist += 1;
+      } else {
+        list.ips.push(ip);
+      }
+
+    }
+
+    list.ips.sort();
+    list.ips.dedup();
+
+    list
+
+  }
+
+  fn v4(&self) -> impl Iterator<Item = &IpAddr> {
+    self.ips.iter().filter(|ip| ip.is_ipv4())
+  }
+
+  fn v6(&self) -> impl Iterator<Item = &IpAddr> {
+    self.ips.iter().filter(|ip| ip.is_ipv6())
+  }
+
+  /// Number of addresses a `format` ruleset cannot hold (the IPv6 addresses of an
+  /// iptables ruleset, the IPv4 ones of an ip6tables ruleset)
+  pub fn left_out(&self, format: BlocklistFormat) -> usize {
+    match format {
+      BlocklistFormat::Iptables => self.v6().count(),
+      BlocklistFormat::Ip6tables => self.v4().count(),
+      _ => 0,
+    }
+  }
+
+  fn header(&self, out: &mut String) {
+    let _ = writeln!(out, "# GreyNoise blocklist: {}", self.query);
+    let _ = writeln!(out, "# {} addresses ({} RIOT and {} allowlisted results excluded)", self.ips.len(), self.excluded_riot, self.excluded_allowlist);
+  }
+
+  /// Render the list in `format`. `name` names the ipset sets, iptables chain, nftables
+  /// table or pf table; IPv6 sets get a `6` suffix.
+  ///
+  /// # Example
+  /// ```rust
+  /// use greynoise::blocklist::{Blocklist, BlocklistFormat};
+  ///
+  /// let list = Blocklist { ips: vec!["45.83.66.65".parse().unwrap()], ..Blocklist::default() };
+  /// assert_eq!(list.render(BlocklistFormat::Edl, "greynoise"), "45.83.66.65\n");
+  ///```
+  pub fn render(&self, format: BlocklistFormat, name: &str) -> String {
+
+    let mut out = String::new();
+
+    match format {
+      BlocklistFormat::Edl => {
+        for ip in &self.ips {
+          let _ = writeln!(out, "{}", ip);
+        }
+      }
+      BlocklistFormat::Plain | BlocklistFormat::Pf => {
+        self.header(&mut out);
+        for ip in &self.ips {
+          let _ = writeln!(out, "{}", ip);
+        }
+      }
+      BlocklistFormat::Ipset => {
+        self.header(&mut out);
+        for (set, family, ips) in [(name.to_string(), "inet", self.v4().collect::<Vec<_>>()), (format!("{}6", name), "inet6", self.v6().collect())] {
+          let _ = writeln!(out, "create {} hash:ip family {} -exist", set, family);
+          let _ = writeln!(out, "flush {}", set);
+          for ip in ips {
+            let _ = writeln!(out, "add {} {}", set, ip);
+          }
+        }
+      }
+      BlocklistFormat::Iptables | BlocklistFormat::Ip6tables => {
+        let (command, other, ips) = match format {
+          BlocklistFormat::Iptables => ("iptables", "ip6tables", self.v4().collect::<Vec<_>>()),
+          _ => ("ip6tables", "iptables", self.v6().collect()),
+        };
+        self.header(&mut out);
+        let _ = writeln!(out, "# load with {}-restore --noflush, then jump to the chain once: {} -I INPUT -j {}", command, command, name);
+        if self.left_out(format) > 0 {
+          let _ = writeln!(out, "# {} addresses are not in this {} ruleset: render them as {}", self.left_out(format), command, other);
+        }
+        let _ = writeln!(out, "*filter");
+        let _ = writeln!(out, ":{} - [0:0]", name);
+        let _ = writeln!(out, "-F {}", name);
+        for ip in ips {
+          let _ = writeln!(out, "-A {} -s {} -j DROP", name, ip);
+        }
+        let _ = writeln!(out, "COMMIT");
+      }
+      BlocklistFormat::Nftables => {
+        self.header(&mut out);
+        // Declaring the table first lets the delete succeed on the first load, so every
+        // load replaces the sets and chain instead of adding to them
+        let _ = writeln!(out, "# load with nft -f; the table is replaced on every load");
+        let _ = writeln!(out, "table inet {}", name);
+        let _ = writeln!(out, "delete table inet {}", name);
+        let _ = writeln!(out, "table inet {} {{", name);
+        for (set, ty, ips) in [("blocklist_v4", "ipv4_addr", self.v4().map(ToString::to_string).collect::<Vec<_>>()), ("blocklist_v6", "ipv6_addr", self.v6().map(ToString::to_string).collect())] {
+          let _ = writeln!(out, "  set {} {{", set);
+          let _ = writeln!(out, "    type {}", ty);
+          if !ips.is_empty() {
+            let _ = writeln!(out, "    elements = {{ {} }}", ips.join(", "));
+          }
+          let _ = writeln!(out, "  }}");
+        }
+        let _ = writeln!(out, "  chain input {{");
+        let _ = writeln!(out, "    type filter hook input priority 0; policy accept;");
+        let _ = writeln!(out, "    ip saddr @blocklist_v4 drop");
+        let _ = writeln!(out, "    ip6 saddr @blocklist_v6 drop");
+        let _ = writeln!(out, "  }}");
+        let _ = writeln!(out, "}}");
+      }
+    }
+
+    out
+
+  }
+
+}
+
+/// Run `query` through GNQL, following every page (up to `limit` results), and build a
+/// blocklist from the results.
+///
+/// # Example
+/// ```rust,no_run
+/// use greynoise::blocklist::{blocklist, Allowlist, BlocklistFormat};
+/// use greynoise::client::Client;
+///
+/// async {
+///  let client = Client::new(Some("API_KEY"));
+///  let allow = Allowlist::new(["203.0.113.0/24"]).unwrap();
+///  let list = blocklist(&client, "classification:malicious last_seen:1d", &allow, None).await.unwrap();
+///  print!("{}", list.render(BlocklistFormat::Nftables, "greynoise"));
+/// };
+///```
+///
+/// # Errors
+/// Returns the [`Error`] of the first GNQL page that fails, or the client's refusal when
+/// plan gating rules GNQL out.
+pub async fn blocklist(client: &Client, query: &str, allowlist: &Allowlist, limit: Option<usize>) -> Result<Blocklist, Error> {
+
+  let data = client.gnql_query_all(query, PAGE_SIZE, limit).await?;
+
+  Ok(Blocklist::from_data(query, &data, allowlist))
+
+}
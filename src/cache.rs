@@ -0,0 +1,312 @@
+//! Opt-in response cache for the GreyNoise client
+//!
+//! Responses are cached as the raw JSON returned by the API, keyed by endpoint and IP address.
+//! Each endpoint has its own time-to-live, since RIOT data changes far less often than
+//! Quick Check data. Storage is delegated to a [`CacheBackend`], so the bundled in-memory
+//! LRU backend can be swapped for a persistent one such as the SQLite backend provided by
+//! the `sqlite-cache` feature.
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use lru::LruCache;
+
+/// API endpoints whose responses can be cached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+  Community,
+  Riot,
+  IpContext,
+  QuickCheck,
+  Metadata,
+}
+
+impl Endpoint {
+
+  /// Stable name of the endpoint, suitable for use in cache keys
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Endpoint::Community => "community",
+      Endpoint::Riot => "riot",
+      Endpoint::IpContext => "ip_context",
+      Endpoint::QuickCheck => "quick_check",
+      Endpoint::Metadata => "metadata",
+    }
+  }
+
+  /// Default time-to-live for responses from this endpoint
+  pub fn default_ttl(&self) -> Duration {
+    match self {
+      Endpoint::Community => Duration::from_secs(60 * 60),
+      Endpoint::Riot => Duration::from_secs(24 * 60 * 60),
+      Endpoint::IpContext => Duration::from_secs(60 * 60),
+      Endpoint::QuickCheck => Duration::from_secs(10 * 60),
+      Endpoint::Metadata => Duration::from_secs(24 * 60 * 60),
+    }
+  }
+
+}
+
+/// A cached API response: the HTTP status, the raw JSON body and when it was fetched.
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+  pub status: u16,
+  pub body: String,
+  pub fetched_at: SystemTime,
+}
+
+impl CachedResponse {
+
+  /// Age of the response, or zero if `fetched_at` is in the future
+  pub fn age(&self) -> Duration {
+    SystemTime::now().duration_since(self.fetched_at).unwrap_or_default()
+  }
+
+}
+
+/// Storage for cached responses.
+///
+/// Implementations only store and retrieve entries; expiry is decided by [`Cache`]
+/// using each entry's `fetched_at`, so a backend may keep entries past their TTL.
+pub trait CacheBackend: Send + Sync {
+
+  /// Retrieve the response stored for `ip` on `endpoint`, if any
+  fn get(&self, endpoint: Endpoint, ip: &str) -> Option<CachedResponse>;
+
+  /// Store (or replace) the response for `ip` on `endpoint`
+  fn put(&self, endpoint: Endpoint, ip: &str, response: CachedResponse);
+
+  /// Remove the response stored for `ip` on `endpoint`, if any
+  fn remove(&self, endpoint: Endpoint, ip: &str);
+
+  /// Remove the responses stored for `ip` on every endpoint, returning how many were removed
+  fn purge_ip(&self, ip: &str) -> usize;
+
+  /// Remove the responses fetched more than `age` ago, returning how many were removed
+  fn purge_older_than(&self, age: Duration) -> usize;
+
+}
+
+/// In-memory cache backend that evicts the least recently used entry once full
+pub struct MemoryBackend {
+  entries: Mutex<LruCache<(Endpoint, String), CachedResponse>>,
+}
+
+impl MemoryBackend {
+
+  /// Create a backend holding at most `capacity` responses (minimum 1)
+  pub fn new(capacity: usize) -> MemoryBackend {
+    let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+    MemoryBackend {
+      entries: Mutex::new(LruCache::new(capacity)),
+    }
+  }
+
+  /// Number of responses currently held
+  pub fn len(&self) -> usize {
+    self.entries.lock().unwrap().len()
+  }
+
+  /// Returns `true` if no responses are held
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  fn purge_where<F>(&self, matches: F) -> usize
+    where F: Fn(&(Endpoint, String), &CachedResponse) -> bool, {
+
+    let mut entries = self.entries.lock().unwrap();
+
+    let doomed: Vec<(Endpoint, String)> = entries.iter()
+      .filter(|(k, v)| matches(k, v))
+      .map(|(k, _)| k.clone())
+      .collect();
+
+    for k in &doomed {
+      entries.pop(k);
+    }
+
+    doomed.len()
+
+  }
+
+}
+
+impl CacheBackend for MemoryBackend {
+
+  fn get(&self, endpoint: Endpoint, ip: &str) -> Option<CachedResponse> {
+    self.entries.lock().unwrap().get(&(endpoint, ip.to_string())).cloned()
+  }
+
+  fn put(&self, endpoint: Endpoint, ip: &str, response: CachedResponse) {
+    self.entries.lock().unwrap().put((endpoint, ip.to_string()), response);
+  }
+
+  fn remove(&self, endpoint: Endpoint, ip: &str) {
+    self.entries.lock().unwrap().pop(&(endpoint, ip.to_string()));
+  }
+
+  fn purge_ip(&self, ip: &str) -> usize {
+    self.purge_where(|(_, entry_ip), _| entry_ip == ip)
+  }
+
+  fn purge_older_than(&self, age: Duration) -> usize {
+    self.purge_where(|_, response| response.age() > age)
+  }
+
+}
+
+/// Cache hit/miss counters.
+///
+/// `stale` counts the hits that were served past their TTL under stale-while-revalidate;
+/// they are included in `hits`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+  pub hits: u64,
+  pub misses: u64,
+  pub stale: u64,
+}
+
+impl CacheStats {
+
+  /// Fraction of lookups served from the cache, or `0.0` if there were none
+  pub fn hit_ratio(&self) -> f64 {
+    let total = self.hits + self.misses;
+    if total == 0 { 0.0 } else { self.hits as f64 / total as f64 }
+  }
+
+}
+
+/// A response found in the cache
+#[derive(Debug, Clone)]
+pub enum CacheEntry {
+  /// Within its TTL
+  Fresh(CachedResponse),
+
+  /// Past its TTL but within the stale-while-revalidate window; it should be refreshed
+  Stale(CachedResponse),
+}
+
+/// Response cache used by [`crate::client::Client`].
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+/// use greynoise::cache::{Cache, Endpoint};
+/// use greynoise::client::Client;
+///
+/// let cache = Cache::memory(10_000).ttl(Endpoint::QuickCheck, Duration::from_secs(300));
+/// let client = Client::new(None).with_cache(cache);
+/// assert_eq!(client.cache_stats().unwrap().hits, 0);
+///```
+pub struct Cache {
+  backend: Box<dyn CacheBackend>,
+  ttls: HashMap<Endpoint, Duration>,
+  stale_window: Duration,
+  hits: AtomicU64,
+  misses: AtomicU64,
+  stale: AtomicU64,
+}
+
+impl Cache {
+
+  /// Create a cache on top of `backend` using each endpoint's default TTL
+  pub fn new<B: CacheBackend + 'static>(backend: B) -> Cache {
+    Cache {
+      backend: Box::new(backend),
+      ttls: HashMap::new(),
+      stale_window: Duration::ZERO,
+      hits: AtomicU64::new(0),
+      misses: AtomicU64::new(0),
+      stale: AtomicU64::new(0),
+    }
+  }
+
+  /// Create an in-memory LRU cache holding at most `capacity` responses
+  pub fn memory(capacity: usize) -> Cache {
+    Cache::new(MemoryBackend::new(capacity))
+  }
+
+  /// Override the time-to-live for responses from `endpoint`
+  pub fn ttl(mut self, endpoint: Endpoint, ttl: Duration) -> Cache {
+    self.ttls.insert(endpoint, ttl);
+    self
+  }
+
+  /// Keep serving responses for up to `window` past their TTL while a fresh copy is fetched
+  /// in the background
+  pub fn stale_while_revalidate(mut self, window: Duration) -> Cache {
+    self.stale_window = window;
+    self
+  }
+
+  /// Time-to-live in effect for `endpoint`
+  pub fn ttl_for(&self, endpoint: Endpoint) -> Duration {
+    self.ttls.get(&endpoint).copied().unwrap_or_else(|| endpoint.default_ttl())
+  }
+
+  /// Retrieve a response that is still within its TTL, recording a hit or a miss
+  pub fn get(&self, endpoint: Endpoint, ip: &str) -> Option<CachedResponse> {
+    match self.lookup(endpoint, ip) {
+      Some(CacheEntry::Fresh(r)) => Some(r),
+      _ => None,
+    }
+  }
+
+  /// Retrieve a response that is within its TTL or the stale-while-revalidate window,
+  /// recording a hit or a miss
+  pub fn lookup(&self, endpoint: Endpoint, ip: &str) -> Option<CacheEntry> {
+
+    let ttl = self.ttl_for(endpoint);
+
+    let entry = match self.backend.get(endpoint, ip) {
+      Some(r) if r.age() <= ttl => Some(CacheEntry::Fresh(r)),
+      Some(r) if r.age() <= ttl + self.stale_window => Some(CacheEntry::Stale(r)),
+      _ => None,
+    };
+
+    match entry {
+      Some(CacheEntry::Fresh(_)) => { self.hits.fetch_add(1, Ordering::Relaxed); }
+      Some(CacheEntry::Stale(_)) => {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        self.stale.fetch_add(1, Ordering::Relaxed);
+      }
+      None => { self.misses.fetch_add(1, Ordering::Relaxed); }
+    }
+
+    entry
+
+  }
+
+  /// Store a response fetched just now
+  pub fn put(&self, endpoint: Endpoint, ip: &str, status: u16, body: String) {
+    self.backend.put(endpoint, ip, CachedResponse { status, body, fetched_at: SystemTime::now() });
+  }
+
+  /// Drop any cached response for `ip` on `endpoint`
+  pub fn invalidate(&self, endpoint: Endpoint, ip: &str) {
+    self.backend.remove(endpoint, ip);
+  }
+
+  /// Drop every cached response for `ip`, returning how many were removed
+  pub fn purge_ip(&self, ip: &str) -> usize {
+    self.backend.purge_ip(ip)
+  }
+
+  /// Drop every cached response fetched more than `age` ago, returning how many were removed
+  pub fn purge_older_than(&self, age: Duration) -> usize {
+    self.backend.purge_older_than(age)
+  }
+
+  /// Hit/miss counters since the cache was created
+  pub fn stats(&self) -> CacheStats {
+    CacheStats {
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+      stale: self.stale.load(Ordering::Relaxed),
+    }
+  }
+
+}
@@ -0,0 +1,277 @@
+//! Record and replay API interactions
+//!
+//! A [`Cassette`] is a [`crate::transport::Middleware`]: attached to a
+//! [`crate::client::Client`] (see [`crate::client::Client::with_cassette`]) it sits in
+//! front of every request the client makes, and added with
+//! [`crate::transport::add_default_middleware`] in front of the module-level functions'. In
+//! record mode requests go to the API and each request/response pair is kept; the JSON
+//! cassette file is written once, by [`Cassette::save`] or when the last clone of the
+//! cassette is dropped. In replay mode no request leaves the process and each request is
+//! answered from the file.
+//!
+//! Requests are matched on method, path, query parameters (in any order) and JSON body; the
+//! host is ignored, so a cassette recorded against the API replays against any base URL.
+//! The API key is never written: the `key` header, and any `key` query parameter or query
+//! parameter carrying the key, are stored as `[REDACTED]`. Bodies are stored as they are.
+//!
+//! A replayed request that matches nothing in the cassette is answered with
+//! `501 Not Implemented`, the body naming the request and the recorded interactions, so a
+//! stale cassette fails a test loudly instead of silently.
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use http::StatusCode;
+use serde_json::Value;
+
+use crate::transport::{BoxFuture, HttpRequest, HttpResponse, Middleware, Next};
+
+#[doc(hidden)]
+const REDACTED: &str = "[REDACTED]";
+
+/// Whether a cassette talks to the API or only to its file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+  /// Send requests and append every interaction to the file
+  Record,
+
+  /// Answer requests from the file only
+  Replay,
+}
+
+/// A request as stored in a cassette
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordedRequest {
+  pub method: String,
+  pub path: String,
+
+  /// Query parameters, sorted
+  #[serde(default)]
+  pub query: Vec<(String, String)>,
+
+  /// Request headers worth keeping (only `key`, always redacted)
+  #[serde(default)]
+  pub headers: Vec<(String, String)>,
+
+  #[serde(default)]
+  pub body: Option<Value>,
+}
+
+/// A response as stored in a cassette
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecordedResponse {
+  pub status: u16,
+  pub body: String,
+}
+
+/// One request and the response it got
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Interaction {
+  pub request: RecordedRequest,
+  pub response: RecordedResponse,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct CassetteFile {
+  interactions: Vec<Interaction>,
+}
+
+/// A cassette file in record or replay mode. Clones share their interactions, so keep a
+/// clone to [`Cassette::save`] one added to the default pipeline, which is never dropped.
+///
+/// # Example
+/// ```rust,no_run
+/// use greynoise::cassette::Cassette;
+/// use greynoise::client::Client;
+///
+/// // Once, against the real API
+/// let client = Client::new(Some("API_KEY")).with_cassette(Cassette::record("tests/cassettes/riot.json"));
+///
+/// // From then on, offline
+/// let client = Client::new(None).with_cassette(Cassette::replay("tests/cassettes/riot.json").unwrap());
+/// async {
+///  let res = client.riot("8.8.8.8").await;
+/// };
+///```
+#[derive(Debug, Clone)]
+pub struct Cassette {
+  tape: Arc<Tape>,
+}
+
+/// State shared by a cassette's clones
+#[derive(Debug)]
+struct Tape {
+  path: PathBuf,
+  mode: CassetteMode,
+  interactions: Mutex<Vec<Interaction>>,
+  played: Mutex<Vec<bool>>,
+}
+
+impl Tape {
+
+  fn save(&self) -> io::Result<()> {
+
+    let file = CassetteFile { interactions: self.interactions.lock().unwrap().clone() };
+
+    if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+      std::fs::create_dir_all(dir)?;
+    }
+
+    std::fs::write(&self.path, serde_json::to_string_pretty(&file)?)
+
+  }
+
+}
+
+impl Drop for Tape {
+  fn drop(&mut self) {
+    if self.mode == CassetteMode::Record {
+      if let Err(e) = self.save() {
+        eprintln!("cassette {}: could not save: {}", self.path.display(), e);
+      }
+    }
+  }
+}
+
+/// Redact a query parameter if it is named `key` or carries the API key
+fn redact_param((name, value): (String, String), key: Option<&str>) -> (String, String) {
+  if name.eq_ignore_ascii_case("key") || key.is_some_and(|k| !k.is_empty() && value == k) {
+    (name, String::from(REDACTED))
+  } else {
+    (name, value)
+  }
+}
+
+/// Describe a request for matching, with the key redacted
+fn recorded_request(request: &HttpRequest, key: Option<&str>) -> RecordedRequest {
+
+  let (path, mut query) = match url::Url::parse(&request.url) {
+    Ok(u) => (u.path().to_string(), u.query_pairs().into_owned().map(|p| redact_param(p, key)).collect::<Vec<_>>()),
+    Err(_) => (request.url.clone(), Vec::new()),
+  };
+  query.sort();
+
+  let body = request.body.as_deref()
+    .map(|b| serde_json::from_str::<Value>(b).unwrap_or_else(|_| Value::String(b.to_string())));
+  let headers = key.map(|_| vec![(String::from("key"), String::from(REDACTED))]).unwrap_or_default();
+
+  RecordedRequest { method: request.method.as_str().to_string(), path, query, headers, body }
+
+}
+
+impl Cassette {
+
+  /// Record to `path`, replacing anything already there
+  pub fn record<P: AsRef<Path>>(path: P) -> Cassette {
+    Cassette::new(path.as_ref(), CassetteMode::Record, Vec::new())
+  }
+
+  fn new(path: &Path, mode: CassetteMode, interactions: Vec<Interaction>) -> Cassette {
+    let played = vec![false; interactions.len()];
+    Cassette {
+      tape: Arc::new(Tape {
+        path: path.to_path_buf(),
+        mode,
+        interactions: Mutex::new(interactions),
+        played: Mutex::new(played),
+      }),
+    }
+  }
+
+  /// Replay the interactions stored at `path`
+  ///
+  /// # Errors
+  /// Returns any error reading or parsing the cassette file.
+  pub fn replay<P: AsRef<Path>>(path: P) -> io::Result<Cassette> {
+
+    let file: CassetteFile = serde_json::from_str(&std::fs::read_to_string(path.as_ref())?)?;
+
+    Ok(Cassette::new(path.as_ref(), CassetteMode::Replay, file.interactions))
+
+  }
+
+  /// Record mode or replay mode
+  pub fn mode(&self) -> CassetteMode {
+    self.tape.mode
+  }
+
+  /// The interactions recorded or loaded so far
+  pub fn interactions(&self) -> Vec<Interaction> {
+    self.tape.interactions.lock().unwrap().clone()
+  }
+
+  /// Write the cassette file now; a recording cassette is also written when its last clone
+  /// is dropped
+  ///
+  /// # Errors
+  /// Returns any error creating or writing the file.
+  pub fn save(&self) -> io::Result<()> {
+    self.tape.save()
+  }
+
+  /// Answer `request` from the cassette: the first interaction not yet played that
+  /// matches, or else the last one that matches, or else a `501` describing the miss
+  fn play(&self, request: &RecordedRequest) -> Result<HttpResponse, StatusCode> {
+
+    let interactions = self.tape.interactions.lock().unwrap();
+    let mut played = self.tape.played.lock().unwrap();
+
+    let matching: Vec<usize> = interactions.iter().enumerate()
+      .filter(|(_, i)| i.request.method == request.method && i.request.path == request.path
+        && i.request.query == request.query && i.request.body == request.body)
+      .map(|(n, _)| n)
+      .collect();
+
+    let n = match matching.iter().find(|n| !played[**n]).or(matching.last()) {
+      Some(n) => *n,
+      None => {
+        let recorded: Vec<String> = interactions.iter()
+          .map(|i| format!("  {} {} {:?} {:?}", i.request.method, i.request.path, i.request.query, i.request.body))
+          .collect();
+        let body = format!(
+          "cassette {}: no recorded interaction matches {} {} {:?} {:?}\nrecorded:\n{}",
+          self.tape.path.display(), request.method, request.path, request.query, request.body, recorded.join("\n"),
+        );
+        return Ok(HttpResponse { status: StatusCode::NOT_IMPLEMENTED, body });
+      }
+    };
+
+    played[n] = true;
+
+    let response = &interactions[n].response;
+    let status = StatusCode::from_u16(response.status).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    Ok(HttpResponse { status, body: response.body.clone() })
+
+  }
+
+  fn store(&self, request: RecordedRequest, response: &HttpResponse) {
+
+    self.tape.interactions.lock().unwrap().push(Interaction {
+      request,
+      response: RecordedResponse { status: response.status.as_u16(), body: response.body.clone() },
+    });
+
+  }
+
+}
+
+impl Middleware for Cassette {
+  fn handle<'a>(&'a self, request: HttpRequest, next: Next<'a>) -> BoxFuture<'a, Result<HttpResponse, StatusCode>> {
+    Box::pin(async move {
+
+      let key = request.header_value("key").map(String::from);
+      let recorded = recorded_request(&request, key.as_deref());
+
+      match self.tape.mode {
+        CassetteMode::Replay => self.play(&recorded),
+        CassetteMode::Record => {
+          let response = next.run(request).await?;
+          self.store(recorded, &response);
+          Ok(response)
+        }
+      }
+
+    })
+  }
+}
@@ -0,0 +1,520 @@
+//! Reusable GreyNoise API client
+//!
+//! The module-level functions (`community::community`, `riot::riot`, …) take the API key on
+//! every call. A `Client` holds the key once and adds behaviour on top of those calls:
+//! optional response caching, an optional local RIOT dataset, and coalescing of concurrent
+//! identical lookups so that many callers asking about the same IP at once share a single
+//! HTTP request.
+//!
+//! Requests go out through a pluggable [`crate::transport::HttpTransport`] (`reqwest` by
+//! default) and any [`crate::transport::Middleware`] added to the client. A new client starts
+//! from the process-wide default pipeline (see [`crate::transport::set_default_transport`]).
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use http::StatusCode;
+
+use crate::cassette::Cassette;
+use crate::cache::{Cache, CacheEntry, CacheStats, CachedResponse, Endpoint};
+use crate::coalesce::Coalescer;
+use crate::error::Error;
+use crate::community::{self, Community};
+use crate::get;
+use crate::gnql::{self, Datum, QNQL};
+use crate::ip_context::{self, IPContext};
+use crate::lookup::Lookup;
+use crate::metadata::{self, Metadatum, TagActivity, TagMetadata};
+use crate::ping::{self, ApiEndpoint, Offering, Ping};
+use crate::policy::{Decision, Policy};
+use crate::quick_check::{self, MultiQuickCheck, QuickCheck};
+use crate::riot::{self, RIOT};
+use crate::riot_dataset::RiotDataset;
+use crate::similarity::{self, Similarity};
+use crate::timeline::{self, Granularity, HourlySummary, Timeline, TimelineField};
+use crate::transport::{self, HttpResponse, HttpTransport, Middleware, Pipeline};
+use crate::verdict::Verdict;
+
+/// GreyNoise API client holding the API key and optional response cache.
+///
+/// Concurrent Community, RIOT, IP Context, Quick Check and Metadata calls for the same IP
+/// are coalesced: one request goes out and every caller receives its result. Share one
+/// client (e.g. in an `Arc`) across tasks to benefit from this.
+///
+/// # Example
+/// ```rust
+/// use greynoise::cache::Cache;
+/// use greynoise::client::Client;
+///
+/// let client = Client::new(None).with_cache(Cache::memory(1_000));
+/// async {
+///  let res = client.community("8.8.8.8").await;
+/// };
+///```
+pub struct Client {
+  key: Option<String>,
+  cache: Option<Arc<Cache>>,
+  inflight: Arc<Coalescer<Result<HttpResponse, StatusCode>>>,
+  riot_dataset: Option<Arc<RiotDataset>>,
+  riot_fallback: bool,
+  base_url: Option<String>,
+  pipeline: Pipeline,
+  plan_gating: bool,
+  downgrade: bool,
+  /// The key's plan once known; `Some(None)` when ping did not report one
+  offering: Mutex<Option<Option<Offering>>>,
+}
+
+impl Client {
+
+  /// Create a client using the GreyNoise API key `key` (or no key)
+  pub fn new(key: Option<&str>) -> Client {
+    Client {
+      key: key.map(String::from),
+      cache: None,
+      inflight: Arc::new(Coalescer::new()),
+      riot_dataset: None,
+      riot_fallback: false,
+      base_url: None,
+      pipeline: transport::default_pipeline(),
+      plan_gating: false,
+      downgrade: false,
+      offering: Mutex::new(None),
+    }
+  }
+
+  /// Cache responses from the Community, RIOT, IP Context, Quick Check and Metadata endpoints
+  pub fn with_cache(mut self, cache: Cache) -> Client {
+    self.cache = Some(Arc::new(cache));
+    self
+  }
+
+  /// Answer RIOT lookups from a local dataset. IPs missing from the dataset are looked up
+  /// through the API if `api_fallback` is `true`, and reported as not found otherwise.
+  pub fn with_riot_dataset(mut self, dataset: RiotDataset, api_fallback: bool) -> Client {
+    self.riot_dataset = Some(Arc::new(dataset));
+    self.riot_fallback = api_fallback;
+    self
+  }
+
+  /// Send requests to `base_url` (e.g. `http://127.0.0.1:8080` for a local mock server)
+  /// instead of `https://api.greynoise.io`. Without this, the `GREYNOISE_API_URL`
+  /// environment variable is honoured.
+  pub fn with_base_url(mut self, base_url: &str) -> Client {
+    self.base_url = Some(base_url.to_string());
+    self
+  }
+
+  /// Send every request through `cassette`, recording interactions to it or replaying
+  /// them from it. The cassette is added to the end of the middleware chain.
+  pub fn with_cassette(self, cassette: Cassette) -> Client {
+    self.with_middleware(cassette)
+  }
+
+  /// Send requests with `transport` instead of the default one
+  pub fn with_transport<T: HttpTransport + 'static>(mut self, transport: T) -> Client {
+    self.pipeline.transport(Arc::new(transport));
+    self
+  }
+
+  /// Pass every request through `middleware`. Middleware runs in the order it is added,
+  /// the first added seeing each request first and each response last.
+  pub fn with_middleware<M: Middleware + 'static>(mut self, middleware: M) -> Client {
+    self.pipeline.push(Arc::new(middleware));
+    self
+  }
+
+  /// Refuse calls the API key's plan cannot make with [`Error::RequiresPlan`] instead of
+  /// sending them. The plan is learned from the ping endpoint on first use (or given with
+  /// [`Client::with_offering`]). With `downgrade`, IP Context and Quick Check calls the plan
+  /// cannot make are answered from the Community API instead of refused.
+  pub fn with_plan_gating(mut self, downgrade: bool) -> Client {
+    self.plan_gating = true;
+    self.downgrade = downgrade;
+    self
+  }
+
+  /// Use `offering` as the API key's plan instead of asking the ping endpoint
+  pub fn with_offering(self, offering: Offering) -> Client {
+    *self.offering.lock().unwrap() = Some(Some(offering));
+    self
+  }
+
+  /// The response cache, if one is configured
+  pub fn cache(&self) -> Option<&Cache> {
+    self.cache.as_deref()
+  }
+
+  /// Cache hit/miss counters, if a cache is configured
+  pub fn cache_stats(&self) -> Option<CacheStats> {
+    self.cache.as_deref().map(Cache::stats)
+  }
+
+  fn key(&self) -> Option<&str> {
+    self.key.as_deref()
+  }
+
+  /// `url` moved onto the configured base URL
+  fn url(&self, url: &str) -> String {
+    match &self.base_url {
+      Some(base) => get::rebase(url, base),
+      None => url.to_string(),
+    }
+  }
+
+  async fn fetch(&self, url: String) -> Result<HttpResponse, StatusCode> {
+    self.pipeline.send(get::get_request(url, self.key())).await
+  }
+
+  async fn post(&self, url: String, ips: Vec<String>) -> Result<HttpResponse, StatusCode> {
+    self.pipeline.send(get::post_request(url, ips, self.key())).await
+  }
+
+  /// Serve `ip` on `endpoint` from the cache, or fetch `url` and cache the response.
+  ///
+  /// A stale entry is served as-is while a background task fetches a fresh copy.
+  /// Fetches are coalesced by `url`.
+  async fn cached_fetch(&self, endpoint: Endpoint, ip: &str, url: String) -> Result<HttpResponse, StatusCode> {
+
+    let cache = match &self.cache {
+      Some(c) => c,
+      None => return self.inflight.run(url.clone(), || self.fetch(url)).await,
+    };
+
+    match cache.lookup(endpoint, ip) {
+      Some(CacheEntry::Fresh(hit)) => raw_response(hit),
+      Some(CacheEntry::Stale(hit)) => {
+        let (cache, inflight, pipeline) = (Arc::clone(cache), Arc::clone(&self.inflight), self.pipeline.clone());
+        let (ip, key) = (ip.to_string(), self.key.clone());
+        tokio::spawn(async move {
+          let _ = inflight.run(url.clone(), || fetch_and_store(&cache, &pipeline, endpoint, &ip, url, key.as_deref())).await;
+        });
+        raw_response(hit)
+      }
+      None => self.inflight.run(url.clone(), || fetch_and_store(cache, &self.pipeline, endpoint, ip, url, self.key())).await,
+    }
+
+  }
+
+  /// See [`ping::ping`]
+  pub async fn ping(&self) -> bool {
+    self.ping_details().await.is_ok()
+  }
+
+  /// See [`ping::ping_details`]
+  ///
+  /// The plan it reports is remembered for plan gating.
+  pub async fn ping_details(&self) -> Result<Ping, Error> {
+    let details: Ping = get::decode(&self.fetch(self.url(ping::PING_URL)).await?)?;
+    *self.offering.lock().unwrap() = Some(details.plan());
+    Ok(details)
+  }
+
+  /// The API key's plan, from [`Client::with_offering`] or else the ping endpoint (asked
+  /// once). `None` if ping does not report one.
+  pub async fn offering(&self) -> Result<Option<Offering>, Error> {
+
+    if let Some(offering) = self.offering.lock().unwrap().clone() {
+      return Ok(offering);
+    }
+
+    Ok(self.ping_details().await?.plan())
+
+  }
+
+  /// Check that the key's plan can call `endpoint`, when plan gating is on
+  async fn require(&self, endpoint: ApiEndpoint) -> Result<(), Error> {
+
+    if !self.plan_gating {
+      return Ok(());
+    }
+
+    // An unreported plan is let through, leaving the API to refuse what it cannot
+    match self.offering().await? {
+      Some(offering) if !offering.allows(endpoint) => Err(Error::RequiresPlan { endpoint, offering }),
+      _ => Ok(()),
+    }
+
+  }
+
+  /// Check that the key's plan can call `endpoint`, or that the call may be downgraded to
+  /// the Community API. Returns `true` to downgrade.
+  async fn require_or_downgrade(&self, endpoint: ApiEndpoint) -> Result<bool, Error> {
+    match self.require(endpoint).await {
+      Ok(()) => Ok(false),
+      Err(Error::RequiresPlan { .. }) if self.downgrade => Ok(true),
+      Err(e) => Err(e),
+    }
+  }
+
+  /// See [`community::community`]
+  pub async fn community(&self, ip: &str) -> Result<Lookup<Community>, Error> {
+    self.require(ApiEndpoint::Community).await?;
+    let url = self.url(&format!("{}/{}", community::COMMUNITY_URL, ip));
+    Ok(get::decode_lookup(&self.cached_fetch(Endpoint::Community, ip, url).await?)?)
+  }
+
+  /// See [`riot::riot`]
+  ///
+  /// If a local RIOT dataset is configured it is consulted first.
+  pub async fn riot(&self, ip: &str) -> Result<Lookup<RIOT>, Error> {
+
+    if let Some(dataset) = &self.riot_dataset {
+      if let Some(found) = dataset.lookup(ip) {
+        return Ok(Lookup::Found(found));
+      }
+      if !self.riot_fallback {
+        return Ok(Lookup::NotFound { message: String::from("IP not found in local RIOT dataset") });
+      }
+    }
+
+    self.require(ApiEndpoint::Riot).await?;
+
+    let url = self.url(&format!("{}/{}", riot::RIOT_URL, ip));
+    Ok(get::decode_lookup(&self.cached_fetch(Endpoint::Riot, ip, url).await?)?)
+
+  }
+
+  /// See [`ip_context::ip_context`]
+  ///
+  /// When downgraded by plan gating, the context only holds what the Community API knows.
+  pub async fn ip_context(&self, ip: &str) -> Result<IPContext, Error> {
+
+    if self.require_or_downgrade(ApiEndpoint::IpContext).await? {
+      return Ok(context_from_community(ip, self.community(ip).await?));
+    }
+
+    let url = self.url(&format!("{}/{}", ip_context::IP_CONTEXT_URL, ip));
+    Ok(get::decode(&self.cached_fetch(Endpoint::IpContext, ip, url).await?)?)
+
+  }
+
+  /// See [`quick_check::quick_check`]
+  ///
+  /// When downgraded by plan gating, the answer comes from the Community API.
+  pub async fn quick_check(&self, ip: &str) -> Result<QuickCheck, Error> {
+
+    if self.require_or_downgrade(ApiEndpoint::QuickCheck).await? {
+      return Ok(quick_check_from_community(ip, self.community(ip).await?));
+    }
+
+    let url = self.url(&format!("{}/{}", quick_check::QUICK_CHECK_CONTEXT_URL, ip));
+    Ok(get::decode(&self.cached_fetch(Endpoint::QuickCheck, ip, url).await?)?)
+
+  }
+
+  /// See [`quick_check::multi_quick_check`]
+  ///
+  /// IPs with a cached Quick Check result are answered from the cache and only the rest
+  /// are sent to the API, sharing the request of an identical batch already in flight.
+  /// Results are returned in the order of `ips`. When downgraded by
+  /// plan gating, each IP is looked up with the Community API.
+  pub async fn multi_quick_check(&self, ips: Vec<String>) -> Result<MultiQuickCheck, Error> {
+
+    if self.require_or_downgrade(ApiEndpoint::MultiQuickCheck).await? {
+      let mut results: MultiQuickCheck = Vec::with_capacity(ips.len());
+      for ip in &ips {
+        results.push(quick_check_from_community(ip, self.community(ip).await?));
+      }
+      return Ok(results);
+    }
+
+    let mut results: MultiQuickCheck = Vec::with_capacity(ips.len());
+    let mut misses: Vec<String> = Vec::new();
+
+    for ip in &ips {
+      match self.cache.as_deref().and_then(|c| c.get(Endpoint::QuickCheck, ip)) {
+        Some(hit) => results.push(get::decode(&raw_response(hit)?)?),
+        None => misses.push(ip.clone()),
+      }
+    }
+
+    if !misses.is_empty() {
+      misses.sort();
+      misses.dedup();
+      let url = self.url(quick_check::MULTI_QUICK_CHECK_CONTEXT_URL);
+      // Concurrent batches asking about the same IPs share one request
+      let batch = format!("POST {} {} {}", url, self.key().unwrap_or_default(), misses.join(","));
+      let fetched: MultiQuickCheck = get::decode(&self.inflight.run(batch, || self.post(url, misses)).await?)?;
+      if let Some(cache) = &self.cache {
+        for qc in &fetched {
+          if let Ok(body) = serde_json::to_string(qc) {
+            cache.put(Endpoint::QuickCheck, &qc.ip, StatusCode::OK.as_u16(), body);
+          }
+        }
+      }
+      results.extend(fetched);
+    }
+
+    let order: HashMap<&str, usize> = ips.iter().enumerate().map(|(i, ip)| (ip.as_str(), i)).collect();
+    results.sort_by_key(|qc| order.get(qc.ip.as_str()).copied().unwrap_or(usize::MAX));
+
+    Ok(results)
+
+  }
+
+  /// See [`verdict::verdict`]
+  ///
+  /// Lookups go through the client's cache and, with plan gating and downgrade, fall back
+  /// to the Community API.
+  pub async fn verdict(&self, ip: &str) -> Result<Verdict, Error> {
+
+    let qc = self.quick_check(ip).await?;
+
+    let riot = match qc.riot {
+      true => self.riot(ip).await?.found(),
+      false => None,
+    };
+
+    let context = match qc.noise {
+      true => Some(self.ip_context(ip).await?),
+      false => None,
+    };
+
+    Ok(Verdict::from_responses(qc, riot, context))
+
+  }
+
+  /// See [`policy::decide`](crate::policy::decide)
+  ///
+  /// Tag metadata goes through the client's cache, so with one configured it is fetched
+  /// once for any number of IPs.
+  pub async fn decide(&self, ip: &str, policy: &Policy) -> Result<Decision, Error> {
+
+    let verdict = self.verdict(ip).await?;
+
+    let tags = match policy.needs_tag_metadata(&verdict) {
+      true => self.metadata().await?.metadata,
+      false => Vec::new(),
+    };
+
+    Ok(policy.evaluate(&verdict, &tags))
+
+  }
+
+  /// See [`metadata::metadata`]
+  pub async fn metadata(&self) -> Result<TagMetadata, Error> {
+    self.require(ApiEndpoint::Metadata).await?;
+    let url = self.url(metadata::METADATA_URL);
+    Ok(get::decode(&self.cached_fetch(Endpoint::Metadata, "", url).await?)?)
+  }
+
+  /// See [`timeline::timeline`]
+  pub async fn timeline(&self, ip: &str, field: TimelineField, days: Option<u32>, granularity: Option<Granularity>) -> Result<Timeline, Error> {
+    self.require(ApiEndpoint::Timeline).await?;
+    let url = timeline::timeline_url(ip, "timeline", &timeline::timeline_params(field, days, granularity));
+    Ok(get::decode(&self.fetch(self.url(&url)).await?)?)
+  }
+
+  /// See [`timeline::hourly_summary`]
+  pub async fn hourly_summary(&self, ip: &str, days: Option<u32>, limit: Option<u32>, cursor: Option<&str>) -> Result<HourlySummary, Error> {
+    self.require(ApiEndpoint::Timeline).await?;
+    let url = timeline::timeline_url(ip, "hourly-summary", &timeline::hourly_summary_params(days, limit, cursor));
+    Ok(get::decode(&self.fetch(self.url(&url)).await?)?)
+  }
+
+  /// See [`similarity::similarity`]
+  pub async fn similarity(&self, ip: &str, min_score: Option<f64>, limit: Option<u32>) -> Result<Similarity, Error> {
+    self.require(ApiEndpoint::Similarity).await?;
+    Ok(get::decode(&self.fetch(self.url(&similarity::similarity_url(ip, min_score, limit))).await?)?)
+  }
+
+  /// See [`metadata::tag`]
+  pub async fn tag(&self, tag: &str) -> Result<Metadatum, Error> {
+    self.require(ApiEndpoint::Tags).await?;
+    Ok(get::decode(&self.fetch(self.url(&format!("{}/{}", metadata::TAGS_URL, tag))).await?)?)
+  }
+
+  /// See [`metadata::tag_activity`]
+  pub async fn tag_activity(&self, tag: &str, days: Option<u32>, granularity: Option<Granularity>) -> Result<TagActivity, Error> {
+    self.require(ApiEndpoint::Tags).await?;
+    Ok(get::decode(&self.fetch(self.url(&metadata::tag_activity_url(tag, days, granularity))).await?)?)
+  }
+
+  /// See [`metadata::tag_ips`]
+  pub async fn tag_ips(&self, name: &str, limit: Option<usize>) -> Result<Vec<Datum>, Error> {
+    self.gnql_query_all(&metadata::tag_query(name), metadata::TAG_IPS_PAGE_SIZE, limit).await
+  }
+
+  /// See [`gnql::gnql_query`]
+  pub async fn gnql_query(&self, query: &str, size: Option<i64>, scroll: Option<&str>) -> Result<QNQL, Error> {
+    self.require(ApiEndpoint::Gnql).await?;
+    Ok(self.gnql_page(query, size, scroll).await?)
+  }
+
+  async fn gnql_page(&self, query: &str, size: Option<i64>, scroll: Option<&str>) -> Result<QNQL, StatusCode> {
+    get::decode(&self.fetch(self.url(&gnql::gnql_url(query, size, scroll))).await?)
+  }
+
+  /// See [`gnql::gnql_query_all`]
+  pub async fn gnql_query_all(&self, query: &str, page_size: i64, limit: Option<usize>) -> Result<Vec<Datum>, Error> {
+
+    self.require(ApiEndpoint::Gnql).await?;
+
+    Ok(gnql::collect_pages(limit, |scroll| async move {
+      self.gnql_page(query, Some(page_size), scroll.as_deref()).await
+    }).await?)
+
+  }
+
+}
+
+/// Fetch `url` and cache the response.
+///
+/// Only `200` and `404` (not in the dataset) responses are cached.
+async fn fetch_and_store(cache: &Cache, pipeline: &Pipeline, endpoint: Endpoint, ip: &str, url: String, key: Option<&str>) -> Result<HttpResponse, StatusCode> {
+
+  let resp = pipeline.send(get::get_request(url, key)).await?;
+
+  if resp.status == StatusCode::OK || resp.status == StatusCode::NOT_FOUND {
+    cache.put(endpoint, ip, resp.status.as_u16(), resp.body.clone());
+  }
+
+  Ok(resp)
+
+}
+
+fn raw_response(cached: CachedResponse) -> Result<HttpResponse, StatusCode> {
+  let status = StatusCode::from_u16(cached.status).map_err(|_| StatusCode::BAD_REQUEST)?;
+  Ok(HttpResponse { status, body: cached.body })
+}
+
+/// The IP Context the Community API's answer amounts to
+fn context_from_community(ip: &str, lookup: Lookup<Community>) -> IPContext {
+
+  let community = lookup.found().filter(|c| c.noise);
+
+  IPContext {
+    ip: ip.to_string(),
+    seen: community.is_some(),
+    first_seen: None,
+    last_seen: community.as_ref().map(|c| c.last_seen.clone()),
+    tags: None,
+    actor: community.as_ref().map(|c| c.name.clone()),
+    spoofable: None,
+    classification: community.as_ref().map(|c| c.classification.clone()),
+    cve: None,
+    bot: None,
+    vpn: None,
+    vpn_service: None,
+    metadata: None,
+    raw_data: None,
+  }
+
+}
+
+/// The Quick Check the Community API's answer amounts to
+fn quick_check_from_community(ip: &str, lookup: Lookup<Community>) -> QuickCheck {
+
+  let (noise, riot) = match lookup.found() {
+    Some(c) => (c.noise, c.riot),
+    None => (false, false),
+  };
+
+  QuickCheck {
+    ip: ip.to_string(),
+    noise,
+    riot,
+    code: String::from(if noise { "0x01" } else { "0x00" }),
+  }
+
+}
@@ -0,0 +1,45 @@
+//! Share a single in-flight request between concurrent callers asking for the same thing
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::OnceCell;
+
+/// Deduplicates concurrent work by key.
+///
+/// The first caller for a key runs its future; callers arriving while it is in flight wait
+/// for that result instead of running their own. Once the work completes the key is
+/// released, so later calls start fresh. If the running caller is cancelled, one of the
+/// waiting callers takes over.
+pub struct Coalescer<T> {
+  inflight: Mutex<HashMap<String, Arc<OnceCell<T>>>>,
+}
+
+impl<T: Clone> Coalescer<T> {
+
+  pub fn new() -> Coalescer<T> {
+    Coalescer {
+      inflight: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// Run `work` for `key`, or wait for the identical call already in flight
+  pub async fn run<F, Fut>(&self, key: String, work: F) -> T
+    where F: FnOnce() -> Fut, Fut: Future<Output = T>, {
+
+    let cell = Arc::clone(
+      self.inflight.lock().unwrap().entry(key.clone()).or_insert_with(|| Arc::new(OnceCell::new()))
+    );
+
+    let value = cell.get_or_init(work).await.clone();
+
+    let mut inflight = self.inflight.lock().unwrap();
+    if inflight.get(&key).is_some_and(|c| Arc::ptr_eq(c, &cell)) {
+      inflight.remove(&key);
+    }
+
+    value
+
+  }
+
+}
@@ -1,5 +1,6 @@
 //! Make a request to the GreyNoise Community API
 use crate::get;
+use crate::lookup::Lookup;
 
 /// Structure to deserialize GreyNoise Community JSON.
 ///
@@ -32,7 +33,7 @@ pub struct Community {
 }
 
 #[doc(hidden)]
-const COMMUNITY_URL: &str = "https://api.greynoise.io/v3/community";
+pub(crate) const COMMUNITY_URL: &str = "https://api.greynoise.io/v3/community";
 
 /// Function to retrieve information about an IP address in the GreyNoise dataset
 ///
@@ -47,19 +48,22 @@ const COMMUNITY_URL: &str = "https://api.greynoise.io/v3/community";
 /// # Example
 /// ```rust
 /// use greynoise::community;
+/// use greynoise::lookup::Lookup;
 /// async {
-///  let res: Result<community::Community, reqwest::StatusCode> = community::community("8.8.8.8", None).await;
-///  assert_eq!(res.unwrap().ip, "8.8.8.8");
+///  let res: Result<Lookup<community::Community>, http::StatusCode> = community::community("8.8.8.8", None).await;
+///  assert_eq!(res.unwrap().found().unwrap().ip, "8.8.8.8");
 /// };
 ///```
 ///
+/// If the IP address is not in the dataset, it will return `Ok(Lookup::NotFound { message })`.
+///
 /// # Errors
 /// If the call fails, it will return a `Err(StatusCode)`.
 /// To see the possible return values, check the [API docs](https://docs.greynoise.io/reference/get_v3-community-ip?).
-pub async fn community(ip: &str, key: Option<&str>) -> Result<Community, reqwest::StatusCode> {
+pub async fn community(ip: &str, key: Option<&str>) -> Result<Lookup<Community>, http::StatusCode> {
 
   let url = format!("{}/{}", COMMUNITY_URL, ip);
-  let res: Result<Community, reqwest::StatusCode> = get::query(url, key).await;
+  let res: Result<Lookup<Community>, http::StatusCode> = get::lookup_query(url, key).await;
 
   res
 
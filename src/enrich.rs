@@ -0,0 +1,367 @@
+//! Annotate log lines with GreyNoise verdicts for the IP addresses they mention
+//!
+//! Lines are read in batches. The IPs in a batch are extracted (with a regex or by field
+//! position), de-duplicated and sent through the Multi Quick Check endpoint; noise hits can
+//! then be deepened with IP Context and RIOT hits with a RIOT lookup. Each line is written
+//! back unchanged with `gn.*` fields appended for every IP found on it, so web server access
+//! logs, SSH auth logs and firewall logs can all be enriched the same way.
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use regex::Regex;
+
+use crate::client::Client;
+use crate::error::Error;
+use crate::lookup::Lookup;
+
+/// Matches IPv4 addresses and IPv6 address candidates; candidates are validated by parsing
+#[doc(hidden)]
+const IP_PATTERN: &str = r"(?:\d{1,3}\.){3}\d{1,3}|[0-9A-Fa-f]{0,4}(?::[0-9A-Fa-f]{0,4}){2,7}(?:(?:\d{1,3}\.){3}\d{1,3})?";
+
+/// The Multi Quick Check endpoint accepts at most this many IPs per request
+pub const MULTI_QUICK_BATCH: usize = 1000;
+
+/// Most IP Context and RIOT lookups in flight at once when deepening verdicts
+#[doc(hidden)]
+const DEEPEN_CONCURRENCY: usize = 16;
+
+/// How IP addresses are found in a line
+#[derive(Debug, Clone)]
+pub enum Extractor {
+  /// Every match of the regex (or of its first capture group, if it has one) that parses
+  /// as an IP address
+  Regex(Regex),
+
+  /// The 1-based `index`th field when the line is split on `delimiter` (or on whitespace
+  /// if `None`), as with `cut -f`
+  Field { index: NonZeroUsize, delimiter: Option<char> },
+}
+
+impl Default for Extractor {
+  fn default() -> Extractor {
+    Extractor::Regex(Regex::new(IP_PATTERN).expect("valid IP pattern"))
+  }
+}
+
+/// Parse `candidate` as an IP address, tolerating `[v6]` brackets and a trailing `:port`
+pub fn parse_ip(candidate: &str) -> Option<IpAddr> {
+
+  let candidate = candidate.trim().trim_matches(|c| c == '"' || c == '\'' || c == ',');
+
+  if let Ok(ip) = candidate.parse() {
+    return Some(ip);
+  }
+
+  if let Some(rest) = candidate.strip_prefix('[') {
+    return rest.split(']').next()?.parse().ok();
+  }
+
+  match candidate.rsplit_once(':') {
+    Some((host, port)) if host.contains('.') && port.chars().all(|c| c.is_ascii_digit()) => host.parse().ok(),
+    _ => None,
+  }
+
+}
+
+/// Returns `true` for addresses GreyNoise can know about: not private, loopback,
+/// link-local, unspecified, multicast or documentation ranges
+pub fn is_routable(ip: &IpAddr) -> bool {
+  match ip {
+    IpAddr::V4(v4) => {
+      let o = v4.octets();
+      !(v4.is_private() || v4.is_loopback() || v4.is_link_local() || v4.is_unspecified()
+        || v4.is_broadcast() || v4.is_multicast() || v4.is_documentation()
+        || (o[0] == 100 && (o[1] & 0xc0) == 64))
+    }
+    IpAddr::V6(v6) => {
+      let s = v6.segments();
+      !(v6.is_loopback() || v6.is_unspecified() || v6.is_multicast()
+        || (s[0] & 0xfe00) == 0xfc00
+        || (s[0] & 0xffc0) == 0xfe80
+        || (s[0] == 0x2001 && s[1] == 0x0db8))
+    }
+  }
+}
+
+impl Extractor {
+
+  /// Routable IP addresses mentioned in `line`, in order of appearance, without duplicates
+  pub fn extract(&self, line: &str) -> Vec<String> {
+
+    let candidates: Vec<&str> = match self {
+      Extractor::Regex(re) => re.captures_iter(line)
+        .filter_map(|c| c.get(1).or_else(|| c.get(0)))
+        .map(|m| m.as_str())
+        .collect(),
+      Extractor::Field { index, delimiter } => {
+        let field = match delimiter {
+          Some(d) => line.split(*d).nth(index.get() - 1),
+          None => line.split_whitespace().nth(index.get() - 1),
+        };
+        field.into_iter().collect()
+      }
+    };
+
+    let mut ips: Vec<String> = Vec::new();
+    for ip in candidates.into_iter().filter_map(parse_ip).filter(is_routable) {
+      let ip = ip.to_string();
+      if !ips.contains(&ip) {
+        ips.push(ip);
+      }
+    }
+
+    ips
+
+  }
+
+}
+
+/// What GreyNoise knows about one IP address
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IpVerdict {
+  pub ip: String,
+
+  /// Observed scanning the internet (Quick Check)
+  pub noise: bool,
+
+  /// Part of the RIOT benign-services dataset (Quick Check)
+  pub riot: bool,
+
+  /// Quick Check response code
+  pub code: String,
+
+  /// From IP Context, when deepened
+  pub classification: Option<String>,
+
+  /// From IP Context, when deepened
+  pub actor: Option<String>,
+
+  /// From IP Context, when deepened
+  pub tags: Vec<String>,
+
+  /// RIOT provider name, when deepened
+  pub name: Option<String>,
+
+  /// RIOT provider category, when deepened
+  pub category: Option<String>,
+
+  /// RIOT trust level, when deepened
+  pub trust_level: Option<String>,
+}
+
+impl IpVerdict {
+
+  /// The verdict as `(field, value)` pairs, omitting fields that are not known
+  pub fn fields(&self) -> Vec<(&'static str, String)> {
+
+    let mut fields = vec![
+      ("ip", self.ip.clone()),
+      ("noise", self.noise.to_string()),
+      ("riot", self.riot.to_string()),
+      ("code", self.code.clone()),
+    ];
+
+    let optional = [
+      ("classification", self.classification.clone()),
+      ("actor", self.actor.clone()),
+      ("tags", Some(self.tags.join(",")).filter(|t| !t.is_empty())),
+      ("name", self.name.clone()),
+      ("category", self.category.clone()),
+      ("trust_level", self.trust_level.clone()),
+    ];
+
+    fields.extend(optional.into_iter().filter_map(|(k, v)| v.map(|v| (k, v))));
+
+    fields
+
+  }
+
+}
+
+/// Quote a `key=value` value if it contains whitespace, quotes or `=`
+fn kv_value(v: &str) -> String {
+  if v.is_empty() || v.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+    format!("\"{}\"", v.replace('\\', "\\\\").replace('"', "\\\""))
+  } else {
+    v.to_string()
+  }
+}
+
+/// Batch log enrichment driven by a [`Client`].
+///
+/// # Example
+/// ```rust,no_run
+/// use greynoise::client::Client;
+/// use greynoise::enrich::Enricher;
+///
+/// async {
+///  let client = Client::new(Some("API_KEY"));
+///  let enricher = Enricher::new(&client).context(true).riot(true);
+///  let file = std::fs::File::open("/var/log/nginx/access.log").unwrap();
+///  enricher.enrich(std::io::BufReader::new(file), std::io::stdout().lock()).await.unwrap();
+/// };
+///```
+pub struct Enricher<'a> {
+  client: &'a Client,
+  extractor: Extractor,
+  context: bool,
+  riot: bool,
+  pub(crate) batch_lines: usize,
+}
+
+impl<'a> Enricher<'a> {
+
+  /// Find IPs with the default regex and only use Quick Check
+  pub fn new(client: &'a Client) -> Enricher<'a> {
+    Enricher {
+      client,
+      extractor: Extractor::default(),
+      context: false,
+      riot: false,
+      batch_lines: 10_000,
+    }
+  }
+
+  /// Use `extractor` to find IPs in each line
+  pub fn extractor(mut self, extractor: Extractor) -> Enricher<'a> {
+    self.extractor = extractor;
+    self
+  }
+
+  /// Look noise hits up with IP Context for classification, actor and tags
+  pub fn context(mut self, context: bool) -> Enricher<'a> {
+    self.context = context;
+    self
+  }
+
+  /// Look RIOT hits up with RIOT for the provider name, category and trust level
+  pub fn riot(mut self, riot: bool) -> Enricher<'a> {
+    self.riot = riot;
+    self
+  }
+
+  /// Number of lines read per batch (default 10,000)
+  pub fn batch_lines(mut self, lines: usize) -> Enricher<'a> {
+    self.batch_lines = lines.max(1);
+    self
+  }
+
+  /// Verdicts for `ips`, keyed by IP.
+  ///
+  /// A single IP is looked up with Quick Check, more with Multi Quick Check; either way
+  /// identical lookups already in flight on the client are shared. IP Context and RIOT
+  /// lookups run concurrently.
+  ///
+  /// # Errors
+  /// Returns the [`Error`] of a failed Quick Check, IP Context or RIOT lookup, so a verdict
+  /// is never returned with its deeper fields silently missing.
+  pub async fn verdicts(&self, ips: &[String]) -> Result<HashMap<String, IpVerdict>, Error> {
+
+    let mut quick = Vec::with_capacity(ips.len());
+
+    match ips {
+      [ip] => quick.push(self.client.quick_check(ip).await?),
+      _ => for batch in ips.chunks(MULTI_QUICK_BATCH) {
+        quick.extend(self.client.multi_quick_check(batch.to_vec()).await?);
+      },
+    }
+
+    let verdicts = quick.into_iter().map(|qc| IpVerdict {
+      ip: qc.ip,
+      noise: qc.noise,
+      riot: qc.riot,
+      code: qc.code,
+      ..IpVerdict::default()
+    });
+
+    stream::iter(verdicts)
+      .map(|verdict| self.deepen(verdict))
+      .buffer_unordered(DEEPEN_CONCURRENCY)
+      .map_ok(|verdict| (verdict.ip.clone(), verdict))
+      .try_collect()
+      .await
+
+  }
+
+  /// Add IP Context details to a noise verdict and RIOT details to a RIOT one, as enabled
+  async fn deepen(&self, mut verdict: IpVerdict) -> Result<IpVerdict, Error> {
+
+    if self.context && verdict.noise {
+      let ctx = self.client.ip_context(&verdict.ip).await?;
+      verdict.classification = ctx.classification;
+      verdict.actor = ctx.actor;
+      verdict.tags = ctx.tags.unwrap_or_default();
+    }
+
+    if self.riot && verdict.riot {
+      if let Lookup::Found(r) = self.client.riot(&verdict.ip).await? {
+        verdict.name = Some(r.name);
+        verdict.category = Some(r.category);
+        verdict.trust_level = Some(r.trust_level);
+      }
+    }
+
+    Ok(verdict)
+
+  }
+
+  /// Enrich `lines`, returning each line with `gn.*` fields appended for its IPs
+  ///
+  /// # Errors
+  /// Returns the [`Error`] of a failed lookup.
+  pub async fn enrich_lines(&self, lines: &[String]) -> Result<Vec<String>, Error> {
+
+    let per_line: Vec<Vec<String>> = lines.iter().map(|l| self.extractor.extract(l)).collect();
+
+    let mut unique: Vec<String> = per_line.iter().flatten().cloned().collect();
+    unique.sort();
+    unique.dedup();
+
+    let verdicts = self.verdicts(&unique).await?;
+
+    Ok(lines.iter().zip(per_line).map(|(line, ips)| {
+      let mut out = line.clone();
+      for verdict in ips.iter().filter_map(|ip| verdicts.get(ip)) {
+        for (k, v) in verdict.fields() {
+          out.push_str(&format!(" gn.{}={}", k, kv_value(&v)));
+        }
+      }
+      out
+    }).collect())
+
+  }
+
+  /// Read lines from `reader` and write them, enriched, to `writer`
+  ///
+  /// # Errors
+  /// Returns any I/O error, or an error wrapping the [`Error`] of a failed lookup.
+  pub async fn enrich<R: BufRead, W: Write>(&self, reader: R, mut writer: W) -> io::Result<()> {
+
+    let mut lines = reader.lines();
+
+    loop {
+
+      let mut batch = Vec::with_capacity(self.batch_lines);
+      for line in lines.by_ref().take(self.batch_lines) {
+        batch.push(line?);
+      }
+
+      if batch.is_empty() {
+        return writer.flush();
+      }
+
+      let enriched = self.enrich_lines(&batch).await
+        .map_err(|e| io::Error::other(format!("GreyNoise quick check failed: {}", e)))?;
+
+      for line in enriched {
+        writeln!(writer, "{}", line)?;
+      }
+
+    }
+
+  }
+
+}
@@ -0,0 +1,70 @@
+//! Errors returned by [`crate::client::Client`]
+//!
+//! The module-level functions report failures as a bare `http::StatusCode`. A `Client`
+//! can also refuse a call itself, before any request is made, when the API key's plan does
+//! not include the endpoint (see [`crate::client::Client::with_plan_gating`]), so its
+//! methods return an [`Error`] that tells the two apart. `Error` converts to and from
+//! `StatusCode`, and compares equal to the status it carries, so code written against
+//! status codes keeps working.
+use std::fmt;
+
+use http::StatusCode;
+
+use crate::ping::{ApiEndpoint, Offering};
+
+/// Why a client call failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+  /// The API answered with this error status, or could not be reached
+  Api(StatusCode),
+
+  /// The API key's plan cannot use `endpoint`; no request was made
+  RequiresPlan {
+    endpoint: ApiEndpoint,
+    offering: Offering,
+  },
+}
+
+impl Error {
+
+  /// The HTTP status this error corresponds to (`403 Forbidden` for a plan refusal, which
+  /// is what the API itself would have answered)
+  pub fn status(&self) -> StatusCode {
+    match self {
+      Error::Api(status) => *status,
+      Error::RequiresPlan { .. } => StatusCode::FORBIDDEN,
+    }
+  }
+
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::Api(status) => write!(f, "{}", status),
+      Error::RequiresPlan { endpoint, offering } => {
+        write!(f, "the {} endpoint is not available on the {} plan", endpoint, offering)
+      }
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+impl From<StatusCode> for Error {
+  fn from(status: StatusCode) -> Error {
+    Error::Api(status)
+  }
+}
+
+impl From<Error> for StatusCode {
+  fn from(e: Error) -> StatusCode {
+    e.status()
+  }
+}
+
+impl PartialEq<StatusCode> for Error {
+  fn eq(&self, status: &StatusCode) -> bool {
+    self.status() == *status
+  }
+}
@@ -0,0 +1,54 @@
+//! Pieces shared by the STIX and MISP exporters
+use uuid::Uuid;
+
+use crate::gnql::Datum;
+use crate::ip_context::IPContext;
+
+/// Namespace of the deterministic IDs the exporters derive
+#[doc(hidden)]
+const GREYNOISE_NAMESPACE: &str = "https://www.greynoise.io/";
+
+/// Deterministic UUID (v5) for `name` in the GreyNoise namespace
+pub(crate) fn greynoise_uuid(name: &str) -> Uuid {
+  let namespace = Uuid::new_v5(&Uuid::NAMESPACE_URL, GREYNOISE_NAMESPACE.as_bytes());
+  Uuid::new_v5(&namespace, name.as_bytes())
+}
+
+/// The parts of an IP lookup that end up in an export
+pub(crate) struct IpFacts<'a> {
+  pub(crate) ip: &'a str,
+  pub(crate) first_seen: Option<&'a str>,
+  pub(crate) last_seen: Option<&'a str>,
+  pub(crate) classification: Option<&'a str>,
+  pub(crate) actor: Option<&'a str>,
+  pub(crate) tags: &'a [String],
+  pub(crate) cves: &'a [String],
+}
+
+impl<'a> From<&'a IPContext> for IpFacts<'a> {
+  fn from(context: &'a IPContext) -> IpFacts<'a> {
+    IpFacts {
+      ip: &context.ip,
+      first_seen: context.first_seen.as_deref(),
+      last_seen: context.last_seen.as_deref(),
+      classification: context.classification.as_deref(),
+      actor: context.actor.as_deref(),
+      tags: context.tags.as_deref().unwrap_or_default(),
+      cves: context.cve.as_deref().unwrap_or_default(),
+    }
+  }
+}
+
+impl<'a> From<&'a Datum> for IpFacts<'a> {
+  fn from(datum: &'a Datum) -> IpFacts<'a> {
+    IpFacts {
+      ip: &datum.ip,
+      first_seen: datum.first_seen.as_deref(),
+      last_seen: datum.last_seen.as_deref(),
+      classification: datum.classification.as_deref(),
+      actor: datum.actor.as_deref(),
+      tags: datum.tags.as_deref().unwrap_or_default(),
+      cves: datum.cve.as_deref().unwrap_or_default(),
+    }
+  }
+}
@@ -0,0 +1,352 @@
+//! Select fields by dotted path and flatten nested records into rows
+//!
+//! `IPContext` and `gnql::Datum` nest `metadata` and `raw_data` objects holding vectors of
+//! `Scan`, `JA3` and `HASSH` entries. A [`Flattener`] turns such records into flat rows with
+//! dotted column names (`metadata.asn`, `raw_data.scan.port`), keeping only the selected
+//! fields and handling arrays in one of three ways (see [`ArrayMode`]).
+//!
+//! Fields of the objects in one array stay aligned: exploding `raw_data.scan` yields one row
+//! per scan with its `port` and `protocol` side by side, not every port/protocol pairing.
+use std::str::FromStr;
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// How arrays are turned into cells
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ArrayMode {
+  /// Join the elements into one cell with the separator
+  #[default]
+  Join,
+
+  /// Emit one row per element; several arrays in one record multiply out
+  Explode,
+
+  /// Replace the array with its number of elements
+  Count,
+}
+
+impl FromStr for ArrayMode {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<ArrayMode, String> {
+    match s.to_ascii_lowercase().as_str() {
+      "join" => Ok(ArrayMode::Join),
+      "explode" => Ok(ArrayMode::Explode),
+      "count" => Ok(ArrayMode::Count),
+      other => Err(format!("unknown array mode '{}' (expected join, explode or count)", other)),
+    }
+  }
+}
+
+/// Records flattened into a header and rows of cells
+#[derive(Debug, Default, PartialEq)]
+pub struct Rows {
+  pub header: Vec<String>,
+  pub rows: Vec<Vec<String>>,
+}
+
+/// One flattened field of a record.
+///
+/// `group` is the path of the outermost array the field lives in, if any; `values` then
+/// holds one entry per element of that array.
+#[derive(Debug)]
+struct Column {
+  path: String,
+  group: Option<String>,
+  values: Vec<String>,
+}
+
+fn scalar(v: &Value) -> String {
+  match v {
+    Value::Null => String::new(),
+    Value::String(s) => s.clone(),
+    other => other.to_string(),
+  }
+}
+
+fn join_path(prefix: &str, key: &str) -> String {
+  if prefix.is_empty() { key.to_string() } else { format!("{}.{}", prefix, key) }
+}
+
+/// Collect the leaves of `value` (an array element or a whole record) as `(path, value)`,
+/// joining any arrays nested inside it
+fn leaves(value: &Value, path: &str, separator: &str, out: &mut Vec<(String, String)>) {
+  match value {
+    Value::Object(map) => {
+      for (k, v) in map {
+        leaves(v, &join_path(path, k), separator, out);
+      }
+    }
+    Value::Array(items) => {
+      let mut nested = Vec::new();
+      for item in items {
+        leaves(item, path, separator, &mut nested);
+      }
+      let mut merged: Vec<(String, Vec<String>)> = Vec::new();
+      for (p, v) in nested {
+        match merged.iter_mut().find(|(mp, _)| *mp == p) {
+          Some((_, vals)) => vals.push(v),
+          None => merged.push((p, vec![v])),
+        }
+      }
+      for (p, vals) in merged {
+        out.push((p, vals.into_iter().filter(|v| !v.is_empty()).collect::<Vec<_>>().join(separator)));
+      }
+    }
+    other => out.push((path.to_string(), scalar(other))),
+  }
+}
+
+fn column<'a>(columns: &'a mut Vec<Column>, path: &str, group: Option<&str>) -> &'a mut Column {
+  let i = match columns.iter().position(|c| c.path == path) {
+    Some(i) => i,
+    None => {
+      columns.push(Column { path: path.to_string(), group: group.map(String::from), values: Vec::new() });
+      columns.len() - 1
+    }
+  };
+  &mut columns[i]
+}
+
+/// Flatten a record into columns, aligning the fields of each top-level array by element
+fn columns(value: &Value, path: &str, separator: &str, out: &mut Vec<Column>) {
+  match value {
+    Value::Object(map) => {
+      for (k, v) in map {
+        columns(v, &join_path(path, k), separator, out);
+      }
+    }
+    Value::Array(items) => {
+      // Make sure an empty array still yields its column
+      if items.is_empty() {
+        column(out, path, Some(path));
+      }
+      for (i, item) in items.iter().enumerate() {
+        let mut element = Vec::new();
+        leaves(item, path, separator, &mut element);
+        if element.is_empty() {
+          element.push((path.to_string(), String::new()));
+        }
+        for (p, v) in element {
+          let col = column(out, &p, Some(path));
+          col.values.resize(i, String::new());
+          col.values.push(v);
+        }
+      }
+      for col in out.iter_mut().filter(|c| c.group.as_deref() == Some(path)) {
+        col.values.resize(items.len(), String::new());
+      }
+    }
+    other => column(out, path, None).values = vec![scalar(other)],
+  }
+}
+
+/// Values found at dotted `path` in `record`, descending into every element of the arrays
+/// on the way.
+///
+/// # Example
+/// ```rust
+/// use greynoise::fields::select;
+///
+/// let record = serde_json::json!({"raw_data": {"scan": [{"port": 22}, {"port": 80}]}});
+/// assert_eq!(select(&record, "raw_data.scan.port"), vec![serde_json::json!(22), serde_json::json!(80)]);
+///```
+pub fn select<T: Serialize>(record: &T, path: &str) -> Vec<Value> {
+
+  let mut current = vec![serde_json::to_value(record).unwrap_or(Value::Null)];
+
+  for key in path.split('.').filter(|k| !k.is_empty()) {
+    current = current.into_iter()
+      .flat_map(|v| match v {
+        Value::Array(items) => items,
+        other => vec![other],
+      })
+      .filter_map(|v| match v {
+        Value::Object(mut map) => map.remove(key),
+        _ => None,
+      })
+      .collect();
+  }
+
+  current.into_iter()
+    .flat_map(|v| match v {
+      Value::Array(items) => items,
+      other => vec![other],
+    })
+    .collect()
+
+}
+
+/// Turns records into rows of selected, flattened fields.
+///
+/// # Example
+/// ```rust
+/// use greynoise::fields::{ArrayMode, Flattener};
+///
+/// let record = serde_json::json!({
+///   "ip": "71.6.233.151",
+///   "metadata": {"asn": "AS10439", "country": "United States"},
+///   "raw_data": {"scan": [{"port": 22, "protocol": "TCP"}, {"port": 80, "protocol": "TCP"}]}
+/// });
+///
+/// let rows = Flattener::new()
+///   .fields(["ip", "metadata.asn", "raw_data.scan"])
+///   .arrays(ArrayMode::Explode)
+///   .rows(&[record]);
+///
+/// assert_eq!(rows.header, vec!["ip", "metadata.asn", "raw_data.scan.port", "raw_data.scan.protocol"]);
+/// assert_eq!(rows.rows[1], vec!["71.6.233.151", "AS10439", "80", "TCP"]);
+///```
+#[derive(Debug, Clone)]
+pub struct Flattener {
+  fields: Vec<String>,
+  arrays: ArrayMode,
+  separator: String,
+}
+
+impl Default for Flattener {
+  fn default() -> Flattener {
+    Flattener::new()
+  }
+}
+
+impl Flattener {
+
+  /// Keep every field and join arrays with `;`
+  pub fn new() -> Flattener {
+    Flattener {
+      fields: Vec::new(),
+      arrays: ArrayMode::Join,
+      separator: String::from(";"),
+    }
+  }
+
+  /// Keep only these dotted paths, in this order. A path naming an object or an array of
+  /// objects keeps every field beneath it. No paths means every field.
+  pub fn fields<I, S>(mut self, fields: I) -> Flattener
+    where I: IntoIterator<Item = S>, S: Into<String>, {
+    self.fields = fields.into_iter().map(Into::into).collect();
+    self
+  }
+
+  /// How to handle arrays
+  pub fn arrays(mut self, mode: ArrayMode) -> Flattener {
+    self.arrays = mode;
+    self
+  }
+
+  /// Separator used when joining array elements
+  pub fn separator(mut self, separator: &str) -> Flattener {
+    self.separator = separator.to_string();
+    self
+  }
+
+  /// Position of the first selected field covering `path`, or `None` if it is not selected
+  fn rank(&self, path: &str) -> Option<usize> {
+
+    if self.fields.is_empty() {
+      return Some(0);
+    }
+
+    self.fields.iter().position(|f| {
+      path == f || (path.starts_with(f.as_str()) && path[f.len()..].starts_with('.'))
+    })
+
+  }
+
+  fn record_columns<T: Serialize>(&self, record: &T) -> Vec<Column> {
+
+    let value = serde_json::to_value(record).unwrap_or(Value::Null);
+
+    let mut cols = Vec::new();
+    columns(&value, "", &self.separator, &mut cols);
+
+    let mut cols: Vec<(usize, Column)> = cols.into_iter()
+      .filter_map(|c| self.rank(&c.path).map(|r| (r, c)))
+      .collect();
+    cols.sort_by_key(|(r, _)| *r);
+
+    cols.into_iter().map(|(_, c)| c).collect()
+
+  }
+
+  /// Cells of one record's rows, keyed by column
+  fn record_rows(&self, cols: &[Column]) -> Vec<Vec<(String, String)>> {
+
+    let cell = |c: &Column, v: String| (c.path.clone(), v);
+
+    match self.arrays {
+      ArrayMode::Join => vec![cols.iter().map(|c| {
+        cell(c, c.values.iter().filter(|v| !v.is_empty()).cloned().collect::<Vec<_>>().join(&self.separator))
+      }).collect()],
+      ArrayMode::Count => vec![cols.iter().map(|c| match c.group {
+        Some(_) => cell(c, c.values.len().to_string()),
+        None => cell(c, c.values.concat()),
+      }).collect()],
+      ArrayMode::Explode => {
+
+        let mut groups: Vec<(&str, usize)> = Vec::new();
+        for c in cols {
+          if let Some(g) = c.group.as_deref() {
+            if !groups.iter().any(|(name, _)| *name == g) {
+              groups.push((g, c.values.len()));
+            }
+          }
+        }
+
+        // Cartesian product of the element indices of every array group
+        let mut combos: Vec<Vec<usize>> = vec![Vec::new()];
+        for (_, len) in &groups {
+          combos = combos.into_iter()
+            .flat_map(|combo| (0..(*len).max(1)).map(move |i| {
+              let mut next = combo.clone();
+              next.push(i);
+              next
+            }))
+            .collect();
+        }
+
+        combos.into_iter().map(|combo| cols.iter().map(|c| {
+          let v = match c.group.as_deref() {
+            Some(g) => {
+              let gi = groups.iter().position(|(name, _)| *name == g).unwrap_or(0);
+              c.values.get(combo[gi]).cloned().unwrap_or_default()
+            }
+            None => c.values.concat(),
+          };
+          cell(c, v)
+        }).collect()).collect()
+
+      }
+    }
+
+  }
+
+  /// Flatten `records` into rows sharing one header (the union of their columns)
+  pub fn rows<T: Serialize>(&self, records: &[T]) -> Rows {
+
+    let per_record: Vec<Vec<Vec<(String, String)>>> = records.iter()
+      .map(|r| self.record_rows(&self.record_columns(r)))
+      .collect();
+
+    let mut header: Vec<(usize, String)> = Vec::new();
+    for (col, _) in per_record.iter().flatten().flatten() {
+      if !header.iter().any(|(_, h)| h == col) {
+        header.push((self.rank(col).unwrap_or(0), col.clone()));
+      }
+    }
+    header.sort_by_key(|(r, _)| *r);
+    let header: Vec<String> = header.into_iter().map(|(_, h)| h).collect();
+
+    let rows = per_record.into_iter().flatten()
+      .map(|row| header.iter()
+        .map(|col| row.iter().find(|(c, _)| c == col).map(|(_, v)| v.clone()).unwrap_or_default())
+        .collect())
+      .collect();
+
+    Rows { header, rows }
+
+  }
+
+}
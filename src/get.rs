@@ -1,46 +1,57 @@
-use reqwest::StatusCode;
+use http::StatusCode;
 use serde::de::DeserializeOwned;
+use crate::lookup::Lookup;
+use crate::transport::{self, HttpRequest, HttpResponse};
 
+/// Host of the GreyNoise API; every endpoint URL constant starts with it
 #[doc(hidden)]
-pub async fn query<T>(url: String, key: Option<&str>) -> Result<T, StatusCode> 
-  where T: DeserializeOwned, {
-
-  let client = reqwest::Client::new();
+pub(crate) const API_BASE_URL: &str = "https://api.greynoise.io";
 
-  let mut call = client.get(url);
+/// Environment variable that points the module-level functions at another host, such as a
+/// local mock server
+#[doc(hidden)]
+pub const API_URL_ENV: &str = "GREYNOISE_API_URL";
 
-  if let Some(k) = key {
-    call = call.header("key", k)
-  }
-  
-  let resp = call.send().await;
-
-  match &resp {
-    Ok(r) => {
-      if r.status() != StatusCode::OK {
-        return Err(r.status());
-      }
-    }
-    Err(e) => {
-      if e.is_status() {
-        return Err(e.status().unwrap());
-      } else {
-        return Err(StatusCode::BAD_REQUEST);
-      }
-    }
+/// Move a GreyNoise API `url` onto `base` (e.g. `http://127.0.0.1:8080`), keeping its path
+#[doc(hidden)]
+pub(crate) fn rebase(url: &str, base: &str) -> String {
+  match url.strip_prefix(API_BASE_URL) {
+    Some(path) => format!("{}{}", base.trim_end_matches('/'), path),
+    None => url.to_string(),
   }
-  
-  // Parse the response body as Json
-  let content = resp.unwrap().json::<T>().await;
-  
-  match content {
-    Ok(s) => Ok(s),
-    Err(e) => {
-      println!("{:?}", e);
-      Err(StatusCode::BAD_REQUEST)
-    }
+}
+
+/// Apply the `GREYNOISE_API_URL` override, if it is set
+#[doc(hidden)]
+pub(crate) fn resolve(url: String) -> String {
+  match std::env::var(API_URL_ENV) {
+    Ok(base) if !base.is_empty() => rebase(&url, &base),
+    _ => url,
   }
-  
+}
+
+/// Issue a GET request through the default pipeline and return the response without interpreting the status
+#[doc(hidden)]
+pub async fn fetch(url: String, key: Option<&str>) -> Result<HttpResponse, StatusCode> {
+
+  transport::default_pipeline().send(get_request(url, key)).await
+
+}
+
+/// Describe a GET request for `url`
+#[doc(hidden)]
+pub(crate) fn get_request(url: String, key: Option<&str>) -> HttpRequest {
+  HttpRequest::get(resolve(url)).key(key)
+}
+
+/// Describe a POST request for `url` carrying a list of IPs
+#[doc(hidden)]
+pub(crate) fn post_request(url: String, ips: Vec<String>, key: Option<&str>) -> HttpRequest {
+
+  let body = IPVec { ips };
+
+  HttpRequest::post(resolve(url), serde_json::to_string(&body).unwrap_or_default()).key(key)
+
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -48,53 +59,78 @@ struct IPVec {
   ips: Vec<String>
 }
 
+/// Issue a POST request with a list of IPs through the default pipeline and return the response without interpreting the status
 #[doc(hidden)]
-pub async fn post_query<T>(url: String, ips: Vec<String>, key: Option<&str>) -> Result<T, StatusCode> 
-  where T: DeserializeOwned, {
-     
-  let body = IPVec {
-    ips: ips.clone()
-  };
-
-  // let client = reqwest::Client::new();
-  let builder = reqwest::ClientBuilder::new();
+pub async fn post_fetch(url: String, ips: Vec<String>, key: Option<&str>) -> Result<HttpResponse, StatusCode> {
 
-  let client = builder.connection_verbose(true).build().expect("Could not build client");
+  transport::default_pipeline().send(post_request(url, ips, key)).await
 
-  println!("{:?}", serde_json::to_vec(&body));
+}
 
-  let mut call = client.post(url).json(&body);
+/// Parse a successful response body as Json
+#[doc(hidden)]
+pub fn decode<T>(resp: &HttpResponse) -> Result<T, StatusCode>
+  where T: DeserializeOwned, {
 
-  if let Some(k) = key {
-    call = call.header("key", k)
+  if resp.status != StatusCode::OK {
+    return Err(resp.status);
   }
-  
-  let resp = call.send().await;
-
-  match &resp {
-    Ok(r) => {
-      if r.status() != StatusCode::OK {
-        return Err(r.status());
-      }
-    }
-    Err(e) => {
-      if e.is_status() {
-        return Err(e.status().unwrap());
-      } else {
-        return Err(StatusCode::BAD_REQUEST);
-      }
-    }
-  }
-  
-  // Parse the response body as Json
-  let content = resp.unwrap().json::<T>().await;
-  
-  match content {
+
+  match serde_json::from_str::<T>(&resp.body) {
     Ok(s) => Ok(s),
     Err(e) => {
-      println!("{:?}", e);
+      eprintln!("{:?}", e);
       Err(StatusCode::BAD_REQUEST)
     }
   }
-  
-}
\ No newline at end of file
+
+}
+
+#[derive(Deserialize, Debug)]
+struct NotFoundBody {
+  message: Option<String>
+}
+
+/// Parse a response body as Json, treating a `404` as "not in this dataset"
+#[doc(hidden)]
+pub fn decode_lookup<T>(resp: &HttpResponse) -> Result<Lookup<T>, StatusCode>
+  where T: DeserializeOwned, {
+
+  match resp.status {
+    StatusCode::OK => decode(resp).map(Lookup::Found),
+    // The API uses a 404 with a JSON body to say "not in this dataset"
+    StatusCode::NOT_FOUND => {
+      let message = match serde_json::from_str::<NotFoundBody>(&resp.body) {
+        Ok(NotFoundBody { message: Some(m) }) => m,
+        _ => String::from("IP not found"),
+      };
+      Ok(Lookup::NotFound { message })
+    }
+    status => Err(status),
+  }
+
+}
+
+#[doc(hidden)]
+pub async fn query<T>(url: String, key: Option<&str>) -> Result<T, StatusCode>
+  where T: DeserializeOwned, {
+
+  decode(&fetch(url, key).await?)
+
+}
+
+#[doc(hidden)]
+pub async fn lookup_query<T>(url: String, key: Option<&str>) -> Result<Lookup<T>, StatusCode>
+  where T: DeserializeOwned, {
+
+  decode_lookup(&fetch(url, key).await?)
+
+}
+
+#[doc(hidden)]
+pub async fn post_query<T>(url: String, ips: Vec<String>, key: Option<&str>) -> Result<T, StatusCode>
+  where T: DeserializeOwned, {
+
+  decode(&post_fetch(url, ips, key).await?)
+
+}
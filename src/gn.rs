@@ -13,7 +13,7 @@ pub fn get_config(path: Option<String>) -> HashMap<String, HashMap<String, Optio
     map = ini::ini!(shellexpand::tilde("~/.config/greynoise/config").as_ref());
   }
 
-  return map;
+  map
 
 }
 
@@ -29,6 +29,6 @@ pub fn api_key(path: Option<String>) -> String {
     map = ini::ini!(shellexpand::tilde("~/.config/greynoise/config").as_ref());
   }
 
-  return map["greynoise"]["api_key"].clone().unwrap();
+  map["greynoise"]["api_key"].clone().unwrap()
 
 }
@@ -53,6 +53,9 @@ pub struct Datum {
   
   #[serde(rename = "seen")]
   pub seen: Option<bool>,
+
+  #[serde(rename = "riot")]
+  pub riot: Option<bool>,
   
   #[serde(rename = "tags")]
   pub tags: Option<Vec<String>>,
@@ -158,7 +161,7 @@ pub struct HASSH {
 }
 
 #[doc(hidden)]
-const QNQL_QUERY_URL: &str = "https://api.greynoise.io/v2/experimental/gnql";
+pub(crate) const QNQL_QUERY_URL: &str = "https://api.greynoise.io/v2/experimental/gnql";
 
 /// Function to retrieve list of GreyNoise tags and their respective metadata
 ///
@@ -171,14 +174,88 @@ const QNQL_QUERY_URL: &str = "https://api.greynoise.io/v2/experimental/gnql";
 /// # Errors
 /// If the call fails, it will return a `Err(StatusCode)`.
 /// To see the possible return values, check the [API docs](ttps://api.greynoise.io/v2/experimental/gnql).
-pub async fn gnql_query(query: &str, size: Option<i64>, scroll: Option<&str>, key: Option<&str>) -> Result<QNQL, reqwest::StatusCode> {
-
-  let size_param: String = if let Some(size) = size { format!("&size={}", size.to_string()) } else { format!("") };
-  let scroll: String = if let Some(scroll) = scroll { format!("&scroll={}", scroll.to_string()) } else { format!("") } ;
-  let url = format!("{}?query={}{}{}", QNQL_QUERY_URL, query, size_param, scroll);
+pub async fn gnql_query(query: &str, size: Option<i64>, scroll: Option<&str>, key: Option<&str>) -> Result<QNQL, http::StatusCode> {
 
-  let res: Result<QNQL, reqwest::StatusCode> = get::query(url, key).await;
+  let url = gnql_url(query, size, scroll);
+  let res: Result<QNQL, http::StatusCode> = get::query(url, key).await;
 
   res
 
 }
+
+/// Build a GNQL query URL, percent-encoding the query and scroll token
+#[doc(hidden)]
+pub(crate) fn gnql_url(query: &str, size: Option<i64>, scroll: Option<&str>) -> String {
+
+  let mut params: Vec<(&str, String)> = vec![("query", query.to_string())];
+
+  if let Some(size) = size {
+    params.push(("size", size.to_string()));
+  }
+
+  if let Some(scroll) = scroll {
+    params.push(("scroll", scroll.to_string()));
+  }
+
+  match url::Url::parse_with_params(QNQL_QUERY_URL, &params) {
+    Ok(url) => url.to_string(),
+    Err(_) => QNQL_QUERY_URL.to_string(),
+  }
+
+}
+
+/// Function to run a GNQL query and collect every page of results
+///
+/// Follows the `scroll` token of each page, requesting `page_size` results at a time, until
+/// the query is complete or `limit` results have been collected.
+///
+/// # Example
+/// ```rust
+/// use greynoise::gnql;
+/// //async {
+/// // let res: Result<Vec<gnql::Datum>, http::StatusCode> = gnql::gnql_query_all("classification:malicious last_seen:1d", 1000, None, Some(API_KEY)).await;
+/// //};
+///```
+///
+/// # Errors
+/// If any page fails, it will return a `Err(StatusCode)`.
+/// To see the possible return values, check the [API docs](https://api.greynoise.io/v2/experimental/gnql).
+pub async fn gnql_query_all(query: &str, page_size: i64, limit: Option<usize>, key: Option<&str>) -> Result<Vec<Datum>, http::StatusCode> {
+
+  collect_pages(limit, |scroll| async move {
+    gnql_query(query, Some(page_size), scroll.as_deref(), key).await
+  }).await
+
+}
+
+/// Call `fetch_page` with each scroll token in turn, collecting up to `limit` results
+#[doc(hidden)]
+pub(crate) async fn collect_pages<F, Fut>(limit: Option<usize>, mut fetch_page: F) -> Result<Vec<Datum>, http::StatusCode>
+  where F: FnMut(Option<String>) -> Fut, Fut: std::future::Future<Output = Result<QNQL, http::StatusCode>>, {
+
+  let mut data: Vec<Datum> = Vec::new();
+  let mut scroll: Option<String> = None;
+
+  loop {
+
+    let page = fetch_page(scroll.take()).await?;
+    let fetched = page.data.len();
+    data.extend(page.data);
+
+    if let Some(limit) = limit {
+      if data.len() >= limit {
+        data.truncate(limit);
+        break;
+      }
+    }
+
+    match page.scroll {
+      Some(s) if fetched > 0 && !s.is_empty() && page.complete != Some(true) => scroll = Some(s),
+      _ => break,
+    }
+
+  }
+
+  Ok(data)
+
+}
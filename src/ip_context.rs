@@ -152,7 +152,7 @@ pub struct HASSH {
 }
 
 #[doc(hidden)]
-const IP_CONTEXT_URL: &str = "https://api.greynoise.io/v2/noise/context";
+pub(crate) const IP_CONTEXT_URL: &str = "https://api.greynoise.io/v2/noise/context";
 
 /// Function to retrieve list of GreyNoise tags and their respective metadata
 ///
@@ -166,7 +166,7 @@ const IP_CONTEXT_URL: &str = "https://api.greynoise.io/v2/noise/context";
 /// ```rust
 /// use greynoise::ip_context;
 /// //async {
-///  //let res: Result<ip_context::IPContext, reqwest::StatusCode> = ip_context::ip_context("71.6.233.151", Some(API_KEY)).await;
+///  //let res: Result<ip_context::IPContext, http::StatusCode> = ip_context::ip_context("71.6.233.151", Some(API_KEY)).await;
 ///  // assert_eq!(res.unwrap().ip, "8.8.8.8");
 /// //};
 ///```
@@ -174,10 +174,10 @@ const IP_CONTEXT_URL: &str = "https://api.greynoise.io/v2/noise/context";
 /// # Errors
 /// If the call fails, it will return a `Err(StatusCode)`.
 /// To see the possible return values, check the [API docs](https://docs.greynoise.io/reference/noisecontextip-1).
-pub async fn ip_context(ip: &str, key: Option<&str>) -> Result<IPContext, reqwest::StatusCode> {
+pub async fn ip_context(ip: &str, key: Option<&str>) -> Result<IPContext, http::StatusCode> {
 
   let url = format!("{}/{}", IP_CONTEXT_URL, ip);
-  let res: Result<IPContext, reqwest::StatusCode> = get::query(url, key).await;
+  let res: Result<IPContext, http::StatusCode> = get::query(url, key).await;
 
   res
 
@@ -6,14 +6,40 @@ extern crate serde_json;
 extern crate ini;
 
 pub mod gn;
+pub mod cache;
+#[cfg(feature = "sqlite-cache")]
+pub mod sqlite_cache;
+pub mod client;
+pub mod error;
 pub mod community;
 pub mod ip_context;
+pub mod timeline;
+pub mod similarity;
+pub mod verdict;
+pub mod policy;
 pub mod quick_check;
 pub mod riot;
+pub mod riot_dataset;
 pub mod metadata;
 pub mod ping;
 pub mod gnql;
+pub mod lookup;
+pub mod output;
+pub mod fields;
+pub mod enrich;
+pub mod sensor_logs;
+pub mod blocklist;
+pub mod stix;
+pub mod misp;
+pub mod siem;
+pub mod rate_limit;
+pub mod cassette;
+pub mod transport;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
 mod get;
+mod coalesce;
+mod facts;
 
 #[allow(unused_macros)]
 macro_rules! vec_of_strings {
@@ -25,90 +51,107 @@ mod tests {
 
   use crate::*;
   
+  /// API key the shared mock server requires
+  const TEST_KEY: &str = "test-key";
+
+  /// Moves requests for the GreyNoise API onto another host; other requests pass unchanged
+  struct Rebase(String);
+
+  impl transport::Middleware for Rebase {
+    fn handle<'a>(&'a self, mut request: transport::HttpRequest, next: transport::Next<'a>) -> transport::BoxFuture<'a, Result<transport::HttpResponse, http::StatusCode>> {
+      request.url = get::rebase(&request.url, &self.0);
+      next.run(request)
+    }
+  }
+
+  /// Start the shared mock server (once) and point the module-level functions at it through
+  /// the default pipeline, leaving the process environment alone
+  fn mock() -> &'static mock::MockServer {
+    static MOCK: std::sync::OnceLock<mock::MockServer> = std::sync::OnceLock::new();
+    MOCK.get_or_init(|| {
+      let server = mock::MockServer::start(mock::Fixtures::sample()).unwrap();
+      server.require_key(Some(TEST_KEY));
+      transport::add_default_middleware(Rebase(server.url()));
+      server
+    })
+  }
+
+  fn key() -> &'static str {
+    mock();
+    TEST_KEY
+  }
+
   #[tokio::test]
   async fn community_works() {
-    let res: Result<community::Community, reqwest::StatusCode> = community::community("8.8.8.8", None).await;
-    assert_eq!(res.unwrap().ip, "8.8.8.8");
+    mock();
+    let res: Result<lookup::Lookup<community::Community>, http::StatusCode> = community::community("8.8.8.8", None).await;
+    assert_eq!(res.unwrap().found().unwrap().ip, "8.8.8.8");
   }
 
   #[tokio::test]
   async fn community_with_key_works() {
-    let res: Result<community::Community, reqwest::StatusCode> = community::community("8.8.8.8", Some(gn::api_key(None).as_ref())).await;
-    assert_eq!(res.unwrap().ip, "8.8.8.8");
+    let res: Result<lookup::Lookup<community::Community>, http::StatusCode> = community::community("8.8.8.8", Some(key())).await;
+    assert_eq!(res.unwrap().found().unwrap().ip, "8.8.8.8");
   }
 
   #[tokio::test]
   async fn community_fails() {
-    let res: Result<community::Community, reqwest::StatusCode> = community::community("eights", None).await;
-    match res {
-      Ok(_) => { assert!(false)}
-      Err(_) => { assert!(true)}
-    }
+    mock();
+    let res: Result<lookup::Lookup<community::Community>, http::StatusCode> = community::community("eights", None).await;
+    assert!(res.is_err());
   }
 
   #[tokio::test]
   async fn community_with_key_fails() {
-    let res: Result<community::Community, reqwest::StatusCode> = community::community("eights", Some(gn::api_key(None).as_ref())).await;
-    match res {
-      Ok(_) => { assert!(false)}
-      Err(_) => { assert!(true)}
-    }
+    let res: Result<lookup::Lookup<community::Community>, http::StatusCode> = community::community("eights", Some(key())).await;
+    assert!(res.is_err());
   }
 
   // #[tokio::test]
   // async fn riot_works() {
-  //   let res: Result<riot::RIOT, reqwest::StatusCode> = riot::riot("8.8.8.8", None).await;
-  //   assert_eq!(res.unwrap().ip, "8.8.8.8");
+  //   let res: Result<lookup::Lookup<riot::RIOT>, http::StatusCode> = riot::riot("8.8.8.8", None).await;
+  //   assert_eq!(res.unwrap().found().unwrap().ip, "8.8.8.8");
   // }
 
   #[tokio::test]
   async fn riot_with_key_works() {
-    let res: Result<riot::RIOT, reqwest::StatusCode> = riot::riot("8.8.8.8", Some(gn::api_key(None).as_ref())).await;
-    assert_eq!(res.unwrap().ip, "8.8.8.8");
+    let res: Result<lookup::Lookup<riot::RIOT>, http::StatusCode> = riot::riot("8.8.8.8", Some(key())).await;
+    assert_eq!(res.unwrap().found().unwrap().ip, "8.8.8.8");
   }
 
   #[tokio::test]
   async fn riot_fails() {
-    let res: Result<riot::RIOT, reqwest::StatusCode> = riot::riot("eights", None).await;
-    match res {
-      Ok(_) => { assert!(false)}
-      Err(_) => { assert!(true)}
-    }
+    mock();
+    let res: Result<lookup::Lookup<riot::RIOT>, http::StatusCode> = riot::riot("eights", None).await;
+    assert!(res.is_err());
   }
 
   #[tokio::test]
   async fn riot_with_key_fails() {
-    let res: Result<riot::RIOT, reqwest::StatusCode> = riot::riot("eights", Some(gn::api_key(None).as_ref())).await;
-    match res {
-      Ok(_) => { assert!(false)}
-      Err(_) => { assert!(true)}
-    }
+    let res: Result<lookup::Lookup<riot::RIOT>, http::StatusCode> = riot::riot("eights", Some(key())).await;
+    assert!(res.is_err());
   }
 
   #[tokio::test]
   async fn metadata_with_key_works() {
-    let res: Result<metadata::TagMetadata, reqwest::StatusCode> = metadata::metadata(Some(gn::api_key(None).as_ref())).await;
-    assert!(res.unwrap().metadata.len() > 0);
+    let res: Result<metadata::TagMetadata, http::StatusCode> = metadata::metadata(Some(key())).await;
+    assert!(!res.unwrap().metadata.is_empty());
   }
 
   #[tokio::test]
   async fn ip_context_works() {
     
-    let res: Result<ip_context::IPContext, reqwest::StatusCode> = ip_context::ip_context("71.6.233.151", Some(gn::api_key(None).as_ref())).await;
+    let res: Result<ip_context::IPContext, http::StatusCode> = ip_context::ip_context("71.6.233.151", Some(key())).await;
 
-    match res {
-      Ok(_) => { assert!(true)}
-      Err(err) => { 
-        eprintln!("ERROR: {}", err.as_str());
-        assert!(false)
-      }
+    if let Err(err) = res {
+      panic!("ERROR: {}", err.as_str());
     }
 
   }
 
   #[tokio::test]
   async fn quick_check_with_key_works() {
-    let res: Result<quick_check::QuickCheck, reqwest::StatusCode> = quick_check::quick_check("71.6.233.151", Some(gn::api_key(None).as_ref())).await;
+    let res: Result<quick_check::QuickCheck, http::StatusCode> = quick_check::quick_check("71.6.233.151", Some(key())).await;
     assert_eq!(res.unwrap().ip, "71.6.233.151");
   }
 
@@ -116,27 +159,920 @@ mod tests {
   #[tokio::test]
   async fn multi_quick_check_with_key_works() {
     let ips: Vec<String> = vec_of_strings![ "8.8.8.8", "8.8.4.4" ];
-    let res: Result<quick_check::MultiQuickCheck, reqwest::StatusCode> = quick_check::multi_quick_check(ips, Some(gn::api_key(None).as_ref())).await;
+    let res: Result<quick_check::MultiQuickCheck, http::StatusCode> = quick_check::multi_quick_check(ips, Some(key())).await;
     match res {
-      Ok(_) => { assert_eq!(res.unwrap().len(), 2) }
-      Err(err) => { 
-        eprintln!("ERROR: {}", err.as_str());
-        assert!(false)
-      }
+      Ok(r) => { assert_eq!(r.len(), 2) }
+      Err(err) => panic!("ERROR: {}", err.as_str()),
     }
   }
 
   #[tokio::test]
   async fn check_ping() {
-    assert!(ping::ping(Some(gn::api_key(None).as_ref())).await)
+    assert!(ping::ping(Some(key())).await)
+  }
+
+  #[tokio::test]
+  async fn ping_details_parse_plan_and_expiry() {
+    use ping::{ApiEndpoint, Offering};
+
+    let details = ping::ping_details(Some(key())).await.unwrap();
+    assert_eq!(details.offering, Some(Offering::Enterprise));
+    assert_eq!(details.expiration, chrono::NaiveDate::from_ymd_opt(2099, 12, 31));
+    assert_eq!(details.days_until_expiry_from(chrono::NaiveDate::from_ymd_opt(2099, 12, 1).unwrap()), Some(30));
+    assert!(details.allowed_endpoints().contains(&ApiEndpoint::Gnql));
+
+    let community: ping::Ping = serde_json::from_str(r#"{"message":"pong","expiration":"","offering":"community"}"#).unwrap();
+    assert_eq!(community.expiration, None);
+    assert_eq!(community.allowed_endpoints(), vec![ApiEndpoint::Ping, ApiEndpoint::Community]);
+    assert_eq!("Enterprise-Trial".parse::<Offering>(), Ok(Offering::EnterpriseTrial));
+    assert_eq!(serde_json::to_value(Offering::Other(String::from("platinum"))).unwrap(), "platinum");
+
+    let unreported: ping::Ping = serde_json::from_str(r#"{"message":"pong"}"#).unwrap();
+    assert_eq!(unreported.plan(), None);
+    assert_eq!(unreported.allowed_endpoints(), ApiEndpoint::ALL.to_vec());
   }
 
 
   #[tokio::test]
   async fn gnql_query_with_key_works() {
-    let res: Result<gnql::QNQL, reqwest::StatusCode> = gnql::gnql_query("tags:Mirai", Some(100), Some("scrolly"), Some(gn::api_key(None).as_ref())).await;
+    let res: Result<gnql::QNQL, http::StatusCode> = gnql::gnql_query("tags:Mirai", Some(100), Some("scrolly"), Some(key())).await;
     assert_eq!(res.unwrap().query, "tags:Mirai");
   }
 
+  #[test]
+  fn memory_cache_evicts_least_recently_used() {
+    use cache::{CacheBackend, CachedResponse, Endpoint, MemoryBackend};
+    let backend = MemoryBackend::new(2);
+    let resp = || CachedResponse { status: 200, body: String::from("{}"), fetched_at: std::time::SystemTime::now() };
+    backend.put(Endpoint::Riot, "1.1.1.1", resp());
+    backend.put(Endpoint::Riot, "8.8.8.8", resp());
+    assert!(backend.get(Endpoint::Riot, "1.1.1.1").is_some());
+    backend.put(Endpoint::Riot, "9.9.9.9", resp());
+    assert!(backend.get(Endpoint::Riot, "8.8.8.8").is_none());
+    assert!(backend.get(Endpoint::Riot, "1.1.1.1").is_some());
+    assert!(backend.get(Endpoint::Community, "1.1.1.1").is_none());
+  }
+
+  #[test]
+  fn cache_expires_by_endpoint_ttl_and_counts_hits() {
+    use std::time::Duration;
+    use cache::{Cache, Endpoint};
+    let cache = Cache::memory(10).ttl(Endpoint::QuickCheck, Duration::ZERO);
+    cache.put(Endpoint::QuickCheck, "8.8.8.8", 200, String::from("{}"));
+    cache.put(Endpoint::Riot, "8.8.8.8", 200, String::from("{}"));
+    std::thread::sleep(Duration::from_millis(5));
+    assert!(cache.get(Endpoint::QuickCheck, "8.8.8.8").is_none());
+    assert!(cache.get(Endpoint::Riot, "8.8.8.8").is_some());
+    assert!(cache.get(Endpoint::Riot, "1.1.1.1").is_none());
+    assert_eq!(cache.stats(), cache::CacheStats { hits: 1, misses: 2, stale: 0 });
+  }
+
+  #[cfg(feature = "sqlite-cache")]
+  #[test]
+  fn sqlite_cache_stores_and_purges() {
+    use std::time::{Duration, SystemTime};
+    use cache::{CacheBackend, CachedResponse, Endpoint};
+    let backend = sqlite_cache::SqliteBackend::in_memory().unwrap();
+    let old = SystemTime::now() - Duration::from_secs(3600);
+    backend.put(Endpoint::Riot, "8.8.8.8", CachedResponse { status: 200, body: String::from("{}"), fetched_at: SystemTime::now() });
+    backend.put(Endpoint::Community, "8.8.8.8", CachedResponse { status: 404, body: String::from("{}"), fetched_at: old });
+    backend.put(Endpoint::Riot, "1.1.1.1", CachedResponse { status: 200, body: String::from("{}"), fetched_at: old });
+    assert_eq!(backend.get(Endpoint::Community, "8.8.8.8").unwrap().status, 404);
+    assert_eq!(backend.purge_older_than(Duration::from_secs(60)), 2);
+    assert_eq!(backend.purge_ip("8.8.8.8"), 1);
+    assert!(backend.is_empty());
+  }
+
+  #[tokio::test]
+  async fn coalescer_shares_inflight_work() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    let coalescer = Arc::new(coalesce::Coalescer::new());
+    let calls = Arc::new(AtomicUsize::new(0));
+    let tasks: Vec<_> = (0..50).map(|_| {
+      let (coalescer, calls) = (Arc::clone(&coalescer), Arc::clone(&calls));
+      tokio::spawn(async move {
+        coalescer.run(String::from("context/8.8.8.8"), || async {
+          calls.fetch_add(1, Ordering::SeqCst);
+          tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+          42
+        }).await
+      })
+    }).collect();
+    for t in tasks {
+      assert_eq!(t.await.unwrap(), 42);
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+  }
+
+  #[tokio::test]
+  async fn client_coalesces_identical_batches() {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let server = mock::MockServer::start(mock::Fixtures::sample()).unwrap();
+    let client = Arc::new(client::Client::new(Some("k")).with_base_url(&server.url()));
+    server.latency(Duration::from_millis(50));
+
+    let tasks: Vec<_> = (0..10).map(|n| {
+      let client = Arc::clone(&client);
+      let ips = if n % 2 == 0 { vec_of_strings!["8.8.8.8", "45.83.66.65"] } else { vec_of_strings!["45.83.66.65", "8.8.8.8", "8.8.8.8"] };
+      tokio::spawn(async move { client.multi_quick_check(ips).await })
+    }).collect();
+    for t in tasks {
+      assert_eq!(t.await.unwrap().unwrap().len(), 2);
+    }
+
+    let posts = server.requests().iter().filter(|(r, _)| *r == mock::Route::MultiQuick).count();
+    assert_eq!(posts, 1);
+  }
+
+  #[test]
+  fn riot_dataset_longest_prefix_match() {
+    let csv = "cidr,name,category,trust_level\n\
+               8.8.0.0/16,Google,public_dns,2\n\
+               8.8.8.0/24,Google Public DNS,public_dns,1\n\
+               2001:4860::/32,Google IPv6,public_dns,1\n";
+    let dataset = riot_dataset::RiotDataset::from_csv(csv.as_bytes()).unwrap();
+    assert_eq!(dataset.len(), 3);
+    assert_eq!(dataset.lookup("8.8.8.8").unwrap().name, "Google Public DNS");
+    assert_eq!(dataset.lookup("8.8.4.4").unwrap().trust_level, "2");
+    assert_eq!(dataset.lookup("2001:4860:4860::8888").unwrap().name, "Google IPv6");
+    assert!(dataset.lookup("9.9.9.9").is_none());
+    assert!(dataset.lookup("eights").is_none());
+    assert!(riot_dataset::RiotDataset::from_json(r#"[{"cidr": "8.8.8.0/33"}]"#.as_bytes()).is_err());
+  }
+
+  #[test]
+  fn output_flattens_nested_fields() {
+    let datum: gnql::Datum = serde_json::from_str(r#"{
+      "ip": "71.6.233.151", "classification": "benign", "tags": ["Mirai", "SSH Worm"],
+      "metadata": {"asn": "AS10439", "country": "United States"},
+      "raw_data": {"scan": [{"port": 22, "protocol": "TCP"}, {"port": 80, "protocol": "TCP"}]}
+    }"#).unwrap();
+    let rows = output::to_rows(&[datum]);
+    let cell = |col: &str| rows.rows[0][rows.header.iter().position(|h| h == col).unwrap()].clone();
+    assert_eq!(rows.header[0], "ip");
+    assert_eq!(cell("metadata.asn"), "AS10439");
+    assert_eq!(cell("tags"), "Mirai;SSH Worm");
+    assert_eq!(cell("raw_data.scan.port"), "22;80");
+    assert_eq!(cell("vpn"), "");
+    let ndjson = output::render(&rows.rows, output::Format::Ndjson);
+    assert_eq!(ndjson.lines().count(), 1);
+  }
+
+  #[test]
+  fn fields_select_and_flatten_arrays() {
+    use fields::{ArrayMode, Flattener};
+    let ctx: ip_context::IPContext = serde_json::from_str(r#"{
+      "ip": "71.6.233.151", "seen": true, "tags": ["Mirai", "SSH Worm"],
+      "metadata": {"asn": "AS10439"},
+      "raw_data": {"scan": [{"port": 22, "protocol": "TCP"}, {"port": 161, "protocol": "UDP"}], "ja3": [], "hassh": []}
+    }"#).unwrap();
+    assert_eq!(fields::select(&ctx, "raw_data.scan.protocol").len(), 2);
+    let only = Flattener::new().fields(["tags", "ip"]).rows(&[&ctx]);
+    assert_eq!(only.header, vec!["tags", "ip"]);
+    assert_eq!(only.rows, vec![vec!["Mirai;SSH Worm", "71.6.233.151"]]);
+    let counted = Flattener::new().fields(["tags", "raw_data.scan.port"]).arrays(ArrayMode::Count).rows(&[&ctx]);
+    assert_eq!(counted.rows, vec![vec!["2", "2"]]);
+    let exploded = Flattener::new().fields(["tags", "raw_data.scan"]).arrays(ArrayMode::Explode).rows(&[&ctx]);
+    assert_eq!(exploded.header, vec!["tags", "raw_data.scan.port", "raw_data.scan.protocol"]);
+    assert_eq!(exploded.rows.len(), 4);
+    assert_eq!(exploded.rows[3], vec!["SSH Worm", "161", "UDP"]);
+  }
+
+  #[test]
+  fn enrich_extracts_routable_ips() {
+    use enrich::Extractor;
+    let line = r#"71.6.233.151 - - [19/Oct/2026:10:22:33 +0000] "GET / HTTP/1.1" 200 from 10.0.0.1 via [2001:4860:4860::8888]:443 and 8.8.8.8:53"#;
+    assert_eq!(Extractor::default().extract(line), vec!["71.6.233.151", "2001:4860:4860::8888", "8.8.8.8"]);
+    let field = Extractor::Field { index: std::num::NonZeroUsize::new(3).unwrap(), delimiter: Some(',') };
+    assert_eq!(field.extract("2026-10-19,deny,203.0.114.9,22"), vec!["203.0.114.9"]);
+    assert!(field.extract("2026-10-19,deny,192.168.1.1,22").is_empty());
+  }
+
+  #[tokio::test]
+  async fn enricher_deepens_concurrently_and_reports_failures() {
+    let server = mock::MockServer::start(mock::Fixtures::sample()).unwrap();
+    let client = client::Client::new(Some("k")).with_base_url(&server.url());
+    let enricher = enrich::Enricher::new(&client).context(true).riot(true);
+    let ips = vec_of_strings!["45.83.66.65", "71.6.233.151", "8.8.8.8"];
+
+    server.fail_next(Some(mock::Route::Context), 503, 1);
+    assert_eq!(enricher.verdicts(&ips).await.unwrap_err(), error::Error::Api(http::StatusCode::SERVICE_UNAVAILABLE));
+
+    let verdicts = enricher.verdicts(&ips).await.unwrap();
+    assert_eq!(verdicts["45.83.66.65"].classification.as_deref(), Some("malicious"));
+    assert_eq!(verdicts["71.6.233.151"].actor.as_deref(), Some("Shodan.io"));
+    assert_eq!(verdicts["8.8.8.8"].trust_level.as_deref(), Some("1"));
+    assert!(verdicts["45.83.66.65"].fields().contains(&("code", String::from("0x01"))));
+
+    let single = enricher.verdicts(&[String::from("8.8.8.8")]).await.unwrap();
+    assert_eq!(single["8.8.8.8"].name.as_deref(), Some("Google Public DNS"));
+    assert!(server.requests().iter().any(|(r, _)| *r == mock::Route::Quick));
+  }
+
+  #[tokio::test]
+  async fn sensor_logs_rewrite_zeek_headers() {
+    use sensor_logs::{enrich_sensor_log, SensorFormat};
+    let log = "#separator \\x09\n#unset_field\t-\n#fields\tts\tuid\tid.orig_h\n#types\ttime\tstring\taddr\n1.0\tCx\t10.0.0.1\n";
+    assert_eq!(SensorFormat::detect(log.lines().next().unwrap()), Some(SensorFormat::ZeekTsv));
+    assert_eq!(SensorFormat::detect(r#"{"event_type":"alert","src_ip":"10.0.0.1"}"#), Some(SensorFormat::SuricataEve));
+    let client = client::Client::new(None);
+    let enricher = enrich::Enricher::new(&client);
+    let mut out = Vec::new();
+    enrich_sensor_log(&enricher, SensorFormat::ZeekTsv, None, log.as_bytes(), &mut out).await.unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert!(lines[2].ends_with("id.orig_h\tgreynoise.noise\tgreynoise.riot\tgreynoise.classification\tgreynoise.actor\tgreynoise.tags\tgreynoise.name\tgreynoise.category\tgreynoise.trust_level"));
+    assert!(lines[3].contains("\tbool\tbool\tstring"));
+    assert_eq!(lines[4], "1.0\tCx\t10.0.0.1\t-\t-\t-\t-\t-\t-\t-\t-");
+  }
+
+  #[tokio::test]
+  async fn sensor_logs_enrich_every_format() {
+    use sensor_logs::{enrich_sensor_log, SensorFormat};
+    use serde_json::Value;
+
+    let server = mock::MockServer::start(mock::Fixtures::sample()).unwrap();
+    let client = client::Client::new(Some("k")).with_base_url(&server.url());
+    let enricher = enrich::Enricher::new(&client).context(true).riot(true);
+
+    let enrich = |format: SensorFormat, log: String| {
+      let enricher = &enricher;
+      async move {
+        let mut out = Vec::new();
+        enrich_sensor_log(enricher, format, None, log.as_bytes(), &mut out).await.unwrap();
+        String::from_utf8(out).unwrap()
+      }
+    };
+
+    let tsv = enrich(SensorFormat::ZeekTsv, String::from(
+      "#separator \\x09\n#set_separator\t,\n#unset_field\t-\n#fields\tts\tid.orig_h\n#types\ttime\taddr\n1.0\t45.83.66.65\n2.0\t8.8.8.8\n"
+    )).await;
+    let rows: Vec<&str> = tsv.lines().skip(5).collect();
+    assert_eq!(rows[0], "1.0\t45.83.66.65\tT\tF\tmalicious\tunknown\tMirai,SSH Bruteforcer\t-\t-\t-");
+    assert_eq!(rows[1], "2.0\t8.8.8.8\tF\tT\t-\t-\t-\tGoogle Public DNS\tpublic_dns\t1");
+
+    let zeek_json = enrich(SensorFormat::ZeekJson, String::from(
+      "{\"ts\":1.0,\"id.orig_h\":\"45.83.66.65\"}\n{\"ts\":2.0,\"id.orig_h\":\"8.8.8.8\"}\n"
+    )).await;
+    let records: Vec<Value> = zeek_json.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(records[0]["greynoise.noise"], true);
+    assert_eq!(records[0]["greynoise.classification"], "malicious");
+    assert_eq!(records[0]["greynoise.tags"], serde_json::json!(["Mirai", "SSH Bruteforcer"]));
+    assert_eq!(records[1]["greynoise.riot"], true);
+    assert_eq!(records[1]["greynoise.trust_level"], "1");
+    assert!(records[1].get("greynoise").is_none());
+
+    let eve = enrich(SensorFormat::SuricataEve, String::from(
+      "{\"event_type\":\"alert\",\"src_ip\":\"45.83.66.65\"}\n{\"event_type\":\"flow\",\"src_ip\":\"8.8.8.8\"}\n{\"event_type\":\"flow\",\"src_ip\":\"10.0.0.1\"}\n"
+    )).await;
+    let records: Vec<Value> = eve.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(records[0]["greynoise"]["noise"], true);
+    assert_eq!(records[0]["greynoise"]["actor"], "unknown");
+    assert_eq!(records[1]["greynoise"]["riot"], true);
+    assert_eq!(records[1]["greynoise"]["name"], "Google Public DNS");
+    assert!(records[1].get("greynoise.riot").is_none());
+    assert!(records[2].get("greynoise").is_none());
+  }
+
+  #[test]
+  fn blocklist_excludes_riot_and_allowlisted() {
+    use crate::blocklist::{Allowlist, Blocklist, BlocklistFormat};
+
+    let data: Vec<gnql::Datum> = serde_json::from_value(serde_json::json!([
+      {"ip": "45.83.66.65"},
+      {"ip": "8.8.8.8", "riot": true},
+      {"ip": "203.0.113.9"},
+      {"ip": "198.51.100.7"},
+      {"ip": "192.168.1.1"},
+      {"ip": "2a06:4880::1"},
+      {"ip": "45.83.66.65"}
+    ])).unwrap();
+
+    let allow = Allowlist::new(["198.51.100.0/24", "# comment", ""]).unwrap();
+    let list = Blocklist::from_data("classification:malicious", &data, &allow);
+
+    assert_eq!(list.ips, vec!["45.83.66.65".parse::<std::net::IpAddr>().unwrap(), "2a06:4880::1".parse().unwrap()]);
+    assert_eq!((list.excluded_riot, list.excluded_allowlist), (1, 3));
+    assert!(Allowlist::new(["10.0.0.0/33"]).is_err());
+
+    let nft = list.render(BlocklistFormat::Nftables, "gn");
+    assert!(nft.contains("table inet gn\ndelete table inet gn\ntable inet gn {"));
+    assert!(nft.contains("elements = { 45.83.66.65 }"));
+    assert!(nft.contains("elements = { 2a06:4880::1 }"));
+
+    let ipt = list.render(BlocklistFormat::Iptables, "gn");
+    assert!(ipt.contains("-A gn -s 45.83.66.65 -j DROP"));
+    assert!(ipt.contains("iptables -I INPUT -j gn"));
+    assert!(ipt.contains("# 1 addresses are not in this iptables ruleset: render them as ip6tables"));
+    assert_eq!(list.left_out(BlocklistFormat::Iptables), 1);
+
+    let ip6t = list.render(BlocklistFormat::Ip6tables, "gn");
+    assert!(ip6t.contains("-A gn -s 2a06:4880::1 -j DROP"));
+    assert!(!ip6t.contains("-s 45.83.66.65"));
+    assert_eq!("ip6tables".parse::<BlocklistFormat>(), Ok(BlocklistFormat::Ip6tables));
+
+    assert_eq!(list.render(BlocklistFormat::Edl, "gn"), "45.83.66.65\n2a06:4880::1\n");
+  }
+
+  #[test]
+  fn stix_bundle_links_indicators_tags_and_cves() {
+    use crate::stix::StixBundle;
+
+    let tags: Vec<metadata::Metadatum> = serde_json::from_value(serde_json::json!([{
+      "id": "b8f9d6e4", "label": "MIRAI", "slug": "mirai", "name": "Mirai", "category": "worm",
+      "intention": "malicious", "description": "Mirai botnet", "references": [], "recommend_block": true,
+      "cves": ["CVE-2016-10401"], "created_at": "2020-04-07"
+    }])).unwrap();
+
+    let context: ip_context::IPContext = serde_json::from_value(serde_json::json!({
+      "ip": "45.83.66.65", "seen": true, "first_seen": "2021-06-01", "classification": "malicious",
+      "tags": ["Mirai"], "cve": ["CVE-2016-10401"]
+    })).unwrap();
+
+    let mut bundle = StixBundle::new();
+    bundle.add_ip_context(&context).add_ip_context(&context).add_tags(&tags);
+
+    let json = bundle.to_value();
+    let objects = json["objects"].as_array().unwrap();
+    let of_type = |t: &str| objects.iter().filter(|o| o["type"] == t).collect::<Vec<_>>();
+
+    assert_eq!(of_type("ipv4-addr").len(), 1);
+    assert_eq!(of_type("vulnerability").len(), 1);
+    assert_eq!(of_type("malware")[0]["malware_types"][0], "worm");
+
+    let indicator = of_type("indicator")[0];
+    assert_eq!(indicator["valid_from"], "2021-06-01T00:00:00.000Z");
+    assert_eq!(indicator["indicator_types"][0], "malicious-activity");
+
+    let verbs: Vec<&str> = of_type("relationship").iter().map(|r| r["relationship_type"].as_str().unwrap()).collect();
+    assert_eq!(verbs, vec!["related-to", "related-to", "exploits", "indicates"]);
+  }
+
+  #[test]
+  fn misp_event_maps_tags_and_cves() {
+    use crate::misp::MispEvent;
+
+    let tags: Vec<metadata::Metadatum> = serde_json::from_value(serde_json::json!([{
+      "id": "b8f9d6e4", "label": "MIRAI", "slug": "mirai", "name": "Mirai", "category": "worm",
+      "intention": "malicious", "description": "Mirai botnet", "references": [], "recommend_block": true,
+      "cves": [], "created_at": "2020-04-07"
+    }])).unwrap();
+
+    let context: ip_context::IPContext = serde_json::from_value(serde_json::json!({
+      "ip": "45.83.66.65", "seen": true, "first_seen": "2021-06-01", "classification": "malicious",
+      "tags": ["Mirai", "SSH Scanner"], "cve": ["CVE-2016-10401"]
+    })).unwrap();
+
+    let mut event = MispEvent::new("test").seed("2026-10-19").tag_metadata(&tags).map_tag("SSH Scanner", "greynoise:ssh");
+    event.add_ip_context(&context).add_ip_context(&context);
+
+    let json = event.to_value();
+    let attributes = json["Event"]["Attribute"].as_array().unwrap();
+
+    assert_eq!(attributes.len(), 2);
+    assert_eq!(attributes[0]["to_ids"], true);
+    assert_eq!(attributes[0]["first_seen"], "2021-06-01T00:00:00Z");
+    let names: Vec<&str> = attributes[0]["Tag"].as_array().unwrap().iter().map(|t| t["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec![
+      "estimative-language:likelihood-probability=\"very-likely\"",
+      "misp-galaxy:malpedia=\"Mirai\"",
+      "greynoise:ssh",
+    ]);
+    assert_eq!(attributes[1]["type"], "vulnerability");
+    assert_eq!(json["Event"]["Tag"][0]["name"], "tlp:green");
+    assert_eq!(json["Event"]["threat_level_id"], "2");
+
+    let mut again = MispEvent::new("test").seed("2026-10-19");
+    again.add_ip_context(&context);
+    let again = again.to_value();
+    assert_eq!(again["Event"]["uuid"], json["Event"]["uuid"]);
+    assert_eq!(again["Event"]["Attribute"][0]["uuid"], attributes[0]["uuid"]);
+    assert_eq!(again["Event"]["Attribute"][1]["uuid"], attributes[1]["uuid"]);
+    assert_ne!(attributes[0]["uuid"], attributes[1]["uuid"]);
+
+    let other = MispEvent::new("other").seed("2026-10-19").to_value();
+    assert_ne!(other["Event"]["uuid"], json["Event"]["uuid"]);
+
+    let mut next_day = MispEvent::new("test").seed("2026-10-20");
+    next_day.add_ip_context(&context);
+    let next_day = next_day.to_value();
+    assert_ne!(next_day["Event"]["uuid"], json["Event"]["uuid"]);
+    assert_ne!(next_day["Event"]["Attribute"][0]["uuid"], attributes[0]["uuid"]);
+  }
+
+  #[test]
+  fn siem_formats_escape_values() {
+    use crate::siem::{cef, leef};
+
+    let riot = riot::RIOT {
+      ip: String::from("8.8.8.8"), riot: true, category: String::from("public_dns"),
+      name: String::from("Google|DNS=1\\x"), description: String::new(), explanation: String::new(),
+      last_updated: String::new(), reference: String::new(), trust_level: String::from("1"),
+    };
+
+    let cef = cef(&riot);
+    assert!(cef.starts_with("CEF:0|GreyNoise|greynoise-rust|"));
+    assert!(cef.contains("|riot|GreyNoise RIOT lookup|1|src=8.8.8.8 GreyNoiseRiot=true GreyNoiseName=Google|DNS\\=1\\\\x GreyNoiseCategory"));
+    assert!(!cef.contains("GreyNoiseReference"));
+
+    let leef = leef(&riot);
+    assert!(leef.contains("|riot|src=8.8.8.8\tsev=1\tGreyNoiseRiot=true\tGreyNoiseName=Google|DNS=1\\\\x\t"));
+  }
+
+  #[tokio::test]
+  async fn syslog_sender_frames_rfc5424() {
+    use crate::siem::{SyslogProtocol, SyslogSender};
+    use tokio::io::AsyncReadExt;
+
+    let qc = quick_check::QuickCheck { ip: String::from("8.8.8.8"), noise: false, riot: true, code: String::from("0x00") };
+
+    let listener = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let mut sender = SyslogSender::connect(&addr, SyslogProtocol::Udp).await.unwrap().facility(16);
+    sender.send_cef(&qc).await.unwrap();
+
+    let mut buf = [0u8; 2048];
+    let n = listener.recv(&mut buf).await.unwrap();
+    let msg = String::from_utf8_lossy(&buf[..n]).to_string();
+    assert!(msg.starts_with("<134>1 "));
+    assert!(msg.contains(" greynoise "));
+    assert!(msg.ends_with("src=8.8.8.8 GreyNoiseNoise=false GreyNoiseRiot=true GreyNoiseCode=0x00"));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let mut sender = SyslogSender::connect(&addr, SyslogProtocol::Tcp).await.unwrap();
+    let (mut stream, _) = listener.accept().await.unwrap();
+    sender.send_leef(&qc).await.unwrap();
+    drop(sender);
+
+    let mut received = String::new();
+    stream.read_to_string(&mut received).await.unwrap();
+    let (len, frame) = received.split_once(' ').unwrap();
+    assert_eq!(len.parse::<usize>().unwrap(), frame.len());
+    assert!(frame.starts_with("<14>1 "));
+    assert!(frame.contains("LEEF:1.0|GreyNoise|"));
+  }
+
+  #[tokio::test]
+  async fn mock_server_scripts_errors_and_paginates_gnql() {
+    use std::time::{Duration, Instant};
+
+    let server = mock::MockServer::start(mock::Fixtures::sample()).unwrap();
+    let client = client::Client::new(Some("k")).with_base_url(&server.url());
+
+    server.fail_next(Some(mock::Route::Quick), 503, 1);
+    assert_eq!(client.quick_check("45.83.66.65").await.unwrap_err(), http::StatusCode::SERVICE_UNAVAILABLE);
+    assert!(client.quick_check("45.83.66.65").await.unwrap().noise);
+
+    server.require_key(Some("other"));
+    assert_eq!(client.ip_context("45.83.66.65").await.unwrap_err(), http::StatusCode::UNAUTHORIZED);
+    server.reset();
+
+    server.latency(Duration::from_millis(50));
+    let started = Instant::now();
+    assert!(client.riot("8.8.8.8").await.unwrap().is_found());
+    assert!(started.elapsed() >= Duration::from_millis(50));
+    server.reset();
+
+    let malicious = client.gnql_query_all("classification:malicious", 1, None).await.unwrap();
+    assert_eq!(malicious.len(), 1);
+    assert_eq!(malicious[0].ip, "45.83.66.65");
+    assert_eq!(client.gnql_query_all("-tags:Mirai", 1, None).await.unwrap().len(), 1);
+
+    server.rate_limit(0.001, 1).unwrap();
+    assert!(client.ping().await);
+    assert!(!client.ping().await);
+
+    let gnql_pages = server.requests().iter().filter(|(r, _)| *r == mock::Route::Gnql).count();
+    assert_eq!(gnql_pages, 2);
+  }
+
+  #[tokio::test]
+  async fn cassette_records_redacts_and_replays() {
+    use cassette::Cassette;
+    use std::sync::Arc;
+
+    let path = std::env::temp_dir().join(format!("greynoise-cassette-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let server = mock::MockServer::start(mock::Fixtures::sample()).unwrap();
+    server.require_key(Some("secret-key"));
+
+    let recorder = client::Client::new(Some("secret-key")).with_base_url(&server.url()).with_cassette(Cassette::record(&path));
+    let live = recorder.riot("8.8.8.8").await.unwrap().found().unwrap();
+    let quick = recorder.multi_quick_check(vec_of_strings!["45.83.66.65", "8.8.8.8"]).await.unwrap();
+    recorder.gnql_query("tags:Mirai", Some(5), None).await.unwrap();
+    assert!(!path.exists());
+    drop(recorder);
+
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(!saved.contains("secret-key"));
+    assert!(saved.contains("[REDACTED]"));
+    drop(server);
+
+    let replayer = Arc::new(client::Client::new(Some("other-key")).with_base_url("http://127.0.0.1:9").with_cassette(Cassette::replay(&path).unwrap()));
+    assert_eq!(replayer.riot("8.8.8.8").await.unwrap().found().unwrap().name, live.name);
+    assert_eq!(replayer.multi_quick_check(vec_of_strings!["45.83.66.65", "8.8.8.8"]).await.unwrap().len(), quick.len());
+    assert_eq!(replayer.gnql_query("tags:Mirai", Some(5), None).await.unwrap().count, 1);
+
+    let unmatched = tokio::spawn({
+      let replayer = Arc::clone(&replayer);
+      async move { replayer.riot("1.1.1.1").await }
+    }).await;
+    assert_eq!(unmatched.unwrap().unwrap_err(), http::StatusCode::NOT_IMPLEMENTED);
+
+    /// Keeps the body of every response it passes back
+    struct Bodies(Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl transport::Middleware for Bodies {
+      fn handle<'a>(&'a self, request: transport::HttpRequest, next: transport::Next<'a>) -> transport::BoxFuture<'a, Result<transport::HttpResponse, http::StatusCode>> {
+        Box::pin(async move {
+          let resp = next.run(request).await?;
+          self.0.lock().unwrap().push(resp.body.clone());
+          Ok(resp)
+        })
+      }
+    }
+
+    let bodies = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let client = client::Client::new(None).with_middleware(Bodies(Arc::clone(&bodies))).with_cassette(Cassette::replay(&path).unwrap());
+    assert!(client.riot("1.1.1.1").await.is_err());
+    assert!(bodies.lock().unwrap()[0].contains("no recorded interaction matches GET /v2/riot/1.1.1.1"));
+
+    let _ = std::fs::remove_file(&path);
+  }
+
+  #[tokio::test]
+  async fn cassette_redacts_only_where_the_key_is_sent() {
+    use cassette::Cassette;
+
+    let path = std::env::temp_dir().join(format!("greynoise-cassette-short-key-{}.json", std::process::id()));
+    let server = mock::MockServer::start(mock::Fixtures::sample()).unwrap();
+    server.require_key(Some("8"));
+
+    let cassette = Cassette::record(&path);
+    let recorder = client::Client::new(Some("8")).with_base_url(&server.url()).with_cassette(cassette.clone());
+    recorder.riot("8.8.8.8").await.unwrap();
+    recorder.multi_quick_check(vec_of_strings!["8.8.4.4"]).await.unwrap();
+
+    let recorded = cassette.interactions();
+    assert_eq!(recorded[0].request.path, "/v2/riot/8.8.8.8");
+    assert_eq!(recorded[0].request.headers, vec![(String::from("key"), String::from("[REDACTED]"))]);
+    assert!(recorded[0].response.body.contains("\"ip\":\"8.8.8.8\""));
+    assert_eq!(recorded[1].request.body, Some(serde_json::json!({ "ips": ["8.8.4.4"] })));
+
+    drop(recorder);
+    drop(cassette);
+    let _ = std::fs::remove_file(&path);
+  }
+
+  #[tokio::test]
+  async fn custom_transport_runs_through_middleware() {
+    use std::sync::{Arc, Mutex};
+    use transport::{BoxFuture, Headers, HttpRequest, HttpResponse, HttpTransport, Metrics};
+
+    /// Answers from memory and keeps every request it sees
+    struct Fake(Arc<Mutex<Vec<HttpRequest>>>);
+
+    impl HttpTransport for Fake {
+      fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, http::StatusCode>> {
+        Box::pin(async move {
+          let status = if request.url.ends_with("/1.1.1.1") { http::StatusCode::UNAUTHORIZED } else { http::StatusCode::OK };
+          self.0.lock().unwrap().push(request);
+          Ok(HttpResponse { status, body: String::from(r#"{"ip":"8.8.8.8","noise":false,"riot":true,"code":"0x00"}"#) })
+        })
+      }
+    }
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let metrics = Metrics::new();
+    let client = client::Client::new(Some("k"))
+      .with_base_url("http://fake.invalid")
+      .with_transport(Fake(Arc::clone(&seen)))
+      .with_middleware(Headers::new().header("user-agent", "greynoise-test"))
+      .with_middleware(metrics.clone());
+
+    assert!(client.quick_check("8.8.8.8").await.unwrap().riot);
+    assert_eq!(client.quick_check("1.1.1.1").await.unwrap_err(), http::StatusCode::UNAUTHORIZED);
+    assert_eq!(client.multi_quick_check(vec_of_strings!["8.8.8.8"]).await.unwrap_err(), http::StatusCode::BAD_REQUEST);
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 3);
+    assert!(seen[0].url.starts_with("http://fake.invalid/v2/noise/quick/"));
+    assert_eq!(seen[0].header_value("key"), Some("k"));
+    assert_eq!(seen[0].header_value("User-Agent"), Some("greynoise-test"));
+    assert_eq!(seen[2].method, transport::HttpMethod::Post);
+    assert_eq!(seen[2].body.as_deref(), Some(r#"{"ips":["8.8.8.8"]}"#));
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.requests, 3);
+    assert_eq!(snapshot.statuses.get(&200), Some(&2));
+    assert_eq!(snapshot.statuses.get(&401), Some(&1));
+  }
+
+  #[tokio::test]
+  async fn module_functions_use_the_default_pipeline() {
+    use transport::{BoxFuture, HttpRequest, HttpResponse, Middleware, Next};
+
+    /// Answers for one IP and passes every other request on, so other tests are unaffected
+    struct Answer;
+
+    impl Middleware for Answer {
+      fn handle<'a>(&'a self, request: HttpRequest, next: Next<'a>) -> BoxFuture<'a, Result<HttpResponse, http::StatusCode>> {
+        if !request.url.ends_with("/203.0.113.77") {
+          return next.run(request);
+        }
+        Box::pin(async move {
+          Ok(HttpResponse { status: http::StatusCode::OK, body: String::from(r#"{"ip":"203.0.113.77","noise":true,"riot":false,"code":"0x01"}"#) })
+        })
+      }
+    }
+
+    transport::add_default_middleware(Answer);
+
+    assert!(quick_check::quick_check("203.0.113.77", None).await.unwrap().noise);
+    assert!(client::Client::new(None).quick_check("203.0.113.77").await.unwrap().noise);
+  }
+
+  #[tokio::test]
+  async fn timeline_builds_requests_and_finds_malicious_span() {
+    use std::sync::{Arc, Mutex};
+    use timeline::{Granularity, TimelineField};
+    use transport::{BoxFuture, HttpRequest, HttpResponse, HttpTransport};
+
+    struct Canned(Arc<Mutex<Vec<String>>>);
+
+    impl HttpTransport for Canned {
+      fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, http::StatusCode>> {
+        Box::pin(async move {
+          let body = if request.url.contains("/timeline") {
+            serde_json::json!({
+              "metadata": { "ip": "45.83.66.65", "field": "classification", "granularity": "1d", "metric": "count" },
+              "results": [
+                { "timestamp": "2024-03-03T00:00:00Z", "label": "malicious", "data": 4 },
+                { "timestamp": "2024-03-01T00:00:00Z", "label": "benign", "data": 9 },
+                { "timestamp": "2024-03-02T00:00:00Z", "label": "malicious", "data": 12 },
+                { "timestamp": "2024-03-04T00:00:00Z", "label": "malicious", "data": 0 },
+              ],
+            })
+          } else {
+            serde_json::json!({
+              "metadata": { "ip": "45.83.66.65", "limit": 1, "next_cursor": "abc" },
+              "activity": [{
+                "timestamp": "2024-03-02T13:00:00Z",
+                "classification": "malicious",
+                "tags": [{ "name": "Mirai", "intention": "malicious", "category": "worm" }],
+                "protocols": [{ "port": 23, "transport_protocol": "TCP" }],
+                "http_user_agents": ["Mozilla/5.0"],
+              }],
+            })
+          };
+          self.0.lock().unwrap().push(request.url);
+          Ok(HttpResponse { status: http::StatusCode::OK, body: body.to_string() })
+        })
+      }
+    }
+
+    assert_eq!("6h".parse::<Granularity>(), Ok(Granularity::Hours(6)));
+    assert!("25h".parse::<Granularity>().is_err());
+    assert!("é".parse::<Granularity>().is_err());
+    assert!("1é".parse::<Granularity>().is_err());
+    assert!("".parse::<Granularity>().is_err());
+    assert_eq!("tags".parse::<TimelineField>(), Ok(TimelineField::TagIds));
+
+    let urls = Arc::new(Mutex::new(Vec::new()));
+    let client = client::Client::new(None).with_transport(Canned(Arc::clone(&urls)));
+
+    let res = client.timeline("45.83.66.65", TimelineField::Classification, Some(30), Some(Granularity::Days(1))).await.unwrap();
+    assert_eq!(res.span("malicious"), Some(("2024-03-02T00:00:00Z", "2024-03-03T00:00:00Z")));
+    assert_eq!(res.span("unknown"), None);
+
+    let hourly = client.hourly_summary("45.83.66.65", Some(1), Some(1), Some("xyz")).await.unwrap();
+    assert_eq!(hourly.activity[0].tags[0].name, "Mirai");
+    assert_eq!(hourly.activity[0].protocols[0].port, Some(23));
+    assert_eq!(hourly.metadata.next_cursor.as_deref(), Some("abc"));
+
+    let urls = urls.lock().unwrap();
+    assert!(urls[0].ends_with("/v3/noise/ips/45.83.66.65/timeline?field=classification&days=30&granularity=1d"));
+    assert!(urls[1].ends_with("/v3/noise/ips/45.83.66.65/hourly-summary?days=1&limit=1&cursor=xyz"));
+  }
+
+  #[tokio::test]
+  async fn similarity_requests_scores_and_features() {
+    use std::sync::{Arc, Mutex};
+    use transport::{BoxFuture, HttpRequest, HttpResponse, HttpTransport};
+
+    struct Canned(Arc<Mutex<Vec<String>>>);
+
+    impl HttpTransport for Canned {
+      fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, http::StatusCode>> {
+        Box::pin(async move {
+          self.0.lock().unwrap().push(request.url);
+          let body = serde_json::json!({
+            "ip": { "ip": "45.83.66.65", "classification": "malicious", "actor": "unknown" },
+            "similar_ips": [
+              { "ip": "45.83.66.66", "score": 0.99, "features": ["ja3_fp", "ports", "useragents"], "asn": "AS208843" },
+              { "ip": "185.224.128.17", "score": 0.91, "features": ["ports"] },
+            ],
+            "total": 27,
+          });
+          Ok(HttpResponse { status: http::StatusCode::OK, body: body.to_string() })
+        })
+      }
+    }
+
+    let urls = Arc::new(Mutex::new(Vec::new()));
+    let client = client::Client::new(None).with_transport(Canned(Arc::clone(&urls)));
+
+    let res = client.similarity("45.83.66.65", Some(0.9), Some(2)).await.unwrap();
+    assert_eq!(res.ip.score, None);
+    assert_eq!(res.total, Some(27));
+    assert_eq!(res.similar_ips[0].features, vec_of_strings!["ja3_fp", "ports", "useragents"]);
+    assert_eq!(res.ips_above(0.95), vec!["45.83.66.66"]);
+    assert!(urls.lock().unwrap()[0].ends_with("/v3/similarity/ips/45.83.66.65?minimum_score=0.9&limit=2"));
+  }
+
+  #[tokio::test]
+  async fn tag_detail_activity_and_ips() {
+    let mirai = metadata::tag("mirai", Some(key())).await.unwrap();
+    assert!(matches!(mirai.category, metadata::Category::Worm));
+    assert!(matches!(mirai.intention, metadata::Intention::Malicious));
+    assert_eq!(metadata::tag(&mirai.id, Some(key())).await.unwrap().slug, "mirai");
+    assert_eq!(metadata::tag("no-such-tag", Some(key())).await.unwrap_err(), http::StatusCode::NOT_FOUND);
+
+    let activity = metadata::tag_activity("mirai", Some(3), Some(timeline::Granularity::Days(1)), Some(key())).await.unwrap();
+    assert_eq!(activity.metadata.slug.as_deref(), Some("mirai"));
+    assert_eq!(activity.results.iter().map(|b| b.data).collect::<Vec<_>>(), vec![1, 1, 1]);
+
+    let ips = metadata::tag_ips(&mirai.name, None, Some(key())).await.unwrap();
+    assert_eq!(ips.iter().map(|d| d.ip.as_str()).collect::<Vec<_>>(), vec!["45.83.66.65"]);
+    assert_eq!(metadata::tag_query("Say \"hi\""), "tags:\"Say \\\"hi\\\"\"");
+  }
+
+  #[tokio::test]
+  async fn plan_gating_refuses_and_downgrades() {
+    use error::Error;
+    use ping::{ApiEndpoint, Offering};
+
+    let mut fixtures = mock::Fixtures::sample();
+    fixtures.ping = serde_json::json!({ "message": "pong", "expiration": "2099-12-31", "offering": "community" });
+    let server = mock::MockServer::start(fixtures).unwrap();
+
+    let gated = client::Client::new(Some("k")).with_base_url(&server.url()).with_plan_gating(false);
+    let refused = gated.ip_context("45.83.66.65").await.unwrap_err();
+    assert_eq!(refused, Error::RequiresPlan { endpoint: ApiEndpoint::IpContext, offering: Offering::Community });
+    assert_eq!(refused, http::StatusCode::FORBIDDEN);
+    assert_eq!(refused.to_string(), "the ip_context endpoint is not available on the community plan");
+    assert!(gated.gnql_query("tags:Mirai", None, None).await.is_err());
+    assert!(gated.community("45.83.66.65").await.unwrap().is_found());
+
+    let routes: Vec<mock::Route> = server.requests().into_iter().map(|(r, _)| r).collect();
+    assert_eq!(routes, vec![mock::Route::Ping, mock::Route::Community]);
+
+    let downgraded = client::Client::new(Some("k")).with_base_url(&server.url()).with_plan_gating(true);
+    let context = downgraded.ip_context("45.83.66.65").await.unwrap();
+    assert!(context.seen);
+    assert_eq!(context.classification.as_deref(), Some("malicious"));
+    assert!(!downgraded.ip_context("10.0.0.1").await.unwrap().seen);
+    let quick = downgraded.multi_quick_check(vec_of_strings!["45.83.66.65", "8.8.8.8"]).await.unwrap();
+    assert_eq!(quick.iter().map(|q| (q.noise, q.riot)).collect::<Vec<_>>(), vec![(true, false), (false, true)]);
+    assert!(matches!(downgraded.gnql_query("tags:Mirai", None, None).await, Err(Error::RequiresPlan { .. })));
+    let allow = blocklist::Allowlist::default();
+    let refused = blocklist::blocklist(&downgraded, "tags:Mirai", &allow, None).await.unwrap_err();
+    assert_eq!(refused, Error::RequiresPlan { endpoint: ApiEndpoint::Gnql, offering: Offering::Community });
+
+    let mut fixtures = mock::Fixtures::sample();
+    fixtures.ping = serde_json::json!({ "message": "pong" });
+    let unreported = mock::MockServer::start(fixtures).unwrap();
+    let gated = client::Client::new(Some("k")).with_base_url(&unreported.url()).with_plan_gating(true);
+    assert_eq!(gated.offering().await.unwrap(), None);
+    assert_eq!(gated.ip_context("45.83.66.65").await.unwrap().classification.as_deref(), Some("malicious"));
+    assert!(gated.gnql_query("tags:Mirai", None, None).await.is_ok());
+
+    let pings = || server.requests().iter().filter(|(r, _)| *r == mock::Route::Ping).count();
+    let before = pings();
+    let enterprise = client::Client::new(Some("k")).with_base_url(&server.url()).with_plan_gating(false).with_offering(Offering::Enterprise);
+    assert_eq!(enterprise.gnql_query("tags:Mirai", None, None).await.unwrap().count, 1);
+    assert_eq!(pings(), before);
+  }
+
+  #[tokio::test]
+  async fn verdict_deepens_only_when_needed() {
+    use verdict::{Confidence, Disposition, Reason};
+
+    let server = mock::MockServer::start(mock::Fixtures::sample()).unwrap();
+    let client = client::Client::new(Some("k")).with_base_url(&server.url());
+
+    let google = client.verdict("8.8.8.8").await.unwrap();
+    assert_eq!((google.disposition, google.confidence), (Disposition::BenignService, Confidence::High));
+    assert!(google.context.is_none());
+    assert_eq!(google.reasons[0].to_string(), "RIOT: Google Public DNS (public_dns), trust level 1");
+
+    let mirai = client.verdict("45.83.66.65").await.unwrap();
+    assert_eq!((mirai.disposition, mirai.confidence), (Disposition::Malicious, Confidence::High));
+    assert!(mirai.reasons.contains(&Reason::Tag { name: String::from("Mirai") }));
+    assert!(mirai.riot.is_none());
+
+    let shodan = client.verdict("71.6.233.151").await.unwrap();
+    assert_eq!(shodan.disposition, Disposition::KnownScanner);
+    assert!(shodan.reasons.contains(&Reason::Actor { name: String::from("Shodan.io") }));
+
+    let unseen = client.verdict("10.0.0.1").await.unwrap();
+    assert_eq!((unseen.disposition, unseen.reasons.clone()), (Disposition::NotSeen, vec![Reason::NotObserved]));
+
+    let routes: Vec<mock::Route> = server.requests().into_iter().map(|(r, _)| r).collect();
+    assert_eq!(routes, vec![
+      mock::Route::Quick, mock::Route::Riot,
+      mock::Route::Quick, mock::Route::Context,
+      mock::Route::Quick, mock::Route::Context,
+      mock::Route::Quick,
+    ]);
+
+    let json = serde_json::to_value(&mirai).unwrap();
+    assert_eq!(json["disposition"], "malicious");
+    assert_eq!(json["reasons"][0], serde_json::json!({ "kind": "classification", "classification": "malicious" }));
+    assert_eq!(verdict::verdict("8.8.8.8", Some(key())).await.unwrap().disposition, Disposition::BenignService);
+  }
+
+  #[cfg(feature = "toml")]
+  #[tokio::test]
+  async fn policy_decides_and_reports_matched_rules() {
+    use policy::{Action, Policy};
+
+    let policy = Policy::from_toml(r#"
+      default = "alert"
+      inventory_cves = ["cve-2016-10401"]
+
+      [[rules]]
+      name = "trusted service"
+      action = "ignore"
+      when = { riot_trust_level = "1" }
+
+      [[rules]]
+      name = "malicious, block recommended"
+      action = "block"
+      when = { classification = "malicious", recommend_block = true }
+
+      [[rules]]
+      name = "targets our stack"
+      action = "alert"
+      when = { inventory_cve = true, tags = ["mirai", "Eternalblue"] }
+    "#).unwrap();
+    assert!(policy.uses_tag_metadata());
+
+    let server = mock::MockServer::start(mock::Fixtures::sample()).unwrap();
+    let client = client::Client::new(Some("k")).with_base_url(&server.url()).with_cache(cache::Cache::memory(100));
+
+    let google = client.decide("8.8.8.8", &policy).await.unwrap();
+    assert_eq!((google.action, google.rule.as_deref()), (Action::Ignore, Some("trusted service")));
+
+    let mirai = client.decide("45.83.66.65", &policy).await.unwrap();
+    assert_eq!(mirai.action, Action::Block);
+    assert_eq!(mirai.matched, vec!["malicious, block recommended", "targets our stack"]);
+
+    let shodan = client.decide("71.6.233.151", &policy).await.unwrap();
+    assert_eq!((shodan.action, shodan.rule, shodan.matched.len()), (Action::Alert, None, 0));
+
+    let metadata_calls = server.requests().into_iter().filter(|(r, _)| *r == mock::Route::Metadata).count();
+    assert_eq!(metadata_calls, 1);
+
+    let json = serde_json::to_value(&mirai).unwrap();
+    assert_eq!((json["action"].clone(), json["disposition"].clone()), (serde_json::json!("block"), serde_json::json!("malicious")));
+    assert_eq!(policy::decide("45.83.66.65", &policy, Some(key())).await.unwrap().action, Action::Block);
+
+    assert!(Policy::from_toml("[[rules]]\nname = \"x\"\naction = \"block\"\nwhen = { colour = \"red\" }").is_err());
+    assert!(Policy::from_toml("[[rules]]\nname = \"\"\naction = \"block\"").is_err());
+    assert!(Policy::from_toml("[[rules]]\nname = \"x\"\naction = \"drop\"").is_err());
+
+    #[cfg(feature = "yaml")]
+    {
+      let yaml = Policy::from_yaml("rules:\n  - name: scanners\n    action: alert\n    when:\n      disposition: [known_scanner, unknown]\n").unwrap();
+      let shodan = client.verdict("71.6.233.151").await.unwrap();
+      assert_eq!(yaml.evaluate(&shodan, &[]).action, Action::Alert);
+    }
+  }
+
+  #[test]
+  fn rate_limiter_rejects_bad_rates_and_caps_waits() {
+    use rate_limit::RateLimiter;
+
+    assert!(RateLimiter::new(0.0, 1).is_err());
+    assert!(RateLimiter::new(-1.0, 1).is_err());
+    assert!(RateLimiter::new(f64::NAN, 1).is_err());
+
+    let slow = RateLimiter::new(f64::MIN_POSITIVE, 1).unwrap();
+    assert!(slow.check("a").is_ok());
+    assert_eq!(slow.check("a"), Err(std::time::Duration::from_secs(86_400)));
+
+    let limiter = RateLimiter::new(1.0, 5).unwrap();
+    assert!(limiter.check_n("a", 3).is_ok());
+    assert!(limiter.check_n("a", 3).is_err());
+    assert!(limiter.check_n("a", 2).is_ok());
+    assert!(limiter.check_n("b", 20).is_ok());
+    let wait = limiter.check("b").unwrap_err();
+    assert!(wait > std::time::Duration::from_secs(15) && wait <= std::time::Duration::from_secs(16));
+  }
 
 }
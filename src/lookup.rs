@@ -0,0 +1,32 @@
+//! Typed outcome for lookups that can legitimately come back empty
+
+/// Result of looking an IP address up in a GreyNoise dataset.
+///
+/// The Community and RIOT APIs answer with a `404` and a JSON body when an IP is not part
+/// of their dataset. That answer is data, not a failure, so it is returned as
+/// `Lookup::NotFound` (carrying the API's message) instead of an `Err(StatusCode)`.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Lookup<T> {
+  /// The IP address is in the dataset
+  Found(T),
+
+  /// The IP address is not in the dataset
+  NotFound { message: String },
+}
+
+impl<T> Lookup<T> {
+
+  /// Returns `true` if the IP address was found in the dataset
+  pub fn is_found(&self) -> bool {
+    matches!(self, Lookup::Found(_))
+  }
+
+  /// Converts the lookup into an `Option`, discarding the not-found message
+  pub fn found(self) -> Option<T> {
+    match self {
+      Lookup::Found(v) => Some(v),
+      Lookup::NotFound { .. } => None,
+    }
+  }
+
+}
@@ -0,0 +1,699 @@
+//! `greynoise` command-line utility
+//!
+//! Thin wrapper around the `greynoise` crate with one subcommand per API module. IPs can be
+//! passed as arguments or, when none are given (or `-` is), read one per line from stdin.
+//!
+//! Exit status:
+//!
+//! - `0`: every lookup succeeded
+//! - `1`: at least one IP was not found in the Community or RIOT dataset
+//! - `2`: usage error or unreadable input
+//! - `3`: an API call failed
+//! - `4`: the API key was rejected or is missing
+//! - `5`: the API rate limit was hit
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use http::StatusCode;
+use serde::Serialize;
+
+use greynoise::blocklist::{blocklist, Allowlist, BlocklistFormat};
+use greynoise::client::Client;
+use greynoise::enrich::{Enricher, Extractor, MULTI_QUICK_BATCH};
+use greynoise::error::Error;
+use greynoise::fields::{ArrayMode, Flattener};
+use greynoise::gn;
+use greynoise::lookup::Lookup;
+use greynoise::metadata::Metadatum;
+use greynoise::output::{self, Format};
+use greynoise::policy::Policy;
+use greynoise::sensor_logs::{enrich_sensor_log, SensorFormat};
+use greynoise::timeline::{Granularity, TimelineField};
+
+const EXIT_NOT_FOUND: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_API_ERROR: u8 = 3;
+const EXIT_AUTH: u8 = 4;
+const EXIT_RATE_LIMITED: u8 = 5;
+
+/// `ping` warns when the key expires within this many days
+const KEY_EXPIRY_WARNING_DAYS: i64 = 30;
+
+#[derive(Parser)]
+#[command(name = "greynoise", version, about = "Query the GreyNoise API")]
+struct Cli {
+  /// GreyNoise API key (defaults to the one in the config file)
+  #[arg(short, long, global = true)]
+  key: Option<String>,
+
+  /// Path to the GreyNoise config file
+  #[arg(short, long, global = true, default_value = "~/.config/greynoise/config")]
+  config: String,
+
+  /// Output format: table, json, ndjson, csv, tsv or markdown
+  #[arg(short, long, global = true, default_value = "json")]
+  format: Format,
+
+  /// Only output these comma-separated dotted fields, e.g. ip,metadata.asn,raw_data.scan.port
+  #[arg(long, global = true, value_delimiter = ',')]
+  fields: Vec<String>,
+
+  /// How to flatten arrays: join, explode (one row per element) or count
+  #[arg(long, global = true)]
+  arrays: Option<ArrayMode>,
+
+  /// Check the key's plan before each call: refuse calls it cannot make, and answer
+  /// context and quick check lookups from the Community API instead
+  #[arg(long, global = true)]
+  downgrade: bool,
+
+  #[command(subcommand)]
+  command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+  /// Check GreyNoise API status and key validity
+  Ping,
+
+  /// Look IPs up with the Community API
+  Community {
+    /// IP addresses (read from stdin if omitted)
+    ips: Vec<String>,
+  },
+
+  /// Look IPs up in the RIOT dataset
+  Riot {
+    /// IP addresses (read from stdin if omitted)
+    ips: Vec<String>,
+  },
+
+  /// Retrieve full IP context
+  Context {
+    /// IP addresses (read from stdin if omitted)
+    ips: Vec<String>,
+  },
+
+  /// One verdict per IP from Quick Check, RIOT and IP Context
+  Verdict {
+    /// IP addresses (read from stdin if omitted)
+    ips: Vec<String>,
+  },
+
+  /// Decide block, alert or ignore per IP with a policy file
+  Decide {
+    /// Policy file (TOML, or YAML with the yaml feature)
+    #[arg(long)]
+    policy: String,
+
+    /// IP addresses (read from stdin if omitted)
+    ips: Vec<String>,
+  },
+
+  /// Show an IP's activity over time
+  Timeline {
+    /// IP address
+    ip: String,
+
+    /// Field to break activity down by: classification, destination_port, http_path,
+    /// http_user_agent, source_asn, source_org, source_rdns or tag_ids
+    #[arg(long, default_value = "classification")]
+    field: TimelineField,
+
+    /// Number of days to cover
+    #[arg(short, long)]
+    days: Option<u32>,
+
+    /// Bucket size, 1h to 24h or 1d to 90d
+    #[arg(short, long)]
+    granularity: Option<Granularity>,
+
+    /// Show the hour-by-hour activity summary instead
+    #[arg(long, conflicts_with_all = ["field", "granularity"])]
+    hourly: bool,
+
+    /// Hours per page of the hourly summary
+    #[arg(long, requires = "hourly")]
+    limit: Option<u32>,
+
+    /// Cursor from a previous page of the hourly summary
+    #[arg(long, requires = "hourly")]
+    cursor: Option<String>,
+  },
+
+  /// Find IPs behaving like an IP
+  Similar {
+    /// IP address
+    ip: String,
+
+    /// Lowest similarity score to include, 0.85 to 1
+    #[arg(long)]
+    min_score: Option<f64>,
+
+    /// Most similar IPs to return
+    #[arg(short, long)]
+    limit: Option<u32>,
+  },
+
+  /// Quick check IPs one at a time
+  Quick {
+    /// IP addresses (read from stdin if omitted)
+    ips: Vec<String>,
+  },
+
+  /// Quick check IPs in bulk
+  MultiQuick {
+    /// IP addresses (read from stdin if omitted)
+    ips: Vec<String>,
+  },
+
+  /// Run a GNQL query
+  Gnql {
+    /// GNQL query, e.g. "tags:Mirai"
+    query: String,
+
+    /// Number of results per page
+    #[arg(short, long)]
+    size: Option<i64>,
+
+    /// Scroll token from a previous page
+    #[arg(long)]
+    scroll: Option<String>,
+  },
+
+  /// List GreyNoise tags and their metadata
+  Metadata,
+
+  /// Show one tag, how many IPs carried it over time, or the IPs carrying it
+  Tag {
+    /// Tag ID or slug, e.g. mirai
+    tag: String,
+
+    /// Show the number of IPs carrying the tag over time
+    #[arg(long, conflicts_with = "ips")]
+    activity: bool,
+
+    /// List the IPs carrying the tag
+    #[arg(long)]
+    ips: bool,
+
+    /// Number of days of activity to cover
+    #[arg(short, long, requires = "activity")]
+    days: Option<u32>,
+
+    /// Activity bucket size, 1h to 24h or 1d to 90d
+    #[arg(short, long, requires = "activity")]
+    granularity: Option<Granularity>,
+
+    /// Most IPs to list
+    #[arg(short, long, requires = "ips")]
+    limit: Option<usize>,
+  },
+
+  /// Append GreyNoise verdicts to every line of a log file
+  Enrich {
+    /// Log file (stdin if omitted or `-`)
+    file: Option<String>,
+
+    /// Regex matching the IPs to look up (its first capture group, if it has one)
+    #[arg(long, conflicts_with = "field")]
+    regex: Option<String>,
+
+    /// 1-based field holding the IP to look up
+    #[arg(long)]
+    field: Option<NonZeroUsize>,
+
+    /// Field delimiter for --field (whitespace by default)
+    #[arg(long, requires = "field")]
+    delimiter: Option<char>,
+
+    /// Deepen noise hits with IP Context
+    #[arg(long)]
+    context: bool,
+
+    /// Deepen RIOT hits with a RIOT lookup
+    #[arg(long)]
+    riot: bool,
+  },
+
+  /// Add GreyNoise fields to a Zeek conn.log or Suricata eve.json, keeping its format
+  EnrichSensor {
+    /// Log file (stdin if omitted or `-`)
+    file: Option<String>,
+
+    /// Log type: zeek, zeek-json or eve (detected from the first line if omitted)
+    #[arg(long)]
+    log_type: Option<SensorFormat>,
+
+    /// Field holding the IP to look up (id.orig_h for Zeek, src_ip for Suricata)
+    #[arg(long)]
+    ip_field: Option<String>,
+
+    /// Deepen noise hits with IP Context
+    #[arg(long)]
+    context: bool,
+
+    /// Deepen RIOT hits with a RIOT lookup
+    #[arg(long)]
+    riot: bool,
+  },
+
+  /// Build a firewall blocklist from the IPs matched by a GNQL query
+  Blocklist {
+    /// GNQL query, e.g. "classification:malicious last_seen:1d"
+    query: String,
+
+    /// List format: plain, ipset, iptables, ip6tables, nftables, pf or edl
+    #[arg(long, default_value = "plain")]
+    list_format: BlocklistFormat,
+
+    /// With iptables (or ip6tables), write the ruleset for the other address family here;
+    /// required when the list holds addresses of both
+    #[arg(long)]
+    other_family_output: Option<String>,
+
+    /// Set, chain or table name used in the list
+    #[arg(long, default_value = "greynoise")]
+    name: String,
+
+    /// CIDR that must never be blocked (repeatable)
+    #[arg(long)]
+    allow: Vec<String>,
+
+    /// File of CIDRs that must never be blocked, one per line
+    #[arg(long)]
+    allow_file: Option<String>,
+
+    /// Stop after this many GNQL results
+    #[arg(long)]
+    limit: Option<usize>,
+  },
+}
+
+/// Use `--key`, falling back to the config file if it exists
+fn resolve_key(cli: &Cli) -> Option<String> {
+
+  if cli.key.is_some() {
+    return cli.key.clone();
+  }
+
+  if Path::new(shellexpand::tilde(&cli.config).as_ref()).exists() {
+    Some(gn::api_key(Some(cli.config.clone())))
+  } else {
+    None
+  }
+
+}
+
+/// Use the IPs given as arguments, or read them from stdin
+fn read_ips(ips: Vec<String>) -> Vec<String> {
+
+  if !ips.is_empty() && ips != ["-"] {
+    return ips;
+  }
+
+  io::stdin().lock().lines()
+    .map_while(Result::ok)
+    .map(|l| l.trim().to_string())
+    .filter(|l| !l.is_empty() && !l.starts_with('#'))
+    .collect()
+
+}
+
+fn exit_code_for(status: StatusCode) -> u8 {
+  match status {
+    StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => EXIT_AUTH,
+    StatusCode::TOO_MANY_REQUESTS => EXIT_RATE_LIMITED,
+    _ => EXIT_API_ERROR,
+  }
+}
+
+/// Open `file`, or stdin if it is `None` or `-`
+fn open_input(file: Option<String>) -> io::Result<Box<dyn BufRead>> {
+  match file.filter(|f| f != "-") {
+    Some(path) => File::open(&path)
+      .map(|f| Box::new(BufReader::new(f)) as Box<dyn BufRead>)
+      .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e))),
+    None => Ok(Box::new(io::stdin().lock())),
+  }
+}
+
+/// Tracks the exit status across lookups (the highest code wins) and prints results
+struct Outcome {
+  code: u8,
+  format: Format,
+  flattener: Option<Flattener>,
+}
+
+impl Outcome {
+
+  fn fail(&mut self, code: u8) {
+    self.code = self.code.max(code);
+  }
+
+  /// Report a failed call on stderr and pass a successful one through
+  fn check<T, E: Into<Error>>(&mut self, res: Result<T, E>, what: &str) -> Option<T> {
+    match res.map_err(Into::into) {
+      Ok(v) => Some(v),
+      Err(e) => {
+        eprintln!("{}: {}", what, e);
+        self.fail(exit_code_for(e.status()));
+        None
+      }
+    }
+  }
+
+  /// Like `check`, also reporting IPs missing from the dataset on stderr
+  fn check_lookup<T, E: Into<Error>>(&mut self, res: Result<Lookup<T>, E>, ip: &str) -> Option<T> {
+    match self.check(res, ip)? {
+      Lookup::Found(v) => Some(v),
+      Lookup::NotFound { message } => {
+        eprintln!("{}: {}", ip, message);
+        self.fail(EXIT_NOT_FOUND);
+        None
+      }
+    }
+  }
+
+  fn emit<T: Serialize>(&mut self, records: &[T]) {
+    let res = match &self.flattener {
+      Some(f) => output::write_rows(io::stdout().lock(), &f.rows(records), self.format),
+      None => output::write_records(io::stdout().lock(), records, self.format),
+    };
+    if let Err(e) = res {
+      eprintln!("could not write output: {}", e);
+      self.fail(EXIT_API_ERROR);
+    }
+  }
+
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+
+  let cli = Cli::parse();
+  let key = resolve_key(&cli);
+  let client = match cli.downgrade {
+    true => Client::new(key.as_deref()).with_plan_gating(true),
+    false => Client::new(key.as_deref()),
+  };
+  let flattener = if cli.fields.is_empty() && cli.arrays.is_none() {
+    None
+  } else {
+    Some(Flattener::new().fields(cli.fields.clone()).arrays(cli.arrays.clone().unwrap_or_default()))
+  };
+  let mut outcome = Outcome { code: 0, format: cli.format, flattener };
+
+  match cli.command {
+
+    Command::Ping => {
+      match client.ping_details().await {
+        Ok(details) => {
+          match details.plan() {
+            Some(plan) => println!("GreyNoise API is reachable and the key is valid ({} plan)", plan),
+            None => println!("GreyNoise API is reachable and the key is valid (plan not reported)"),
+          }
+          match (details.expiration, details.days_until_expiry()) {
+            (Some(date), Some(days)) if days < 0 => eprintln!("the key expired on {}", date),
+            (Some(date), Some(days)) if days <= KEY_EXPIRY_WARNING_DAYS => eprintln!("the key expires on {} (in {} days)", date, days),
+            (Some(date), _) => println!("the key expires on {}", date),
+            _ => {}
+          }
+          let endpoints: Vec<&str> = details.allowed_endpoints().iter().map(|e| e.as_str()).collect();
+          println!("endpoints: {}", endpoints.join(", "));
+        }
+        Err(e) => {
+          eprintln!("GreyNoise API ping failed: {}", e);
+          outcome.fail(exit_code_for(e.status()));
+        }
+      }
+    }
+
+    Command::Community { ips } => {
+      let mut records = Vec::new();
+      for ip in read_ips(ips) {
+        records.extend(outcome.check_lookup(client.community(&ip).await, &ip));
+      }
+      outcome.emit(&records);
+    }
+
+    Command::Riot { ips } => {
+      let mut records = Vec::new();
+      for ip in read_ips(ips) {
+        records.extend(outcome.check_lookup(client.riot(&ip).await, &ip));
+      }
+      outcome.emit(&records);
+    }
+
+    Command::Context { ips } => {
+      let mut records = Vec::new();
+      for ip in read_ips(ips) {
+        records.extend(outcome.check(client.ip_context(&ip).await, &ip));
+      }
+      outcome.emit(&records);
+    }
+
+    Command::Verdict { ips } => {
+      let mut records = Vec::new();
+      for ip in read_ips(ips) {
+        records.extend(outcome.check(client.verdict(&ip).await, &ip));
+      }
+      outcome.emit(&records);
+    }
+
+    Command::Decide { policy, ips } => {
+
+      let policy = match Policy::load(&policy) {
+        Ok(policy) => policy,
+        Err(e) => {
+          eprintln!("{}: {}", policy, e);
+          return ExitCode::from(EXIT_USAGE);
+        }
+      };
+
+      // Tag metadata is fetched once, when the first IP that needs it comes up
+      let mut tags: Option<Vec<Metadatum>> = None;
+
+      let mut records = Vec::new();
+      for ip in read_ips(ips) {
+
+        let verdict = match outcome.check(client.verdict(&ip).await, &ip) {
+          Some(verdict) => verdict,
+          None => continue,
+        };
+
+        if tags.is_none() && policy.needs_tag_metadata(&verdict) {
+          match outcome.check(client.metadata().await, &ip) {
+            Some(res) => tags = Some(res.metadata),
+            None => continue,
+          }
+        }
+
+        records.push(policy.evaluate(&verdict, tags.as_deref().unwrap_or_default()));
+
+      }
+      outcome.emit(&records);
+
+    }
+
+    Command::Timeline { ip, field, days, granularity, hourly: false, .. } => {
+      if let Some(res) = outcome.check(client.timeline(&ip, field, days, granularity).await, &ip) {
+        if let Some((start, end)) = res.span("malicious") {
+          eprintln!("malicious from {} to {}", start, end);
+        }
+        outcome.emit(&res.results);
+      }
+    }
+
+    Command::Timeline { ip, days, limit, cursor, hourly: true, .. } => {
+      if let Some(res) = outcome.check(client.hourly_summary(&ip, days, limit, cursor.as_deref()).await, &ip) {
+        if let Some(cursor) = &res.metadata.next_cursor {
+          eprintln!("more hours available with --cursor {}", cursor);
+        }
+        outcome.emit(&res.activity);
+      }
+    }
+
+    Command::Similar { ip, min_score, limit } => {
+      if let Some(res) = outcome.check(client.similarity(&ip, min_score, limit).await, &ip) {
+        eprintln!("{} of {} similar IPs", res.similar_ips.len(), res.total.unwrap_or(res.similar_ips.len() as i64));
+        outcome.emit(&res.similar_ips);
+      }
+    }
+
+    Command::Quick { ips } => {
+      let mut records = Vec::new();
+      for ip in read_ips(ips) {
+        records.extend(outcome.check(client.quick_check(&ip).await, &ip));
+      }
+      outcome.emit(&records);
+    }
+
+    Command::MultiQuick { ips } => {
+      let mut records = Vec::new();
+      for batch in read_ips(ips).chunks(MULTI_QUICK_BATCH) {
+        records.extend(outcome.check(client.multi_quick_check(batch.to_vec()).await, "multi-quick").unwrap_or_default());
+      }
+      outcome.emit(&records);
+    }
+
+    Command::Gnql { query, size, scroll } => {
+      if let Some(res) = outcome.check(client.gnql_query(&query, size, scroll.as_deref()).await, "gnql") {
+        eprintln!("{} of {} results", res.data.len(), res.count);
+        if let Some(scroll) = res.scroll.filter(|_| res.complete != Some(true)) {
+          eprintln!("more results available with --scroll {}", scroll);
+        }
+        outcome.emit(&res.data);
+      }
+    }
+
+    Command::Metadata => {
+      if let Some(res) = outcome.check(client.metadata().await, "metadata") {
+        outcome.emit(&res.metadata);
+      }
+    }
+
+    Command::Tag { tag, activity: true, days, granularity, .. } => {
+      if let Some(res) = outcome.check(client.tag_activity(&tag, days, granularity).await, &tag) {
+        outcome.emit(&res.results);
+      }
+    }
+
+    Command::Tag { tag, ips: true, limit, .. } => {
+      if let Some(detail) = outcome.check(client.tag(&tag).await, &tag) {
+        if let Some(res) = outcome.check(client.tag_ips(&detail.name, limit).await, &tag) {
+          outcome.emit(&res);
+        }
+      }
+    }
+
+    Command::Tag { tag, .. } => {
+      if let Some(res) = outcome.check(client.tag(&tag).await, &tag) {
+        outcome.emit(&[res]);
+      }
+    }
+
+    Command::Enrich { file, regex, field, delimiter, context, riot } => {
+
+      let extractor = match (regex, field) {
+        (Some(re), _) => match regex::Regex::new(&re) {
+          Ok(re) => Extractor::Regex(re),
+          Err(e) => {
+            eprintln!("invalid --regex: {}", e);
+            return ExitCode::from(EXIT_USAGE);
+          }
+        },
+        (None, Some(index)) => Extractor::Field { index, delimiter },
+        (None, None) => Extractor::default(),
+      };
+
+      let enricher = Enricher::new(&client).extractor(extractor).context(context).riot(riot);
+
+      let input = match open_input(file) {
+        Ok(input) => input,
+        Err(e) => {
+          eprintln!("{}", e);
+          return ExitCode::from(EXIT_USAGE);
+        }
+      };
+
+      if let Err(e) = enricher.enrich(input, io::stdout().lock()).await {
+        eprintln!("enrich: {}", e);
+        outcome.fail(EXIT_API_ERROR);
+      }
+
+    }
+
+    Command::EnrichSensor { file, log_type, ip_field, context, riot } => {
+
+      let mut input = match open_input(file) {
+        Ok(input) => input,
+        Err(e) => {
+          eprintln!("{}", e);
+          return ExitCode::from(EXIT_USAGE);
+        }
+      };
+
+      // Peek at the first line to detect the log type, then put it back in front
+      let mut first = String::new();
+      if let Err(e) = input.read_line(&mut first) {
+        eprintln!("{}", e);
+        return ExitCode::from(EXIT_USAGE);
+      }
+
+      let format = match log_type.or_else(|| SensorFormat::detect(first.trim_end())) {
+        Some(f) => f,
+        None => {
+          eprintln!("could not detect the log type; pass --log-type zeek, zeek-json or eve");
+          return ExitCode::from(EXIT_USAGE);
+        }
+      };
+
+      let enricher = Enricher::new(&client).context(context).riot(riot);
+      let input = io::Cursor::new(first.into_bytes()).chain(input);
+
+      if let Err(e) = enrich_sensor_log(&enricher, format, ip_field.as_deref(), input, io::stdout().lock()).await {
+        eprintln!("enrich-sensor: {}", e);
+        outcome.fail(EXIT_API_ERROR);
+      }
+
+    }
+
+    Command::Blocklist { query, list_format, name, allow, allow_file, limit, other_family_output } => {
+
+      let mut cidrs = allow;
+      if let Some(path) = allow_file {
+        match std::fs::read_to_string(&path) {
+          Ok(contents) => cidrs.extend(contents.lines().map(String::from)),
+          Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return ExitCode::from(EXIT_USAGE);
+          }
+        }
+      }
+
+      let allowlist = match Allowlist::new(&cidrs) {
+        Ok(allowlist) => allowlist,
+        Err(e) => {
+          eprintln!("{}", e);
+          return ExitCode::from(EXIT_USAGE);
+        }
+      };
+
+      if let Some(list) = outcome.check(blocklist(&client, &query, &allowlist, limit).await, "blocklist") {
+        eprintln!("{} addresses ({} RIOT and {} allowlisted results excluded)", list.ips.len(), list.excluded_riot, list.excluded_allowlist);
+
+        let other = match list_format {
+          BlocklistFormat::Iptables => Some(BlocklistFormat::Ip6tables),
+          BlocklistFormat::Ip6tables => Some(BlocklistFormat::Iptables),
+          _ => None,
+        };
+
+        match (other, other_family_output) {
+          (Some(other), Some(path)) => {
+            if let Err(e) = std::fs::write(&path, list.render(other, &name)) {
+              eprintln!("{}: {}", path, e);
+              return ExitCode::from(EXIT_USAGE);
+            }
+          }
+          (Some(_), None) if list.left_out(list_format) > 0 => {
+            eprintln!("{} addresses of the other family cannot go in this ruleset; pass --other-family-output FILE for them, or use ipset or nftables", list.left_out(list_format));
+            return ExitCode::from(EXIT_USAGE);
+          }
+          _ => {}
+        }
+
+        print!("{}", list.render(list_format, &name));
+      }
+
+    }
+
+  }
+
+  ExitCode::from(outcome.code)
+
+}
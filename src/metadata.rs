@@ -1,5 +1,7 @@
-//! Make a request to the GreyNoise Tag Metadata API
+//! Make requests to the GreyNoise Tag Metadata and Tag APIs
 use crate::get;
+use crate::gnql::{self, Datum};
+use crate::timeline::Granularity;
 
 /// Structure to deserialize GreyNoise Tags Metadata JSON.
 ///
@@ -113,7 +115,7 @@ pub enum Intention {
 }
 
 #[doc(hidden)]
-const METADATA_URL: &str = "https://api.greynoise.io/v2/meta/metadata";
+pub(crate) const METADATA_URL: &str = "https://api.greynoise.io/v2/meta/metadata";
 
 /// Function to retrieve list of GreyNoise tags and their respective metadata
 ///
@@ -126,7 +128,7 @@ const METADATA_URL: &str = "https://api.greynoise.io/v2/meta/metadata";
 /// ```rust
 /// use greynoise::metadata;
 /// async {
-///  let res: Result<metadata::TagMetadata, reqwest::StatusCode> = metadata::metadata(None).await;
+///  let res: Result<metadata::TagMetadata, http::StatusCode> = metadata::metadata(None).await;
 ///  // assert_eq!(res.unwrap().ip, "8.8.8.8");
 /// };
 ///```
@@ -134,12 +136,150 @@ const METADATA_URL: &str = "https://api.greynoise.io/v2/meta/metadata";
 /// # Errors
 /// If the call fails, it will return a `Err(StatusCode)`.
 /// To see the possible return values, check the [API docs](https://docs.greynoise.io/reference/metadata-3).
-pub async fn metadata(key: Option<&str>) -> Result<TagMetadata, reqwest::StatusCode> {
+pub async fn metadata(key: Option<&str>) -> Result<TagMetadata, http::StatusCode> {
 
-  let url = format!("{}", METADATA_URL);
-  let res: Result<TagMetadata, reqwest::StatusCode> = get::query(url, key).await;
+  let url = METADATA_URL.to_string();
+  let res: Result<TagMetadata, http::StatusCode> = get::query(url, key).await;
 
   res
 
 }
 
+/// Structure to deserialize GreyNoise Tag Activity JSON.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TagActivity {
+    #[serde(rename = "metadata")]
+    pub metadata: TagActivityMetadata,
+
+    #[serde(rename = "results", default)]
+    pub results: Vec<TagActivityBucket>,
+}
+
+/// Structure to deserialize the metadata of GreyNoise Tag Activity JSON.
+///
+/// Any values inside `Option` are optional, and you should check if there is `Some` or `None` before using it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TagActivityMetadata {
+    #[serde(rename = "id")]
+    pub id: Option<String>,
+
+    #[serde(rename = "slug")]
+    pub slug: Option<String>,
+
+    #[serde(rename = "start")]
+    pub start: Option<String>,
+
+    #[serde(rename = "end")]
+    pub end: Option<String>,
+
+    #[serde(rename = "granularity")]
+    pub granularity: Option<String>,
+
+    #[serde(rename = "metric")]
+    pub metric: Option<String>,
+}
+
+/// Structure to deserialize one time bucket of GreyNoise Tag Activity JSON.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TagActivityBucket {
+    /// Start of the bucket (RFC 3339)
+    #[serde(rename = "timestamp")]
+    pub timestamp: String,
+
+    /// Number of distinct IPs seen carrying the tag
+    #[serde(rename = "data")]
+    pub data: i64,
+}
+
+#[doc(hidden)]
+pub(crate) const TAGS_URL: &str = "https://api.greynoise.io/v3/tags";
+
+/// Number of GNQL results requested per page by [`tag_ips`]
+#[doc(hidden)]
+pub(crate) const TAG_IPS_PAGE_SIZE: i64 = 1000;
+
+/// Build a Tag Activity URL
+#[doc(hidden)]
+pub(crate) fn tag_activity_url(tag: &str, days: Option<u32>, granularity: Option<Granularity>) -> String {
+
+  let base = format!("{}/{}/activity", TAGS_URL, tag);
+  let mut params: Vec<(&str, String)> = Vec::new();
+
+  if let Some(days) = days {
+    params.push(("days", days.to_string()));
+  }
+
+  if let Some(granularity) = granularity {
+    params.push(("granularity", granularity.to_string()));
+  }
+
+  match url::Url::parse_with_params(&base, &params) {
+    Ok(url) => url.to_string(),
+    Err(_) => base,
+  }
+
+}
+
+/// GNQL query matching the IPs carrying the tag named `name`
+#[doc(hidden)]
+pub(crate) fn tag_query(name: &str) -> String {
+  format!("tags:\"{}\"", name.replace('"', "\\\""))
+}
+
+/// Function to retrieve a single GreyNoise tag
+///
+/// `tag` is the tag's ID or slug (e.g. `mirai`).
+///
+/// For more information on the Tag API endpoint check the [API docs](https://docs.greynoise.io/reference/get_v3-tags-id).
+///
+/// # Example
+/// ```rust
+/// use greynoise::metadata;
+/// //async {
+///  //let res: Result<metadata::Metadatum, http::StatusCode> = metadata::tag("mirai", Some(API_KEY)).await;
+/// //};
+///```
+///
+/// # Errors
+/// If the call fails, it will return a `Err(StatusCode)`; an unknown tag is a `404`.
+/// To see the possible return values, check the [API docs](https://docs.greynoise.io/reference/get_v3-tags-id).
+pub async fn tag(tag: &str, key: Option<&str>) -> Result<Metadatum, http::StatusCode> {
+
+  let url = format!("{}/{}", TAGS_URL, tag);
+  let res: Result<Metadatum, http::StatusCode> = get::query(url, key).await;
+
+  res
+
+}
+
+/// Function to retrieve how many IPs carried a GreyNoise tag over time
+///
+/// `tag` is the tag's ID or slug. Covers the last `days` days in buckets of `granularity`
+/// (the API's defaults when `None`).
+///
+/// For more information on the Tag Activity API endpoint check the [API docs](https://docs.greynoise.io/reference/get_v3-tags-id-activity).
+///
+/// # Errors
+/// If the call fails, it will return a `Err(StatusCode)`; an unknown tag is a `404`.
+/// To see the possible return values, check the [API docs](https://docs.greynoise.io/reference/get_v3-tags-id-activity).
+pub async fn tag_activity(tag: &str, days: Option<u32>, granularity: Option<Granularity>, key: Option<&str>) -> Result<TagActivity, http::StatusCode> {
+
+  let url = tag_activity_url(tag, days, granularity);
+  let res: Result<TagActivity, http::StatusCode> = get::query(url, key).await;
+
+  res
+
+}
+
+/// Function to retrieve every IP carrying a GreyNoise tag
+///
+/// Pages through the GNQL query `tags:"<name>"`, where `name` is the tag's name (e.g.
+/// `Mirai`, as in [`Metadatum::name`]), collecting up to `limit` IPs.
+///
+/// # Errors
+/// If any page fails, it will return that page's `Err(StatusCode)`.
+pub async fn tag_ips(name: &str, limit: Option<usize>, key: Option<&str>) -> Result<Vec<Datum>, http::StatusCode> {
+
+  gnql::gnql_query_all(&tag_query(name), TAG_IPS_PAGE_SIZE, limit, key).await
+
+}
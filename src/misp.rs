@@ -0,0 +1,228 @@
+//! Export GreyNoise results as MISP events
+//!
+//! A [`MispEvent`] collects IP lookups (`ip_context::IPContext`, `gnql::Datum`) and writes
+//! them as MISP event JSON that can be imported offline (Events → Add Event → Import from
+//! MISP JSON):
+//!
+//! - every IP becomes an `ip-src` attribute in the `Network activity` category, flagged for
+//!   IDS export when GreyNoise classifies it as malicious
+//! - every CVE becomes a `vulnerability` attribute in the `External analysis` category
+//! - the GreyNoise `classification` becomes an `estimative-language:likelihood-probability`
+//!   tag on the IP attribute, and the event carries a `tlp:` tag (`tlp:green` by default)
+//! - GreyNoise tags become galaxy tags when their tag metadata is known (`worm` →
+//!   `misp-galaxy:malpedia`, `tool` → `misp-galaxy:tool`), and `greynoise:tag` taxonomy tags
+//!   otherwise; [`MispEvent::map_tag`] overrides the mapping for individual tags
+//!
+//! Event and attribute UUIDs are deterministic (UUIDv5 of the event info, a seed and the IP
+//! or CVE), so importing the same export again updates the existing event instead of
+//! duplicating it. The seed is the event date unless [`MispEvent::seed`] sets another, so a
+//! daily export under a fixed title still makes one event per day.
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+use crate::facts::{greynoise_uuid, IpFacts};
+use crate::gnql::Datum;
+use crate::ip_context::IPContext;
+use crate::metadata::{Category, Metadatum};
+
+/// Builder for a MISP event.
+///
+/// # Example
+/// ```rust
+/// use greynoise::gnql::Datum;
+/// use greynoise::misp::MispEvent;
+///
+/// let datum: Datum = serde_json::from_value(serde_json::json!({
+///   "ip": "45.83.66.65", "classification": "malicious", "tags": ["Mirai"]
+/// })).unwrap();
+///
+/// let mut event = MispEvent::new("GreyNoise: malicious scanners").tlp("amber");
+/// event.add_datum(&datum);
+///
+/// let json = event.to_value();
+/// assert_eq!(json["Event"]["Attribute"][0]["type"], "ip-src");
+/// assert_eq!(json["Event"]["Tag"][0]["name"], "tlp:amber");
+///```
+#[derive(Debug, Clone)]
+pub struct MispEvent {
+  info: String,
+  seed: String,
+  tlp: String,
+  tag_map: HashMap<String, String>,
+  attributes: Vec<Value>,
+  malicious: bool,
+}
+
+/// MISP tag for a GreyNoise classification on the likelihood scale
+fn likelihood(classification: Option<&str>) -> &'static str {
+  match classification {
+    Some("malicious") => "estimative-language:likelihood-probability=\"very-likely\"",
+    Some("benign") => "estimative-language:likelihood-probability=\"very-unlikely\"",
+    _ => "estimative-language:likelihood-probability=\"roughly-even-chance\"",
+  }
+}
+
+/// Turn a GreyNoise date (`2021-06-01`) into the ISO 8601 timestamp MISP expects
+fn iso_time(s: &str) -> String {
+  if s.len() == 10 { format!("{}T00:00:00Z", s) } else { s.to_string() }
+}
+
+fn tags(names: &[String]) -> Value {
+  Value::from(names.iter().map(|name| json!({ "name": name })).collect::<Vec<_>>())
+}
+
+impl MispEvent {
+
+  /// An empty event described by `info`, seeded with today's date
+  pub fn new(info: &str) -> MispEvent {
+    MispEvent {
+      info: info.to_string(),
+      seed: chrono::Utc::now().format("%Y-%m-%d").to_string(),
+      tlp: String::from("green"),
+      tag_map: HashMap::new(),
+      attributes: Vec::new(),
+      malicious: false,
+    }
+  }
+
+  /// Derive the event and attribute UUIDs from `seed` (a run or feed ID, a date…) instead
+  /// of today's date. Call before adding IPs.
+  pub fn seed(mut self, seed: &str) -> MispEvent {
+    self.seed = seed.to_string();
+    self
+  }
+
+  /// Traffic Light Protocol level of the event: `clear`, `white`, `green`, `amber`,
+  /// `amber+strict` or `red`
+  pub fn tlp(mut self, level: &str) -> MispEvent {
+    self.tlp = level.trim_start_matches("tlp:").to_ascii_lowercase();
+    self
+  }
+
+  /// Map GreyNoise tags to galaxy tags by their category. Call before adding IPs.
+  pub fn tag_metadata(mut self, metadata: &[Metadatum]) -> MispEvent {
+    for tag in metadata {
+      let galaxy = match tag.category {
+        Category::Worm => format!("misp-galaxy:malpedia=\"{}\"", tag.name),
+        Category::Tool => format!("misp-galaxy:tool=\"{}\"", tag.name),
+        Category::Activity | Category::Actor | Category::SearchEngine => continue,
+      };
+      self.tag_map.entry(tag.name.clone()).or_insert(galaxy);
+    }
+    self
+  }
+
+  /// Use the MISP tag `misp_tag` for the GreyNoise tag `tag`. Call before adding IPs.
+  pub fn map_tag(mut self, tag: &str, misp_tag: &str) -> MispEvent {
+    self.tag_map.insert(tag.to_string(), misp_tag.to_string());
+    self
+  }
+
+  /// Deterministic UUID for the event (`kind` `event`) or its attribute of type `kind` and
+  /// `value`
+  fn uuid(&self, kind: &str, value: &str) -> String {
+    greynoise_uuid(&format!("misp:{}:{}:{}:{}", self.info, self.seed, kind, value)).to_string()
+  }
+
+  fn misp_tag(&self, tag: &str) -> String {
+    self.tag_map.get(tag).cloned().unwrap_or_else(|| format!("greynoise:tag=\"{}\"", tag))
+  }
+
+  fn has_attribute(&self, kind: &str, value: &str) -> bool {
+    self.attributes.iter().any(|a| a["type"] == kind && a["value"] == value)
+  }
+
+  fn add_ip(&mut self, facts: IpFacts) {
+
+    if self.has_attribute("ip-src", facts.ip) {
+      return;
+    }
+
+    let malicious = facts.classification == Some("malicious");
+    self.malicious |= malicious;
+
+    let mut comment = format!("GreyNoise classification: {}", facts.classification.unwrap_or("unknown"));
+    if let Some(actor) = facts.actor.filter(|a| !a.is_empty() && *a != "unknown") {
+      comment.push_str(&format!("; actor: {}", actor));
+    }
+
+    let mut names = vec![likelihood(facts.classification).to_string()];
+    names.extend(facts.tags.iter().map(|t| self.misp_tag(t)));
+
+    let mut attribute = json!({
+      "uuid": self.uuid("ip-src", facts.ip),
+      "type": "ip-src",
+      "category": "Network activity",
+      "value": facts.ip,
+      "to_ids": malicious,
+      "comment": comment,
+      "Tag": tags(&names),
+    });
+
+    if let Some(first_seen) = facts.first_seen {
+      attribute["first_seen"] = Value::from(iso_time(first_seen));
+    }
+
+    if let Some(last_seen) = facts.last_seen {
+      attribute["last_seen"] = Value::from(iso_time(last_seen));
+    }
+
+    self.attributes.push(attribute);
+
+    for cve in facts.cves {
+      if !self.has_attribute("vulnerability", cve) {
+        self.attributes.push(json!({
+          "uuid": self.uuid("vulnerability", cve),
+          "type": "vulnerability",
+          "category": "External analysis",
+          "value": cve,
+          "to_ids": false,
+          "comment": format!("Exploited by {} per GreyNoise", facts.ip),
+        }));
+      }
+    }
+
+  }
+
+  /// Add an IP Context lookup as an `ip-src` attribute, with its CVEs
+  pub fn add_ip_context(&mut self, context: &IPContext) -> &mut MispEvent {
+    self.add_ip(IpFacts::from(context));
+    self
+  }
+
+  /// Add a GNQL result as an `ip-src` attribute, with its CVEs
+  pub fn add_datum(&mut self, datum: &Datum) -> &mut MispEvent {
+    self.add_ip(IpFacts::from(datum));
+    self
+  }
+
+  /// The event as MISP JSON. Threat level is medium if any IP is malicious and low
+  /// otherwise; the event is unpublished and limited to your organisation.
+  pub fn to_value(&self) -> Value {
+
+    let now = chrono::Utc::now();
+
+    json!({
+      "Event": {
+        "uuid": self.uuid("event", ""),
+        "info": self.info,
+        "date": now.format("%Y-%m-%d").to_string(),
+        "timestamp": now.timestamp().to_string(),
+        "threat_level_id": if self.malicious { "2" } else { "3" },
+        "analysis": "2",
+        "distribution": "0",
+        "published": false,
+        "Tag": tags(&[format!("tlp:{}", self.tlp)]),
+        "Attribute": self.attributes,
+      }
+    })
+
+  }
+
+  /// The event as pretty-printed MISP JSON
+  pub fn to_json(&self) -> String {
+    serde_json::to_string_pretty(&self.to_value()).unwrap_or_default()
+  }
+
+}
@@ -0,0 +1,567 @@
+//! Local GreyNoise API mock for offline testing
+//!
+//! [`MockServer`] serves the ping, Community, RIOT, IP Context, Quick Check, Multi Quick
+//! Check, GNQL, Metadata, Tag and Tag Activity endpoints from [`Fixtures`] on a local port,
+//! in a background thread with its own runtime, so it works from sync tests and from any
+//! async runtime.
+//! Point a [`crate::client::Client`] at it with `with_base_url(&mock.url())`, or point the
+//! module-level functions at it by setting the `GREYNOISE_API_URL` environment variable.
+//!
+//! Responses are derived from the fixtures the way the API derives them: Quick Check and
+//! Community answers come from the IP Context and RIOT fixtures, and GNQL filters the IP
+//! Context fixtures with `field:value` terms (dotted paths such as `metadata.country` work;
+//! `first_seen`/`last_seen` windows are ignored), and Tag Activity counts the IP Context
+//! fixtures carrying the tag on every day. Errors, latency, rate limits and API key
+//! checks can be scripted while the server runs.
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use serde_json::{json, Value};
+
+use crate::fields::select;
+use crate::rate_limit::RateLimiter;
+
+/// Endpoints served by the mock
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Route {
+  Ping,
+  Community,
+  Riot,
+  Context,
+  Quick,
+  MultiQuick,
+  Gnql,
+  Metadata,
+  Tag,
+  TagActivity,
+}
+
+/// Data the mock answers from, as the JSON the API would return
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Fixtures {
+  /// `/ping` response
+  pub ping: Value,
+
+  /// Community responses by IP; IPs without one get a response derived from the other fixtures
+  pub community: HashMap<String, Value>,
+
+  /// RIOT responses by IP
+  pub riot: HashMap<String, Value>,
+
+  /// IP Context responses by IP; these are also the GNQL dataset
+  pub context: HashMap<String, Value>,
+
+  /// Tag metadata entries
+  pub metadata: Vec<Value>,
+}
+
+impl Fixtures {
+
+  /// No IPs or tags, and a successful ping
+  pub fn new() -> Fixtures {
+    Fixtures {
+      ping: json!({ "message": "pong", "expiration": "2099-12-31", "offering": "enterprise" }),
+      ..Fixtures::default()
+    }
+  }
+
+  /// A small dataset: `8.8.8.8`/`8.8.4.4` in RIOT, `71.6.233.151` and `45.83.66.65` as
+  /// noise, and the tags they carry
+  pub fn sample() -> Fixtures {
+    Fixtures::new()
+      .riot("8.8.8.8", json!({
+        "ip": "8.8.8.8", "riot": true, "category": "public_dns", "name": "Google Public DNS",
+        "description": "Google's global domain name system (DNS) resolution service.",
+        "explanation": "Public DNS services are used as alternatives to ISP's name servers.",
+        "last_updated": "2022-06-01T00:00:00Z", "reference": "https://developers.google.com/speed/public-dns/docs/isp#alternative",
+        "trust_level": "1"
+      }))
+      .riot("8.8.4.4", json!({
+        "ip": "8.8.4.4", "riot": true, "category": "public_dns", "name": "Google Public DNS",
+        "description": "Google's global domain name system (DNS) resolution service.",
+        "explanation": "Public DNS services are used as alternatives to ISP's name servers.",
+        "last_updated": "2022-06-01T00:00:00Z", "reference": "https://developers.google.com/speed/public-dns/docs/isp#alternative",
+        "trust_level": "1"
+      }))
+      .context("71.6.233.151", json!({
+        "ip": "71.6.233.151", "seen": true, "first_seen": "2019-02-17", "last_seen": "2022-06-15",
+        "classification": "benign", "actor": "Shodan.io", "spoofable": false, "bot": false, "vpn": false,
+        "vpn_service": "N/A", "tags": ["Shodan.io", "Web Crawler"], "cve": [],
+        "metadata": {
+          "asn": "AS10439", "city": "San Diego", "country": "United States", "country_code": "US",
+          "organization": "CariNet, Inc.", "category": "hosting", "tor": false,
+          "rdns": "census7.shodan.io", "os": "Linux 2.2-3.x", "region": "California"
+        },
+        "raw_data": {
+          "scan": [{ "port": 80, "protocol": "TCP" }, { "port": 443, "protocol": "TCP" }],
+          "web": {},
+          "ja3": [], "hassh": []
+        }
+      }))
+      .context("45.83.66.65", json!({
+        "ip": "45.83.66.65", "seen": true, "first_seen": "2021-06-01", "last_seen": "2022-06-15",
+        "classification": "malicious", "actor": "unknown", "spoofable": false, "bot": true, "vpn": false,
+        "vpn_service": "N/A", "tags": ["Mirai", "SSH Bruteforcer"], "cve": ["CVE-2016-10401"],
+        "metadata": {
+          "asn": "AS208843", "city": "Amsterdam", "country": "Netherlands", "country_code": "NL",
+          "organization": "Alpha Strike Labs GmbH", "category": "hosting", "tor": false,
+          "rdns": "", "os": "Linux 3.11+", "region": "North Holland"
+        },
+        "raw_data": {
+          "scan": [{ "port": 22, "protocol": "TCP" }, { "port": 23, "protocol": "TCP" }],
+          "web": {}, "ja3": [], "hassh": []
+        }
+      }))
+      .tag(json!({
+        "id": "8d8a5b3c-8bd1-4c8c-9c5f-8e1a1a1b3c4d", "label": "MIRAI", "slug": "mirai", "name": "Mirai",
+        "category": "worm", "intention": "malicious", "description": "This IP address exhibits behavior that indicates it is infected with Mirai or a Mirai-like variant of malware.",
+        "references": ["https://en.wikipedia.org/wiki/Mirai_(malware)"], "recommend_block": true,
+        "cves": ["CVE-2016-10401"], "created_at": "2020-04-07"
+      }))
+      .tag(json!({
+        "id": "4a2b8d6e-9e2c-4b0a-8f3d-2c1e5d7f9a0b", "label": "SHODAN_CRAWLER", "slug": "shodan-io", "name": "Shodan.io",
+        "category": "actor", "intention": "benign", "description": "This IP address belongs to Shodan, an internet-wide scanning service.",
+        "references": ["https://www.shodan.io"], "recommend_block": false, "cves": [], "created_at": "2020-04-07"
+      }))
+  }
+
+  /// Read fixtures from a JSON file with `ping`, `community`, `riot`, `context` and
+  /// `metadata` members (all optional)
+  ///
+  /// # Errors
+  /// Returns any error reading or parsing the file.
+  pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Fixtures> {
+    let fixtures: Fixtures = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    Ok(Fixtures { ping: if fixtures.ping.is_null() { Fixtures::new().ping } else { fixtures.ping }, ..fixtures })
+  }
+
+  /// Add a Community response
+  pub fn community(mut self, ip: &str, response: Value) -> Fixtures {
+    self.community.insert(ip.to_string(), response);
+    self
+  }
+
+  /// Add a RIOT response
+  pub fn riot(mut self, ip: &str, response: Value) -> Fixtures {
+    self.riot.insert(ip.to_string(), response);
+    self
+  }
+
+  /// Add an IP Context response
+  pub fn context(mut self, ip: &str, response: Value) -> Fixtures {
+    self.context.insert(ip.to_string(), response);
+    self
+  }
+
+  /// Add a tag metadata entry
+  pub fn tag(mut self, metadatum: Value) -> Fixtures {
+    self.metadata.push(metadatum);
+    self
+  }
+
+}
+
+/// A scripted error
+#[derive(Debug, Clone)]
+struct Fault {
+  route: Option<Route>,
+  status: u16,
+  remaining: usize,
+}
+
+#[derive(Debug, Default)]
+struct Script {
+  faults: Vec<Fault>,
+  latency: Duration,
+  limiter: Option<RateLimiter>,
+  key: Option<String>,
+  requests: Vec<(Route, String)>,
+}
+
+struct MockState {
+  fixtures: Fixtures,
+  script: Mutex<Script>,
+}
+
+/// A running mock API. The server stops when this is dropped.
+///
+/// # Example
+/// ```rust,no_run
+/// use greynoise::client::Client;
+/// use greynoise::mock::{Fixtures, MockServer, Route};
+///
+/// let mock = MockServer::start(Fixtures::sample()).unwrap();
+/// mock.fail_next(Some(Route::Quick), 503, 1);
+///
+/// let client = Client::new(Some("any key")).with_base_url(&mock.url());
+/// async {
+///  assert!(client.quick_check("71.6.233.151").await.is_err());
+///  assert!(client.quick_check("71.6.233.151").await.unwrap().noise);
+/// };
+///```
+pub struct MockServer {
+  addr: std::net::SocketAddr,
+  state: Arc<MockState>,
+  shutdown: Option<tokio::sync::oneshot::Sender<()>>,
+}
+
+impl MockServer {
+
+  /// Serve `fixtures` on a free port on `127.0.0.1`
+  ///
+  /// # Errors
+  /// Returns any error binding the port or starting the server thread.
+  pub fn start(fixtures: Fixtures) -> io::Result<MockServer> {
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+
+    let state = Arc::new(MockState { fixtures, script: Mutex::new(Script::default()) });
+    let app = Router::new().fallback(handle).with_state(Arc::clone(&state));
+    let (shutdown, stopped) = tokio::sync::oneshot::channel::<()>();
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    std::thread::Builder::new().name(String::from("greynoise-mock")).spawn(move || {
+      runtime.block_on(async move {
+        if let Ok(listener) = tokio::net::TcpListener::from_std(listener) {
+          let _ = axum::serve(listener, app).with_graceful_shutdown(async { let _ = stopped.await; }).await;
+        }
+      });
+    })?;
+
+    Ok(MockServer { addr, state, shutdown: Some(shutdown) })
+
+  }
+
+  /// Base URL to point clients at, e.g. `http://127.0.0.1:49152`
+  pub fn url(&self) -> String {
+    format!("http://{}", self.addr)
+  }
+
+  /// Answer the next `times` requests to `route` (or to any route, if `None`) with
+  /// `status` and a JSON error body
+  pub fn fail_next(&self, route: Option<Route>, status: u16, times: usize) {
+    self.state.script.lock().unwrap().faults.push(Fault { route, status, remaining: times });
+  }
+
+  /// Delay every response by `latency`
+  pub fn latency(&self, latency: Duration) {
+    self.state.script.lock().unwrap().latency = latency;
+  }
+
+  /// Answer `429` once more than `burst` requests arrive faster than `per_second`
+  ///
+  /// # Errors
+  /// Returns an `InvalidInput` error if `per_second` is not a positive, finite number.
+  pub fn rate_limit(&self, per_second: f64, burst: u32) -> io::Result<()> {
+    self.state.script.lock().unwrap().limiter = Some(RateLimiter::new(per_second, burst)?);
+    Ok(())
+  }
+
+  /// Require the `key` header to equal `key` (except on Community, which works without a
+  /// key), answering `401` otherwise. `None` accepts any key.
+  pub fn require_key(&self, key: Option<&str>) {
+    self.state.script.lock().unwrap().key = key.map(String::from);
+  }
+
+  /// Clear scripted errors, latency, rate limits and the key requirement
+  pub fn reset(&self) {
+    let mut script = self.state.script.lock().unwrap();
+    let requests = std::mem::take(&mut script.requests);
+    *script = Script { requests, ..Script::default() };
+  }
+
+  /// Requests received so far, as the route and path with query
+  pub fn requests(&self) -> Vec<(Route, String)> {
+    self.state.script.lock().unwrap().requests.clone()
+  }
+
+}
+
+impl Drop for MockServer {
+  fn drop(&mut self) {
+    if let Some(shutdown) = self.shutdown.take() {
+      let _ = shutdown.send(());
+    }
+  }
+}
+
+fn respond(status: u16, body: Value) -> Response {
+  let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+  (status, [(header::CONTENT_TYPE, "application/json")], body.to_string()).into_response()
+}
+
+fn message(status: u16, text: &str) -> Response {
+  respond(status, json!({ "message": text }))
+}
+
+fn route_for(method: &Method, path: &str) -> Option<(Route, Option<String>)> {
+
+  let ip_route = |prefix: &str, route: Route| {
+    path.strip_prefix(prefix).filter(|ip| !ip.is_empty() && !ip.contains('/')).map(|ip| (route, Some(ip.to_string())))
+  };
+
+  match (method, path) {
+    (&Method::GET, "/ping") => Some((Route::Ping, None)),
+    (&Method::GET, "/v2/meta/metadata") => Some((Route::Metadata, None)),
+    (&Method::GET, "/v2/experimental/gnql") => Some((Route::Gnql, None)),
+    (&Method::POST, "/v2/noise/multi/quick") => Some((Route::MultiQuick, None)),
+    (&Method::GET, _) => ip_route("/v3/community/", Route::Community)
+      .or_else(|| ip_route("/v2/riot/", Route::Riot))
+      .or_else(|| ip_route("/v2/noise/context/", Route::Context))
+      .or_else(|| ip_route("/v2/noise/quick/", Route::Quick))
+      .or_else(|| ip_route("/v3/tags/", Route::Tag))
+      .or_else(|| {
+        let tag = path.strip_prefix("/v3/tags/")?.strip_suffix("/activity")?;
+        (!tag.is_empty() && !tag.contains('/')).then(|| (Route::TagActivity, Some(tag.to_string())))
+      }),
+    _ => None,
+  }
+
+}
+
+/// Does `record` satisfy every `field:value` term of a GNQL query?
+fn gnql_matches(record: &Value, query: &str) -> bool {
+
+  query.split_whitespace().all(|term| {
+
+    let (negate, term) = match term.strip_prefix('-') {
+      Some(t) => (true, t),
+      None => (false, term),
+    };
+
+    let (field, value) = match term.split_once(':') {
+      Some((f, v)) => (f, v.trim_matches('"')),
+      None => return true,
+    };
+
+    if matches!(field, "first_seen" | "last_seen") {
+      return true;
+    }
+
+    let found = select(record, field).iter().any(|v| match v {
+      Value::String(s) => s.eq_ignore_ascii_case(value),
+      other => other.to_string().eq_ignore_ascii_case(value),
+    });
+
+    found != negate
+
+  })
+
+}
+
+impl MockState {
+
+  fn quick(&self, ip: &str) -> Value {
+    let noise = self.fixtures.context.get(ip).and_then(|c| c["seen"].as_bool()).unwrap_or(false);
+    let riot = self.fixtures.riot.contains_key(ip);
+    json!({ "ip": ip, "noise": noise, "riot": riot, "code": if noise { "0x01" } else { "0x00" } })
+  }
+
+  fn community(&self, ip: &str) -> Option<Value> {
+
+    if let Some(c) = self.fixtures.community.get(ip) {
+      return Some(c.clone());
+    }
+
+    let link = format!("https://viz.greynoise.io/ip/{}", ip);
+
+    if let Some(r) = self.fixtures.riot.get(ip) {
+      return Some(json!({
+        "ip": ip, "noise": false, "riot": true, "classification": "benign", "name": r["name"],
+        "link": link, "last_seen": r["last_updated"].as_str().unwrap_or_default().get(..10).unwrap_or_default(), "message": "Success"
+      }));
+    }
+
+    self.fixtures.context.get(ip).filter(|c| c["seen"] == true).map(|c| json!({
+      "ip": ip, "noise": true, "riot": false, "classification": c["classification"],
+      "name": c["actor"], "link": link, "last_seen": c["last_seen"], "message": "Success"
+    }))
+
+  }
+
+  fn gnql(&self, params: &HashMap<String, String>) -> Value {
+
+    let query = params.get("query").cloned().unwrap_or_default();
+    let size: usize = params.get("size").and_then(|s| s.parse().ok()).unwrap_or(10).max(1);
+    let offset: usize = params.get("scroll").and_then(|s| s.parse().ok()).unwrap_or(0);
+
+    let mut ips: Vec<&String> = self.fixtures.context.keys().collect();
+    ips.sort();
+
+    let matched: Vec<Value> = ips.into_iter()
+      .map(|ip| &self.fixtures.context[ip])
+      .filter(|c| gnql_matches(c, &query))
+      .cloned()
+      .collect();
+
+    let end = (offset + size).min(matched.len());
+    let data: Vec<Value> = matched.get(offset.min(end)..end).unwrap_or_default().to_vec();
+    let complete = end >= matched.len();
+
+    json!({
+      "complete": complete,
+      "count": matched.len(),
+      "data": data,
+      "message": if matched.is_empty() { "no results" } else { "ok" },
+      "query": query,
+      "scroll": if complete { Value::Null } else { Value::from(end.to_string()) },
+    })
+
+  }
+
+  /// The tag fixture whose ID or slug is `tag`
+  fn tag(&self, tag: &str) -> Option<&Value> {
+    self.fixtures.metadata.iter().find(|t| {
+      t["id"].as_str().is_some_and(|id| id.eq_ignore_ascii_case(tag)) || t["slug"].as_str().is_some_and(|slug| slug.eq_ignore_ascii_case(tag))
+    })
+  }
+
+  /// One bucket per day for the last `days` days (1 by default), each counting the IP
+  /// Context fixtures carrying the tag
+  fn tag_activity(&self, tag: &Value, params: &HashMap<String, String>) -> Value {
+
+    let days: i64 = params.get("days").and_then(|d| d.parse().ok()).unwrap_or(1).clamp(1, 90);
+    let name = tag["name"].as_str().unwrap_or_default();
+    let count = self.fixtures.context.values()
+      .filter(|c| c["tags"].as_array().is_some_and(|tags| tags.iter().any(|t| t.as_str() == Some(name))))
+      .count();
+
+    let today = chrono::Utc::now().date_naive();
+    let results: Vec<Value> = (0..days).rev()
+      .map(|n| json!({ "timestamp": format!("{}T00:00:00Z", today - chrono::Duration::days(n)), "data": count }))
+      .collect();
+
+    json!({
+      "metadata": {
+        "id": tag["id"], "slug": tag["slug"], "granularity": params.get("granularity").map(String::as_str).unwrap_or("1d"),
+        "metric": "unique_ips",
+      },
+      "results": results,
+    })
+
+  }
+
+  fn answer(&self, route: Route, ip: Option<String>, uri: &Uri, body: &[u8]) -> Response {
+
+    if let Some(ip) = ip.as_ref().filter(|_| !matches!(route, Route::Tag | Route::TagActivity)) {
+      if ip.parse::<IpAddr>().is_err() {
+        return message(400, "Request is not a valid routable IPv4 address");
+      }
+    }
+
+    let ip = ip.unwrap_or_default();
+
+    match route {
+      Route::Ping => respond(200, self.fixtures.ping.clone()),
+      Route::Metadata => respond(200, json!({ "metadata": self.fixtures.metadata, "vpn_services": [] })),
+      Route::Quick => respond(200, self.quick(&ip)),
+      Route::Community => match self.community(&ip) {
+        Some(c) => respond(200, c),
+        None => respond(404, json!({
+          "ip": ip, "noise": false, "riot": false,
+          "message": "IP not observed scanning the internet or contained in RIOT data set."
+        })),
+      },
+      Route::Riot => match self.fixtures.riot.get(&ip) {
+        Some(r) => respond(200, r.clone()),
+        None => message(404, "IP not found"),
+      },
+      Route::Context => match self.fixtures.context.get(&ip) {
+        Some(c) => respond(200, c.clone()),
+        None => respond(200, json!({ "ip": ip, "seen": false })),
+      },
+      Route::MultiQuick => {
+        let ips: Vec<String> = serde_json::from_slice::<Value>(body).ok()
+          .and_then(|v| serde_json::from_value(v["ips"].clone()).ok())
+          .unwrap_or_default();
+        let results: Vec<Value> = ips.iter().filter(|ip| ip.parse::<IpAddr>().is_ok()).map(|ip| self.quick(ip)).collect();
+        respond(200, Value::from(results))
+      }
+      Route::Tag => match self.tag(&ip) {
+        Some(t) => respond(200, t.clone()),
+        None => message(404, "Tag not found"),
+      },
+      Route::TagActivity => match self.tag(&ip) {
+        Some(t) => respond(200, self.tag_activity(t, &query_params(uri))),
+        None => message(404, "Tag not found"),
+      },
+      Route::Gnql => {
+        respond(200, self.gnql(&query_params(uri)))
+      }
+    }
+
+  }
+
+}
+
+fn query_params(uri: &Uri) -> HashMap<String, String> {
+  url::Url::parse(&format!("http://mock{}", uri))
+    .map(|u| u.query_pairs().into_owned().collect())
+    .unwrap_or_default()
+}
+
+async fn handle(State(state): State<Arc<MockState>>, method: Method, uri: Uri, headers: HeaderMap, body: Bytes) -> Response {
+
+  let (route, ip) = match route_for(&method, uri.path()) {
+    Some(r) => r,
+    None => return message(404, "not a GreyNoise API endpoint"),
+  };
+
+  let (latency, scripted) = {
+
+    let mut script = state.script.lock().unwrap();
+    script.requests.push((route, uri.path_and_query().map(|p| p.to_string()).unwrap_or_default()));
+
+    let given = headers.get("key").and_then(|k| k.to_str().ok());
+    let unauthorized = match (&script.key, route) {
+      (Some(key), r) if r != Route::Community => given != Some(key.as_str()),
+      (Some(key), _) => given.is_some() && given != Some(key.as_str()),
+      (None, _) => false,
+    };
+
+    let limited = script.limiter.as_ref().map(|l| l.check("mock").is_err()).unwrap_or(false);
+
+    let fault = script.faults.iter_mut()
+      .find(|f| f.remaining > 0 && f.route.map(|r| r == route).unwrap_or(true))
+      .map(|f| {
+        f.remaining -= 1;
+        f.status
+      });
+    script.faults.retain(|f| f.remaining > 0);
+
+    let scripted = if unauthorized {
+      Some(message(401, "Authentication required"))
+    } else if limited {
+      let mut res = message(429, "Rate limit exceeded");
+      res.headers_mut().insert(header::RETRY_AFTER, header::HeaderValue::from_static("1"));
+      Some(res)
+    } else {
+      fault.map(|status| message(status, "Scripted mock error"))
+    };
+
+    (script.latency, scripted)
+
+  };
+
+  if !latency.is_zero() {
+    tokio::time::sleep(latency).await;
+  }
+
+  match scripted {
+    Some(res) => res,
+    None => state.answer(route, ip, &uri, &body),
+  }
+
+}
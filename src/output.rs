@@ -0,0 +1,197 @@
+//! Render GreyNoise results as tables, JSON, NDJSON, CSV/TSV or Markdown
+//!
+//! Any of the response structs (`Community`, `RIOT`, `IPContext`, `QuickCheck`,
+//! `gnql::Datum`, `metadata::Metadatum`, …) can be rendered. The row-oriented formats
+//! (table, CSV, TSV, Markdown) flatten nested fields into dotted column names such as
+//! `metadata.asn`; arrays are joined with `;`, and arrays of objects become one column per
+//! field (`raw_data.scan.port`). Columns appear in struct field order. To pick columns or
+//! change how arrays are handled, flatten with a [`crate::fields::Flattener`] and pass the
+//! result to [`write_rows`].
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+pub use crate::fields::Rows;
+use crate::fields::Flattener;
+
+/// Output format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+  /// Aligned plain-text table
+  Table,
+
+  /// Pretty-printed JSON array
+  Json,
+
+  /// One compact JSON object per line
+  Ndjson,
+
+  /// Comma-separated values with a header row
+  Csv,
+
+  /// Tab-separated values with a header row
+  Tsv,
+
+  /// Markdown table
+  Markdown,
+}
+
+impl FromStr for Format {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Format, String> {
+    match s.to_ascii_lowercase().as_str() {
+      "table" => Ok(Format::Table),
+      "json" => Ok(Format::Json),
+      "ndjson" | "jsonl" => Ok(Format::Ndjson),
+      "csv" => Ok(Format::Csv),
+      "tsv" => Ok(Format::Tsv),
+      "markdown" | "md" => Ok(Format::Markdown),
+      other => Err(format!("unknown format '{}' (expected table, json, ndjson, csv, tsv or markdown)", other)),
+    }
+  }
+}
+
+impl fmt::Display for Format {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(match self {
+      Format::Table => "table",
+      Format::Json => "json",
+      Format::Ndjson => "ndjson",
+      Format::Csv => "csv",
+      Format::Tsv => "tsv",
+      Format::Markdown => "markdown",
+    })
+  }
+}
+
+/// Flatten `records` into rows sharing one header (the union of their columns)
+pub fn to_rows<T: Serialize>(records: &[T]) -> Rows {
+  Flattener::new().rows(records)
+}
+
+fn write_delimited<W: Write>(w: W, rows: &Rows, delimiter: u8) -> io::Result<()> {
+
+  let mut writer = csv::WriterBuilder::new().delimiter(delimiter).from_writer(w);
+
+  writer.write_record(&rows.header)?;
+  for row in &rows.rows {
+    writer.write_record(row)?;
+  }
+
+  writer.flush()
+
+}
+
+fn write_table<W: Write>(mut w: W, rows: &Rows) -> io::Result<()> {
+
+  let widths: Vec<usize> = rows.header.iter().enumerate()
+    .map(|(i, h)| rows.rows.iter().map(|r| r[i].chars().count()).chain([h.chars().count()]).max().unwrap_or(0))
+    .collect();
+
+  let line = |cells: &[String]| -> String {
+    cells.iter().zip(&widths)
+      .map(|(c, width)| format!("{:<width$}", c, width = *width))
+      .collect::<Vec<_>>()
+      .join("  ")
+      .trim_end()
+      .to_string()
+  };
+
+  writeln!(w, "{}", line(&rows.header))?;
+  writeln!(w, "{}", line(&widths.iter().map(|n| "-".repeat(*n)).collect::<Vec<_>>()))?;
+  for row in &rows.rows {
+    writeln!(w, "{}", line(row))?;
+  }
+
+  Ok(())
+
+}
+
+fn write_markdown<W: Write>(mut w: W, rows: &Rows) -> io::Result<()> {
+
+  let line = |cells: &[String]| -> String {
+    let escaped: Vec<String> = cells.iter().map(|c| c.replace('|', "\\|").replace('\n', " ")).collect();
+    format!("| {} |", escaped.join(" | "))
+  };
+
+  writeln!(w, "{}", line(&rows.header))?;
+  writeln!(w, "|{}", "---|".repeat(rows.header.len()))?;
+  for row in &rows.rows {
+    writeln!(w, "{}", line(row))?;
+  }
+
+  Ok(())
+
+}
+
+/// Write `records` to `w` in `format`
+///
+/// # Errors
+/// Returns any error from writing to `w` or serializing the records.
+pub fn write_records<W: Write, T: Serialize>(mut w: W, records: &[T], format: Format) -> io::Result<()> {
+  match format {
+    Format::Json => {
+      serde_json::to_writer_pretty(&mut w, records)?;
+      writeln!(w)
+    }
+    Format::Ndjson => {
+      for record in records {
+        serde_json::to_writer(&mut w, record)?;
+        writeln!(w)?;
+      }
+      Ok(())
+    }
+    _ => write_rows(w, &to_rows(records), format),
+  }
+}
+
+/// Write already flattened `rows` to `w` in `format`. The JSON formats emit one object
+/// per row, keyed by column.
+///
+/// # Errors
+/// Returns any error from writing to `w`.
+pub fn write_rows<W: Write>(mut w: W, rows: &Rows, format: Format) -> io::Result<()> {
+
+  let objects = || rows.rows.iter().map(|row| {
+    rows.header.iter().cloned().zip(row.iter().cloned().map(Value::String)).collect::<Map<String, Value>>()
+  });
+
+  match format {
+    Format::Json => {
+      serde_json::to_writer_pretty(&mut w, &objects().collect::<Vec<_>>())?;
+      writeln!(w)
+    }
+    Format::Ndjson => {
+      for object in objects() {
+        serde_json::to_writer(&mut w, &object)?;
+        writeln!(w)?;
+      }
+      Ok(())
+    }
+    Format::Csv => write_delimited(w, rows, b','),
+    Format::Tsv => write_delimited(w, rows, b'\t'),
+    Format::Table => write_table(w, rows),
+    Format::Markdown => write_markdown(w, rows),
+  }
+
+}
+
+/// Render `records` in `format` as a `String`
+///
+/// # Example
+/// ```rust
+/// use greynoise::output::{render, Format};
+/// use greynoise::quick_check::QuickCheck;
+///
+/// let qc = QuickCheck { ip: String::from("8.8.8.8"), noise: false, riot: true, code: String::from("0x00") };
+/// assert_eq!(render(&[qc], Format::Csv), "ip,noise,riot,code\n8.8.8.8,false,true,0x00\n");
+///```
+pub fn render<T: Serialize>(records: &[T], format: Format) -> String {
+  let mut buf = Vec::new();
+  let _ = write_records(&mut buf, records, format);
+  String::from_utf8_lossy(&buf).into_owned()
+}
@@ -1,31 +1,233 @@
 //! Check GreyNoise API Status
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+
 use crate::get;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Structure to deserialize the GreyNoise Ping response: the state of the API key's account.
+///
+/// Any values inside `Option` are optional, and you should check if there is `Some` or `None` before using it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Ping {
-  #[serde(rename = "expiration")]
-  expiration: Option<String>,
-  
+  /// Day the API key's subscription expires
+  #[serde(rename = "expiration", default, with = "date")]
+  pub expiration: Option<NaiveDate>,
+
   #[serde(rename = "message")]
-  message: Option<String>,
-  
+  pub message: Option<String>,
+
+  /// Plan the API key belongs to
   #[serde(rename = "offering")]
-  offering: Option<String>,
+  pub offering: Option<Offering>,
+}
+
+/// GreyNoise plans
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Offering {
+  Community,
+  CommunityTrial,
+  Enterprise,
+  EnterpriseTrial,
+  Vip,
+
+  /// A plan this version of the crate does not know about
+  Other(String),
+}
+
+/// GreyNoise API endpoints, as far as plans are concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiEndpoint {
+  Ping,
+  Community,
+  Riot,
+  IpContext,
+  QuickCheck,
+  MultiQuickCheck,
+  Gnql,
+  Metadata,
+  Tags,
+  Timeline,
+  Similarity,
+}
+
+impl ApiEndpoint {
+
+  /// Every endpoint the crate wraps
+  pub const ALL: [ApiEndpoint; 11] = [
+    ApiEndpoint::Ping,
+    ApiEndpoint::Community,
+    ApiEndpoint::Riot,
+    ApiEndpoint::IpContext,
+    ApiEndpoint::QuickCheck,
+    ApiEndpoint::MultiQuickCheck,
+    ApiEndpoint::Gnql,
+    ApiEndpoint::Metadata,
+    ApiEndpoint::Tags,
+    ApiEndpoint::Timeline,
+    ApiEndpoint::Similarity,
+  ];
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ApiEndpoint::Ping => "ping",
+      ApiEndpoint::Community => "community",
+      ApiEndpoint::Riot => "riot",
+      ApiEndpoint::IpContext => "ip_context",
+      ApiEndpoint::QuickCheck => "quick_check",
+      ApiEndpoint::MultiQuickCheck => "multi_quick_check",
+      ApiEndpoint::Gnql => "gnql",
+      ApiEndpoint::Metadata => "metadata",
+      ApiEndpoint::Tags => "tags",
+      ApiEndpoint::Timeline => "timeline",
+      ApiEndpoint::Similarity => "similarity",
+    }
+  }
+
+}
+
+impl fmt::Display for ApiEndpoint {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl Offering {
+
+  pub fn as_str(&self) -> &str {
+    match self {
+      Offering::Community => "community",
+      Offering::CommunityTrial => "community_trial",
+      Offering::Enterprise => "enterprise",
+      Offering::EnterpriseTrial => "enterprise_trial",
+      Offering::Vip => "vip",
+      Offering::Other(s) => s,
+    }
+  }
+
+  /// Community plans only reach the Community API (and ping)
+  pub fn is_community(&self) -> bool {
+    matches!(self, Offering::Community | Offering::CommunityTrial)
+  }
+
+  /// Whether the plan can use `endpoint`. Plans the crate does not know are assumed to
+  /// reach everything, leaving the API to refuse what they cannot.
+  pub fn allows(&self, endpoint: ApiEndpoint) -> bool {
+    !self.is_community() || matches!(endpoint, ApiEndpoint::Ping | ApiEndpoint::Community)
+  }
+
+  /// Endpoints the plan can use
+  pub fn endpoints(&self) -> Vec<ApiEndpoint> {
+    ApiEndpoint::ALL.iter().copied().filter(|e| self.allows(*e)).collect()
+  }
+
+}
+
+impl FromStr for Offering {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Offering, String> {
+    Ok(match s.trim().to_ascii_lowercase().replace(['-', ' '], "_").as_str() {
+      "community" => Offering::Community,
+      "community_trial" => Offering::CommunityTrial,
+      "enterprise" => Offering::Enterprise,
+      "enterprise_trial" => Offering::EnterpriseTrial,
+      "vip" => Offering::Vip,
+      _ => Offering::Other(s.to_string()),
+    })
+  }
+}
+
+impl From<String> for Offering {
+  fn from(s: String) -> Offering {
+    s.parse().unwrap_or(Offering::Other(s))
+  }
+}
+
+impl From<Offering> for String {
+  fn from(o: Offering) -> String {
+    o.as_str().to_string()
+  }
+}
+
+impl fmt::Display for Offering {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl Ping {
+
+  /// Days from `today` until the key expires (negative once it has), if the API reported
+  /// an expiration date
+  pub fn days_until_expiry_from(&self, today: NaiveDate) -> Option<i64> {
+    self.expiration.map(|e| (e - today).num_days())
+  }
+
+  /// Days from today (UTC) until the key expires (negative once it has)
+  pub fn days_until_expiry(&self) -> Option<i64> {
+    self.days_until_expiry_from(chrono::Utc::now().date_naive())
+  }
+
+  /// The plan, if the API reported one
+  pub fn plan(&self) -> Option<Offering> {
+    self.offering.clone()
+  }
+
+  /// Endpoints the key's plan can use. When the plan is not reported, every endpoint is
+  /// assumed reachable, leaving the API to refuse what it cannot.
+  pub fn allowed_endpoints(&self) -> Vec<ApiEndpoint> {
+    match &self.offering {
+      Some(offering) => offering.endpoints(),
+      None => ApiEndpoint::ALL.to_vec(),
+    }
+  }
+
+}
+
+/// (De)serialize the `YYYY-MM-DD` expiration date, tolerating a trailing time and treating
+/// an empty string as no date
+mod date {
+  use chrono::NaiveDate;
+  use serde::{Deserialize, Deserializer, Serializer};
+
+  pub fn serialize<S: Serializer>(date: &Option<NaiveDate>, s: S) -> Result<S::Ok, S::Error> {
+    match date {
+      Some(d) => s.serialize_str(&d.format("%Y-%m-%d").to_string()),
+      None => s.serialize_none(),
+    }
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<NaiveDate>, D::Error> {
+
+    let raw: Option<String> = Option::deserialize(d)?;
+
+    match raw.as_deref().map(str::trim).filter(|r| !r.is_empty()) {
+      None => Ok(None),
+      Some(r) => NaiveDate::parse_from_str(r.get(..10).unwrap_or(r), "%Y-%m-%d")
+        .map(Some)
+        .map_err(serde::de::Error::custom),
+    }
+
+  }
 }
 
 #[doc(hidden)]
-const PING_URL: &str = "https://api.greynoise.io/ping";
+pub(crate) const PING_URL: &str = "https://api.greynoise.io/ping";
 
 /// Function to test if the GreyNoise API is alive
 ///
 /// Provides a simple endpoint to check GreyNoise status and GreyNoise API access
-/// 
+///
 /// For more information on the Ping API endpoint check the [API docs](https://docs.greynoise.io/reference/get_ping).
 ///
 /// # Example
 /// ```rust
 /// use greynoise::ping;
-/// 
+///
 /// async {
 ///  let res: bool = ping::ping(Some(greynoise::gn::api_key(None).as_ref())).await;
 ///   assert!(res);
@@ -35,15 +237,37 @@ const PING_URL: &str = "https://api.greynoise.io/ping";
 /// # Errors
 /// If the call fails, it will return `false`.
 /// To see the possible return values, check the [API docs](https://docs.greynoise.io/reference/get_ping).
-
 pub async fn ping(key: Option<&str>) -> bool {
-  
-  let url = format!("{}", PING_URL);
-  let res: Result<Ping, reqwest::StatusCode> = get::query(url, key).await;
-  
-  match res {
-    Ok(_) => { return true }
-    Err(_) => { return false }
-  }
-  
+
+  ping_details(key).await.is_ok()
+
+}
+
+/// Function to retrieve the API key's plan and expiration
+///
+/// Same endpoint as [`ping`], returning what it says about the key's account.
+///
+/// # Example
+/// ```rust
+/// use greynoise::ping;
+///
+/// async {
+///  if let Ok(details) = ping::ping_details(Some(greynoise::gn::api_key(None).as_ref())).await {
+///    if details.days_until_expiry().is_some_and(|d| d < 30) {
+///      eprintln!("GreyNoise {:?} key expires soon", details.plan());
+///    }
+///  }
+/// };
+///```
+///
+/// # Errors
+/// If the call fails, it will return a `Err(StatusCode)`.
+/// To see the possible return values, check the [API docs](https://docs.greynoise.io/reference/get_ping).
+pub async fn ping_details(key: Option<&str>) -> Result<Ping, http::StatusCode> {
+
+  let url = PING_URL.to_string();
+  let res: Result<Ping, http::StatusCode> = get::query(url, key).await;
+
+  res
+
 }
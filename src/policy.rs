@@ -0,0 +1,462 @@
+//! Decide what to do about an IP with declarative rules
+//!
+//! A [`Policy`] is an ordered list of named [`Rule`]s read from TOML (with the `toml`
+//! feature, which the default `cli` feature enables) or YAML (with the `yaml` feature), or
+//! built in code. Each rule has an [`Action`] and the [`Conditions`] under which it
+//! applies, all of which must hold. Rules are evaluated against a [`Verdict`] (the Quick
+//! Check, RIOT and IP Context responses) and tag metadata: the first rule that matches
+//! decides, and every rule that matched is listed in the [`Decision`] so the outcome can be
+//! audited. When no rule matches, the policy's `default` action (`ignore` unless set)
+//! applies.
+//!
+//! ```toml
+//! default = "alert"
+//! inventory_cves = ["CVE-2016-10401", "CVE-2021-44228"]
+//!
+//! [[rules]]
+//! name = "trusted service"
+//! action = "ignore"
+//! when = { riot_trust_level = "1" }
+//!
+//! [[rules]]
+//! name = "malicious, block recommended"
+//! action = "block"
+//! when = { classification = "malicious", recommend_block = true }
+//!
+//! [[rules]]
+//! name = "targets our stack"
+//! action = "alert"
+//! when = { inventory_cve = true }
+//!
+//! [[rules]]
+//! name = "everything else"
+//! action = "ignore"
+//! ```
+//!
+//! String conditions take one value or a list and match case-insensitively if any value
+//! matches. Boolean conditions on IP Context fields treat a missing response as `false`.
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+use crate::metadata::{self, Metadatum};
+use crate::verdict::{self, Disposition, Verdict};
+
+/// What to do about an IP
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+  #[default]
+  Ignore,
+  Alert,
+  Block,
+}
+
+impl Action {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Action::Ignore => "ignore",
+      Action::Alert => "alert",
+      Action::Block => "block",
+    }
+  }
+}
+
+impl FromStr for Action {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Action, String> {
+    match s.to_ascii_lowercase().as_str() {
+      "ignore" => Ok(Action::Ignore),
+      "alert" => Ok(Action::Alert),
+      "block" => Ok(Action::Block),
+      _ => Err(format!("unknown action '{}' (expected ignore, alert or block)", s)),
+    }
+  }
+}
+
+impl fmt::Display for Action {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// One string or a list of strings in a rule, matched case-insensitively
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(from = "OneOrMany")]
+pub struct Values(pub Vec<String>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+  One(String),
+  Many(Vec<String>),
+}
+
+impl From<OneOrMany> for Values {
+  fn from(v: OneOrMany) -> Values {
+    match v {
+      OneOrMany::One(s) => Values(vec![s]),
+      OneOrMany::Many(v) => Values(v),
+    }
+  }
+}
+
+impl Values {
+
+  /// Whether `value` is one of the values
+  pub fn contains(&self, value: &str) -> bool {
+    self.0.iter().any(|v| v.eq_ignore_ascii_case(value))
+  }
+
+  /// Whether any of `values` is one of the values
+  pub fn any<'a, I: IntoIterator<Item = &'a str>>(&self, values: I) -> bool {
+    values.into_iter().any(|v| self.contains(v))
+  }
+
+}
+
+/// What must hold for a rule to apply. Unset conditions are not checked, so a rule with no
+/// conditions matches every IP.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Conditions {
+  /// Verdict disposition, e.g. `malicious` or `not_seen`
+  #[serde(rename = "disposition")]
+  pub disposition: Option<Values>,
+
+  /// Quick Check says the IP is noise
+  #[serde(rename = "noise")]
+  pub noise: Option<bool>,
+
+  /// Quick Check says the IP is in RIOT
+  #[serde(rename = "riot")]
+  pub riot: Option<bool>,
+
+  #[serde(rename = "riot_trust_level")]
+  pub riot_trust_level: Option<Values>,
+
+  #[serde(rename = "riot_category")]
+  pub riot_category: Option<Values>,
+
+  /// IP Context classification
+  #[serde(rename = "classification")]
+  pub classification: Option<Values>,
+
+  #[serde(rename = "actor")]
+  pub actor: Option<Values>,
+
+  /// At least one of the IP's tags is listed
+  #[serde(rename = "tags")]
+  pub tags: Option<Values>,
+
+  /// At least one of the IP's tags recommends blocking (or none does, when `false`)
+  #[serde(rename = "recommend_block")]
+  pub recommend_block: Option<bool>,
+
+  /// At least one of the IP's CVEs (from IP Context and its tags' metadata) is listed
+  #[serde(rename = "cves")]
+  pub cves: Option<Values>,
+
+  /// At least one of the IP's CVEs is in the policy's `inventory_cves` (or none is, when `false`)
+  #[serde(rename = "inventory_cve")]
+  pub inventory_cve: Option<bool>,
+
+  #[serde(rename = "country_code")]
+  pub country_code: Option<Values>,
+
+  #[serde(rename = "spoofable")]
+  pub spoofable: Option<bool>,
+
+  #[serde(rename = "bot")]
+  pub bot: Option<bool>,
+
+  #[serde(rename = "vpn")]
+  pub vpn: Option<bool>,
+
+  #[serde(rename = "tor")]
+  pub tor: Option<bool>,
+}
+
+/// A named action and when it applies
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+  /// Reported in [`Decision::matched`]
+  #[serde(rename = "name")]
+  pub name: String,
+
+  #[serde(rename = "action")]
+  pub action: Action,
+
+  #[serde(rename = "when", default)]
+  pub when: Conditions,
+}
+
+/// An ordered set of rules
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+  /// Action when no rule matches
+  #[serde(rename = "default", default)]
+  pub default: Action,
+
+  /// CVEs that affect our assets, for the `inventory_cve` condition
+  #[serde(rename = "inventory_cves", default)]
+  pub inventory_cves: Vec<String>,
+
+  #[serde(rename = "rules", default)]
+  pub rules: Vec<Rule>,
+}
+
+/// What a policy decided for an IP, and why
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Decision {
+  #[serde(rename = "ip")]
+  pub ip: String,
+
+  #[serde(rename = "action")]
+  pub action: Action,
+
+  /// The rule that decided, `None` when the default action applied
+  #[serde(rename = "rule")]
+  pub rule: Option<String>,
+
+  /// Every rule that matched, in policy order
+  #[serde(rename = "matched")]
+  pub matched: Vec<String>,
+
+  #[serde(rename = "disposition")]
+  pub disposition: Disposition,
+}
+
+/// What the rules are checked against, gathered once per IP
+struct Facts<'a> {
+  tags: Vec<&'a str>,
+  tag_metadata: Vec<&'a Metadatum>,
+  cves: HashSet<String>,
+}
+
+impl<'a> Facts<'a> {
+
+  fn new(verdict: &'a Verdict, metadata: &'a [Metadatum]) -> Facts<'a> {
+
+    let tags: Vec<&str> = verdict.context.iter()
+      .flat_map(|c| c.tags.iter().flatten())
+      .map(String::as_str)
+      .collect();
+
+    let tag_metadata: Vec<&Metadatum> = metadata.iter()
+      .filter(|m| tags.iter().any(|t| t.eq_ignore_ascii_case(&m.name)))
+      .collect();
+
+    let cves = verdict.context.iter()
+      .flat_map(|c| c.cve.iter().flatten())
+      .chain(tag_metadata.iter().flat_map(|m| m.cves.iter()))
+      .map(|c| c.to_ascii_uppercase())
+      .collect();
+
+    Facts { tags, tag_metadata, cves }
+
+  }
+
+}
+
+/// `None` (condition unset) passes; otherwise the condition must equal `value`
+fn check(condition: Option<bool>, value: bool) -> bool {
+  condition.is_none_or(|c| c == value)
+}
+
+/// `None` (condition unset) passes; otherwise `value` must be present and listed
+fn check_str(condition: &Option<Values>, value: Option<&str>) -> bool {
+  match condition {
+    None => true,
+    Some(values) => value.is_some_and(|v| values.contains(v)),
+  }
+}
+
+impl Conditions {
+
+  fn matches(&self, verdict: &Verdict, facts: &Facts, inventory: &Values) -> bool {
+
+    let context = verdict.context.as_ref();
+    let riot = verdict.riot.as_ref();
+    let cves = facts.cves.iter().map(String::as_str);
+
+    check_str(&self.disposition, Some(verdict.disposition.as_str()))
+      && check(self.noise, verdict.quick_check.noise)
+      && check(self.riot, verdict.quick_check.riot)
+      && check_str(&self.riot_trust_level, riot.map(|r| r.trust_level.as_str()))
+      && check_str(&self.riot_category, riot.map(|r| r.category.as_str()))
+      && check_str(&self.classification, context.and_then(|c| c.classification.as_deref()))
+      && check_str(&self.actor, context.and_then(|c| c.actor.as_deref()))
+      && self.tags.as_ref().is_none_or(|t| t.any(facts.tags.iter().copied()))
+      && check(self.recommend_block, facts.tag_metadata.iter().any(|m| m.recommend_block))
+      && self.cves.as_ref().is_none_or(|c| c.any(cves.clone()))
+      && check(self.inventory_cve, inventory.any(cves))
+      && check_str(&self.country_code, context.and_then(|c| c.metadata.as_ref()).and_then(|m| m.country_code.as_deref()))
+      && check(self.spoofable, context.and_then(|c| c.spoofable).unwrap_or(false))
+      && check(self.bot, context.and_then(|c| c.bot).unwrap_or(false))
+      && check(self.vpn, context.and_then(|c| c.vpn).unwrap_or(false))
+      && check(self.tor, context.and_then(|c| c.metadata.as_ref()).and_then(|m| m.tor).unwrap_or(false))
+
+  }
+
+  /// Whether checking these conditions needs tag metadata
+  fn uses_tag_metadata(&self) -> bool {
+    self.recommend_block.is_some() || self.cves.is_some() || self.inventory_cve.is_some()
+  }
+
+}
+
+#[cfg(any(feature = "toml", feature = "yaml"))]
+fn invalid(e: impl fmt::Display) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+#[cfg(feature = "toml")]
+fn parse_toml(s: &str) -> io::Result<Policy> {
+  toml::from_str::<Policy>(s).map_err(invalid)?.validated()
+}
+
+#[cfg(not(feature = "toml"))]
+fn parse_toml(_: &str) -> io::Result<Policy> {
+  Err(io::Error::new(io::ErrorKind::Unsupported, "TOML policies need the `toml` feature"))
+}
+
+#[cfg(feature = "yaml")]
+fn parse_yaml(s: &str) -> io::Result<Policy> {
+  serde_yaml::from_str::<Policy>(s).map_err(invalid)?.validated()
+}
+
+#[cfg(not(feature = "yaml"))]
+fn parse_yaml(_: &str) -> io::Result<Policy> {
+  Err(io::Error::new(io::ErrorKind::Unsupported, "YAML policies need the `yaml` feature"))
+}
+
+impl Policy {
+
+  /// Parse a TOML policy
+  ///
+  /// # Errors
+  /// Returns an `io::Error` if the policy is not valid TOML, uses an unknown field or
+  /// action, or has a rule without a name.
+  #[cfg(feature = "toml")]
+  pub fn from_toml(s: &str) -> io::Result<Policy> {
+    parse_toml(s)
+  }
+
+  /// Parse a YAML policy
+  ///
+  /// # Errors
+  /// Returns an `io::Error` if the policy is not valid YAML, uses an unknown field or
+  /// action, or has a rule without a name.
+  #[cfg(feature = "yaml")]
+  pub fn from_yaml(s: &str) -> io::Result<Policy> {
+    parse_yaml(s)
+  }
+
+  /// Load a policy from disk, reading `.yaml`/`.yml` files as YAML and anything else as
+  /// TOML. The path may start with `~`.
+  ///
+  /// # Errors
+  /// Returns an `io::Error` if the file cannot be read or parsed, or if the feature for its
+  /// format (`toml` or `yaml`) is disabled.
+  pub fn load(path: &str) -> io::Result<Policy> {
+
+    let path = shellexpand::tilde(path).to_string();
+    let contents = fs::read_to_string(&path)?;
+    let lower = path.to_ascii_lowercase();
+
+    if lower.ends_with(".yaml") || lower.ends_with(".yml") {
+      parse_yaml(&contents)
+    } else {
+      parse_toml(&contents)
+    }
+
+  }
+
+  #[cfg(any(feature = "toml", feature = "yaml"))]
+  fn validated(self) -> io::Result<Policy> {
+    match self.rules.iter().position(|r| r.name.trim().is_empty()) {
+      Some(i) => Err(invalid(format!("rule {} has no name", i + 1))),
+      None => Ok(self),
+    }
+  }
+
+  /// Whether any rule needs tag metadata, so callers can skip fetching it
+  pub fn uses_tag_metadata(&self) -> bool {
+    self.rules.iter().any(|r| r.when.uses_tag_metadata())
+  }
+
+  /// Whether evaluating `verdict` needs tag metadata: some rule uses it and the IP carries
+  /// tags
+  pub fn needs_tag_metadata(&self, verdict: &Verdict) -> bool {
+    self.uses_tag_metadata() && verdict.context.as_ref().is_some_and(|c| c.tags.as_ref().is_some_and(|t| !t.is_empty()))
+  }
+
+  /// Evaluate the rules against a verdict and the metadata of the tags it carries (the
+  /// full tag list from [`metadata::metadata`] will do; tags missing from it simply never
+  /// recommend blocking or contribute CVEs)
+  pub fn evaluate(&self, verdict: &Verdict, metadata: &[Metadatum]) -> Decision {
+
+    let facts = Facts::new(verdict, metadata);
+    let inventory = Values(self.inventory_cves.clone());
+
+    let matched: Vec<&Rule> = self.rules.iter()
+      .filter(|r| r.when.matches(verdict, &facts, &inventory))
+      .collect();
+
+    Decision {
+      ip: verdict.ip.clone(),
+      action: matched.first().map_or(self.default, |r| r.action),
+      rule: matched.first().map(|r| r.name.clone()),
+      matched: matched.iter().map(|r| r.name.clone()).collect(),
+      disposition: verdict.disposition,
+    }
+
+  }
+
+}
+
+/// Function to decide what to do about an IP address
+///
+/// Builds a [`Verdict`] for `ip` and evaluates `policy` against it, fetching tag metadata
+/// only when a rule needs it and the IP carries tags.
+///
+/// # Example
+/// ```rust
+/// use greynoise::policy::{self, Action, Conditions, Policy, Rule, Values};
+///
+/// let policy = Policy {
+///   rules: vec![Rule {
+///     name: String::from("malicious"),
+///     action: Action::Block,
+///     when: Conditions { classification: Some(Values(vec![String::from("malicious")])), ..Conditions::default() },
+///   }],
+///   ..Policy::default()
+/// };
+/// assert!(!policy.uses_tag_metadata());
+///
+/// //async {
+///  //let decision = policy::decide("45.83.66.65", &policy, Some(API_KEY)).await.unwrap();
+///  //println!("{} ({:?})", decision.action, decision.matched);
+/// //};
+///```
+///
+/// # Errors
+/// If any of the calls fails, it will return its `Err(StatusCode)`.
+pub async fn decide(ip: &str, policy: &Policy, key: Option<&str>) -> Result<Decision, http::StatusCode> {
+
+  let verdict = verdict::verdict(ip, key).await?;
+
+  let tags = match policy.needs_tag_metadata(&verdict) {
+    true => metadata::metadata(key).await?.metadata,
+    false => Vec::new(),
+  };
+
+  Ok(policy.evaluate(&verdict, &tags))
+
+}
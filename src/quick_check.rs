@@ -22,10 +22,10 @@ pub struct QuickCheck {
 }
 
 #[doc(hidden)]
-const QUICK_CHECK_CONTEXT_URL: &str = "https://api.greynoise.io/v2/noise/quick";
+pub(crate) const QUICK_CHECK_CONTEXT_URL: &str = "https://api.greynoise.io/v2/noise/quick";
 
 #[doc(hidden)]
-const MULTI_QUICK_CHECK_CONTEXT_URL: &str = "https://api.greynoise.io/v2/noise/multi/quick";
+pub(crate) const MULTI_QUICK_CHECK_CONTEXT_URL: &str = "https://api.greynoise.io/v2/noise/multi/quick";
 
 /// Function to retrieve list of GreyNoise tags and their respective metadata
 ///
@@ -38,7 +38,7 @@ const MULTI_QUICK_CHECK_CONTEXT_URL: &str = "https://api.greynoise.io/v2/noise/m
 /// ```rust
 /// use greynoise::quick_check;
 /// //async {
-/// // let res: Result<quick_check::QuickCheck, reqwest::StatusCode> = quick_check::quick_check("71.6.233.151", Some(API_KEY)).await;
+/// // let res: Result<quick_check::QuickCheck, http::StatusCode> = quick_check::quick_check("71.6.233.151", Some(API_KEY)).await;
 /// // assert_eq!(res.unwrap().ip, "71.6.233.151");
 /// //};
 ///```
@@ -46,19 +46,19 @@ const MULTI_QUICK_CHECK_CONTEXT_URL: &str = "https://api.greynoise.io/v2/noise/m
 /// # Errors
 /// If the call fails, it will return a `Err(StatusCode)`.
 /// To see the possible return values, check the [API docs](https://docs.greynoise.io/reference/quickcheck-1).
-pub async fn quick_check(ip: &str, key: Option<&str>) -> Result<QuickCheck, reqwest::StatusCode> {
+pub async fn quick_check(ip: &str, key: Option<&str>) -> Result<QuickCheck, http::StatusCode> {
 
   let url = format!("{}/{}", QUICK_CHECK_CONTEXT_URL, ip);
-  let res: Result<QuickCheck, reqwest::StatusCode> = get::query(url, key).await;
+  let res: Result<QuickCheck, http::StatusCode> = get::query(url, key).await;
 
   res
 
 }
 
-pub async fn multi_quick_check(ips: Vec<String>, key: Option<&str>) -> Result<MultiQuickCheck, reqwest::StatusCode> {
+pub async fn multi_quick_check(ips: Vec<String>, key: Option<&str>) -> Result<MultiQuickCheck, http::StatusCode> {
 
-  let url = format!("{}", MULTI_QUICK_CHECK_CONTEXT_URL);
-  let res: Result<MultiQuickCheck, reqwest::StatusCode> = get::post_query(url, ips, key).await;
+  let url = MULTI_QUICK_CHECK_CONTEXT_URL.to_string();
+  let res: Result<MultiQuickCheck, http::StatusCode> = get::post_query(url, ips, key).await;
 
   res
 
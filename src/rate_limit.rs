@@ -0,0 +1,105 @@
+//! Token-bucket rate limiting keyed by caller
+//!
+//! Each key (a client address, an API key, an endpoint…) gets its own bucket holding up
+//! to `burst` tokens, refilled at `per_second` tokens per second. A call takes one token
+//! (or as many as it costs, see [`RateLimiter::check_n`]), or is refused with the time until
+//! enough are available.
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Buckets untouched for this long are dropped to keep memory bounded
+#[doc(hidden)]
+const IDLE_EVICTION: Duration = Duration::from_secs(600);
+
+/// Longest wait reported when a bucket is empty
+#[doc(hidden)]
+const MAX_WAIT: Duration = Duration::from_secs(86_400);
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+  tokens: f64,
+  updated: Instant,
+}
+
+/// A set of token buckets sharing one rate.
+///
+/// # Example
+/// ```rust
+/// use greynoise::rate_limit::RateLimiter;
+///
+/// let limiter = RateLimiter::new(1.0, 2).unwrap();
+/// assert!(limiter.check("10.0.0.1").is_ok());
+/// assert!(limiter.check("10.0.0.1").is_ok());
+/// assert!(limiter.check("10.0.0.1").is_err());
+/// assert!(limiter.check("10.0.0.2").is_ok());
+///```
+#[derive(Debug)]
+pub struct RateLimiter {
+  per_second: f64,
+  burst: f64,
+  buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+
+  /// Allow `per_second` calls per second per key, with bursts of up to `burst` calls
+  ///
+  /// # Errors
+  /// Returns an `InvalidInput` error if `per_second` is not a positive, finite number.
+  pub fn new(per_second: f64, burst: u32) -> io::Result<RateLimiter> {
+
+    if !(per_second.is_finite() && per_second > 0.0) {
+      return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid rate {} (must be greater than 0)", per_second)));
+    }
+
+    Ok(RateLimiter {
+      per_second,
+      burst: burst.max(1) as f64,
+      buckets: Mutex::new(HashMap::new()),
+    })
+
+  }
+
+  /// Take a token for `key`.
+  ///
+  /// # Errors
+  /// Returns how long to wait before a token is available if the bucket is empty.
+  pub fn check(&self, key: &str) -> Result<(), Duration> {
+    self.check_n(key, 1)
+  }
+
+  /// Take `cost` tokens for `key`. A call costing more than `burst` is let through once the
+  /// bucket is full and leaves it in debt, so the key waits until the overspent tokens have
+  /// been refilled.
+  ///
+  /// # Errors
+  /// Returns how long to wait before enough tokens are available.
+  pub fn check_n(&self, key: &str, cost: u32) -> Result<(), Duration> {
+
+    let now = Instant::now();
+    let mut buckets = self.buckets.lock().unwrap();
+
+    if buckets.len() > 10_000 {
+      buckets.retain(|_, b| now.duration_since(b.updated) < IDLE_EVICTION);
+    }
+
+    let bucket = buckets.entry(key.to_string()).or_insert(Bucket { tokens: self.burst, updated: now });
+
+    let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.burst);
+    bucket.updated = now;
+
+    let needed = (cost as f64).min(self.burst);
+
+    if bucket.tokens >= needed {
+      bucket.tokens -= cost as f64;
+      Ok(())
+    } else {
+      Err(Duration::try_from_secs_f64((needed - bucket.tokens) / self.per_second).map_or(MAX_WAIT, |d| d.min(MAX_WAIT)))
+    }
+
+  }
+
+}
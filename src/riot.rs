@@ -1,5 +1,6 @@
 //! Make a request to the GreyNoise RIOT API
 use crate::get;
+use crate::lookup::Lookup;
 
 /// Structure to deserialize GreyNoise RIOT JSON.
 ///
@@ -35,7 +36,7 @@ pub struct RIOT {
 }
 
 #[doc(hidden)]
-const RIOT_URL: &str = "https://api.greynoise.io/v2/riot";
+pub(crate) const RIOT_URL: &str = "https://api.greynoise.io/v2/riot";
 
 /// Function to retrieve information about an IP address in the GreyNoise RIOT dataset
 ///
@@ -51,19 +52,22 @@ const RIOT_URL: &str = "https://api.greynoise.io/v2/riot";
 /// # Example
 /// ```rust
 /// use greynoise::riot;
+/// use greynoise::lookup::Lookup;
 /// async {
-///  let res: Result<riot::RIOT, reqwest::StatusCode> = riot::riot("8.8.8.8", None).await;
-///  assert_eq!(res.unwrap().ip, "8.8.8.8");
+///  let res: Result<Lookup<riot::RIOT>, http::StatusCode> = riot::riot("8.8.8.8", None).await;
+///  assert_eq!(res.unwrap().found().unwrap().ip, "8.8.8.8");
 /// };
 ///```
 ///
+/// If the IP address is not in the dataset, it will return `Ok(Lookup::NotFound { message })`.
+///
 /// # Errors
 /// If the call fails, it will return a `Err(StatusCode)`.
 /// To see the possible return values, check the [API docs](https://docs.greynoise.io/reference/riotip).
-pub async fn riot(ip: &str, key: Option<&str>) -> Result<RIOT, reqwest::StatusCode> {
+pub async fn riot(ip: &str, key: Option<&str>) -> Result<Lookup<RIOT>, http::StatusCode> {
 
   let url = format!("{}/{}", RIOT_URL, ip);
-  let res: Result<RIOT, reqwest::StatusCode> = get::query(url, key).await;
+  let res: Result<Lookup<RIOT>, http::StatusCode> = get::lookup_query(url, key).await;
 
   res
 
@@ -0,0 +1,271 @@
+//! Answer RIOT lookups offline from a locally stored RIOT dataset export
+//!
+//! RIOT data identifies benign services and changes slowly, so a local copy of the ranges can
+//! answer most lookups without touching the API. A dataset is a list of CIDR ranges with
+//! their provider information, loaded from JSON or CSV and indexed in a prefix trie per
+//! address family, so each lookup is a longest-prefix match over at most 32 or 128 bits.
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::net::IpAddr;
+
+use crate::riot::RIOT;
+
+/// One CIDR range of a RIOT dataset export and the provider it belongs to.
+///
+/// In JSON exports each range is an object with these keys; in CSV exports they are the
+/// column headers. Only `cidr` is required; a bare IP address is treated as a single host.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RiotRange {
+  #[serde(rename = "cidr")]
+  pub cidr: String,
+
+  #[serde(rename = "category", default)]
+  pub category: String,
+
+  #[serde(rename = "name", default)]
+  pub name: String,
+
+  #[serde(rename = "description", default)]
+  pub description: String,
+
+  #[serde(rename = "explanation", default)]
+  pub explanation: String,
+
+  #[serde(rename = "last_updated", default)]
+  pub last_updated: String,
+
+  #[serde(rename = "reference", default)]
+  pub reference: String,
+
+  #[serde(rename = "trust_level", default)]
+  pub trust_level: String,
+}
+
+/// Binary trie over left-aligned 128-bit addresses.
+///
+/// Nodes live in a flat vector; a child index of `0` means "no child", which is safe
+/// because the root (index `0`) is never anyone's child.
+#[derive(Debug, Default)]
+struct PrefixTrie {
+  children: Vec<[usize; 2]>,
+  values: Vec<Option<usize>>,
+}
+
+impl PrefixTrie {
+
+  fn new() -> PrefixTrie {
+    PrefixTrie {
+      children: vec![[0, 0]],
+      values: vec![None],
+    }
+  }
+
+  fn insert(&mut self, addr: u128, prefix_len: u8, value: usize) {
+
+    let mut node = 0;
+
+    for i in 0..prefix_len {
+      let bit = ((addr >> (127 - i)) & 1) as usize;
+      if self.children[node][bit] == 0 {
+        self.children.push([0, 0]);
+        self.values.push(None);
+        self.children[node][bit] = self.children.len() - 1;
+      }
+      node = self.children[node][bit];
+    }
+
+    self.values[node] = Some(value);
+
+  }
+
+  fn longest_match(&self, addr: u128) -> Option<usize> {
+
+    let mut node = 0;
+    let mut best = self.values[0];
+
+    for i in 0..128 {
+      let bit = ((addr >> (127 - i)) & 1) as usize;
+      node = self.children[node][bit];
+      if node == 0 {
+        break;
+      }
+      best = self.values[node].or(best);
+    }
+
+    best
+
+  }
+
+}
+
+/// Left-align an address in 128 bits and return the width of its address family
+pub(crate) fn addr_bits(ip: IpAddr) -> (u128, u8) {
+  match ip {
+    IpAddr::V4(v4) => ((u32::from(v4) as u128) << 96, 32),
+    IpAddr::V6(v6) => (u128::from(v6), 128),
+  }
+}
+
+/// Parse `addr/len` (or a bare address) into a left-aligned address and prefix length
+pub(crate) fn parse_cidr(cidr: &str) -> Option<(IpAddr, u128, u8)> {
+
+  let (addr, len) = match cidr.trim().split_once('/') {
+    Some((a, l)) => (a, Some(l)),
+    None => (cidr.trim(), None),
+  };
+
+  let ip: IpAddr = addr.parse().ok()?;
+  let (bits, width) = addr_bits(ip);
+
+  let len = match len {
+    Some(l) => l.parse::<u8>().ok().filter(|l| *l <= width)?,
+    None => width,
+  };
+
+  Some((ip, bits, len))
+
+}
+
+/// Indexed RIOT dataset answering lookups offline.
+///
+/// # Example
+/// ```rust
+/// use greynoise::riot_dataset::RiotDataset;
+///
+/// let json = r#"[{"cidr": "8.8.8.0/24", "name": "Google Public DNS", "category": "public_dns", "trust_level": "1"}]"#;
+/// let dataset = RiotDataset::from_json(json.as_bytes()).unwrap();
+/// assert_eq!(dataset.lookup("8.8.8.8").unwrap().name, "Google Public DNS");
+/// assert!(dataset.lookup("1.1.1.1").is_none());
+///```
+#[derive(Debug)]
+pub struct RiotDataset {
+  ranges: Vec<RiotRange>,
+  v4: PrefixTrie,
+  v6: PrefixTrie,
+}
+
+impl Default for RiotDataset {
+  fn default() -> RiotDataset {
+    RiotDataset::new()
+  }
+}
+
+impl RiotDataset {
+
+  /// Create an empty dataset
+  pub fn new() -> RiotDataset {
+    RiotDataset {
+      ranges: Vec::new(),
+      v4: PrefixTrie::new(),
+      v6: PrefixTrie::new(),
+    }
+  }
+
+  /// Build a dataset from a JSON array of [`RiotRange`] objects
+  ///
+  /// # Errors
+  /// Returns an `io::Error` if the input cannot be read, is not valid JSON, or contains an
+  /// invalid CIDR.
+  pub fn from_json<R: Read>(reader: R) -> io::Result<RiotDataset> {
+    let ranges: Vec<RiotRange> = serde_json::from_reader(reader)?;
+    RiotDataset::from_ranges(ranges)
+  }
+
+  /// Build a dataset from CSV with a header row naming the [`RiotRange`] fields
+  ///
+  /// # Errors
+  /// Returns an `io::Error` if the input cannot be read, is not valid CSV, or contains an
+  /// invalid CIDR.
+  pub fn from_csv<R: Read>(reader: R) -> io::Result<RiotDataset> {
+    let ranges = csv::Reader::from_reader(reader)
+      .deserialize()
+      .collect::<Result<Vec<RiotRange>, csv::Error>>()?;
+    RiotDataset::from_ranges(ranges)
+  }
+
+  /// Load a dataset export from disk, choosing CSV for `.csv` files and JSON otherwise.
+  /// The path may start with `~`.
+  ///
+  /// # Errors
+  /// Returns an `io::Error` if the file cannot be opened or parsed.
+  pub fn load(path: &str) -> io::Result<RiotDataset> {
+
+    let path = shellexpand::tilde(path).to_string();
+    let reader = BufReader::new(File::open(&path)?);
+
+    if path.to_ascii_lowercase().ends_with(".csv") {
+      RiotDataset::from_csv(reader)
+    } else {
+      RiotDataset::from_json(reader)
+    }
+
+  }
+
+  fn from_ranges(ranges: Vec<RiotRange>) -> io::Result<RiotDataset> {
+
+    let mut dataset = RiotDataset::new();
+
+    for range in ranges {
+      dataset.insert(range)?;
+    }
+
+    Ok(dataset)
+
+  }
+
+  /// Add a range to the dataset. A more specific range takes precedence over a broader one
+  /// on lookup; re-adding the same CIDR replaces it.
+  ///
+  /// # Errors
+  /// Returns an `io::Error` of kind `InvalidData` if `range.cidr` is not a valid CIDR.
+  pub fn insert(&mut self, range: RiotRange) -> io::Result<()> {
+
+    let (ip, bits, len) = parse_cidr(&range.cidr).ok_or_else(|| {
+      io::Error::new(io::ErrorKind::InvalidData, format!("invalid CIDR: {}", range.cidr))
+    })?;
+
+    let trie = if ip.is_ipv4() { &mut self.v4 } else { &mut self.v6 };
+    trie.insert(bits, len, self.ranges.len());
+    self.ranges.push(range);
+
+    Ok(())
+
+  }
+
+  /// Number of ranges in the dataset
+  pub fn len(&self) -> usize {
+    self.ranges.len()
+  }
+
+  /// Returns `true` if the dataset has no ranges
+  pub fn is_empty(&self) -> bool {
+    self.ranges.is_empty()
+  }
+
+  /// Find the most specific range containing `ip`
+  pub fn range_for(&self, ip: &str) -> Option<&RiotRange> {
+
+    let ip: IpAddr = ip.trim().parse().ok()?;
+    let (bits, _) = addr_bits(ip);
+
+    let trie = if ip.is_ipv4() { &self.v4 } else { &self.v6 };
+    trie.longest_match(bits).map(|i| &self.ranges[i])
+
+  }
+
+  /// Look `ip` up offline, returning the same structure as [`crate::riot::riot`]
+  pub fn lookup(&self, ip: &str) -> Option<RIOT> {
+    self.range_for(ip).map(|range| RIOT {
+      ip: ip.trim().to_string(),
+      riot: true,
+      category: range.category.clone(),
+      name: range.name.clone(),
+      description: range.description.clone(),
+      explanation: range.explanation.clone(),
+      last_updated: range.last_updated.clone(),
+      reference: range.reference.clone(),
+      trust_level: range.trust_level.clone(),
+    })
+  }
+
+}
@@ -0,0 +1,327 @@
+//! Enrich Zeek `conn.log` and Suricata `eve.json` records with GreyNoise verdicts
+//!
+//! Records are read in batches, the originator IP of each one (`id.orig_h` for Zeek,
+//! `src_ip` for Suricata by default) is looked up through an [`Enricher`] (Quick Check, then
+//! IP Context / RIOT on hits if the enricher is configured to deepen), and every record is
+//! written back in its original format with GreyNoise fields added:
+//!
+//! - Zeek TSV gains `greynoise.*` columns, declared in the `#fields` and `#types` headers
+//! - Zeek JSON gains flat `greynoise.*` keys, matching Zeek's dotted key style
+//! - Suricata EVE gains a nested `greynoise` object, matching EVE's nesting style
+//!
+//! Records whose IP is not routable, or is unknown to GreyNoise, pass through unchanged
+//! (Zeek TSV rows get unset `-` values so the columns stay aligned).
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde_json::{Map, Value};
+
+use crate::enrich::{is_routable, parse_ip, Enricher, IpVerdict};
+
+/// Supported sensor log formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorFormat {
+  /// Zeek ASCII logs (tab-separated with `#` headers)
+  ZeekTsv,
+
+  /// Zeek JSON logs (one object per line with dotted keys)
+  ZeekJson,
+
+  /// Suricata EVE JSON (one object per line)
+  SuricataEve,
+}
+
+impl FromStr for SensorFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<SensorFormat, String> {
+    match s.to_ascii_lowercase().as_str() {
+      "zeek" | "zeek-tsv" => Ok(SensorFormat::ZeekTsv),
+      "zeek-json" => Ok(SensorFormat::ZeekJson),
+      "eve" | "suricata" => Ok(SensorFormat::SuricataEve),
+      other => Err(format!("unknown sensor format '{}' (expected zeek, zeek-json or eve)", other)),
+    }
+  }
+}
+
+impl SensorFormat {
+
+  /// Guess the format from the first line of a log
+  pub fn detect(first_line: &str) -> Option<SensorFormat> {
+
+    if first_line.starts_with('#') {
+      return Some(SensorFormat::ZeekTsv);
+    }
+
+    match serde_json::from_str::<Map<String, Value>>(first_line) {
+      Ok(obj) if obj.contains_key("id.orig_h") => Some(SensorFormat::ZeekJson),
+      Ok(obj) if obj.contains_key("src_ip") || obj.contains_key("event_type") => Some(SensorFormat::SuricataEve),
+      _ => None,
+    }
+
+  }
+
+  /// Field holding the IP to look up unless another is given
+  pub fn default_ip_field(&self) -> &'static str {
+    match self {
+      SensorFormat::ZeekTsv | SensorFormat::ZeekJson => "id.orig_h",
+      SensorFormat::SuricataEve => "src_ip",
+    }
+  }
+
+}
+
+/// Zeek TSV columns added to each record, with their Zeek types
+#[doc(hidden)]
+const ZEEK_COLUMNS: [(&str, &str); 8] = [
+  ("noise", "bool"),
+  ("riot", "bool"),
+  ("classification", "string"),
+  ("actor", "string"),
+  ("tags", "set[string]"),
+  ("name", "string"),
+  ("category", "string"),
+  ("trust_level", "string"),
+];
+
+/// Header state of a Zeek TSV log
+#[derive(Debug, Clone)]
+struct ZeekHeader {
+  separator: String,
+  set_separator: String,
+  unset_field: String,
+  ip_column: Option<usize>,
+}
+
+impl Default for ZeekHeader {
+  fn default() -> ZeekHeader {
+    ZeekHeader {
+      separator: String::from("\t"),
+      set_separator: String::from(","),
+      unset_field: String::from("-"),
+      ip_column: None,
+    }
+  }
+}
+
+/// Decode `\xHH` escapes as used by Zeek's `#separator` header
+fn unescape_zeek(s: &str) -> String {
+
+  let mut out = String::new();
+  let mut rest = s;
+
+  while let Some(i) = rest.find("\\x") {
+    out.push_str(&rest[..i]);
+    match rest.get(i + 2..i + 4).and_then(|h| u8::from_str_radix(h, 16).ok()) {
+      Some(b) => {
+        out.push(b as char);
+        rest = &rest[i + 4..];
+      }
+      None => {
+        out.push_str("\\x");
+        rest = &rest[i + 2..];
+      }
+    }
+  }
+
+  out.push_str(rest);
+  out
+
+}
+
+/// One line of a batch, classified before verdicts are fetched
+enum Line {
+  /// Written out as-is (already rewritten if it was a header)
+  Verbatim(String),
+
+  /// A Zeek TSV row and the IP to look up, if any
+  ZeekRow { row: String, ip: Option<String>, header: Arc<ZeekHeader> },
+
+  /// A JSON record and the IP to look up, if any
+  Json { record: Map<String, Value>, ip: Option<String> },
+}
+
+/// Rewrite a Zeek header line, updating the header state
+fn zeek_header_line(line: &str, header: &mut ZeekHeader, ip_field: &str) -> String {
+
+  if let Some(sep) = line.strip_prefix("#separator ") {
+    header.separator = unescape_zeek(sep);
+    return line.to_string();
+  }
+
+  let mut parts = line.splitn(2, header.separator.as_str());
+  let (directive, value) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+
+  match directive {
+    "#set_separator" => header.set_separator = value.to_string(),
+    "#unset_field" => header.unset_field = value.to_string(),
+    "#fields" => {
+      header.ip_column = value.split(header.separator.as_str()).position(|f| f == ip_field);
+      let added: Vec<String> = ZEEK_COLUMNS.iter().map(|(name, _)| format!("greynoise.{}", name)).collect();
+      return format!("{}{}{}", line, header.separator, added.join(&header.separator));
+    }
+    "#types" => {
+      let added: Vec<&str> = ZEEK_COLUMNS.iter().map(|(_, ty)| *ty).collect();
+      return format!("{}{}{}", line, header.separator, added.join(&header.separator));
+    }
+    _ => {}
+  }
+
+  line.to_string()
+
+}
+
+/// Zeek TSV cells for a verdict, in `ZEEK_COLUMNS` order
+fn zeek_cells(verdict: Option<&IpVerdict>, header: &ZeekHeader) -> Vec<String> {
+
+  let unset = || header.unset_field.clone();
+
+  match verdict {
+    None => ZEEK_COLUMNS.iter().map(|_| unset()).collect(),
+    Some(v) => vec![
+      if v.noise { String::from("T") } else { String::from("F") },
+      if v.riot { String::from("T") } else { String::from("F") },
+      v.classification.clone().unwrap_or_else(unset),
+      v.actor.clone().unwrap_or_else(unset),
+      if v.tags.is_empty() { unset() } else { v.tags.join(&header.set_separator) },
+      v.name.clone().unwrap_or_else(unset),
+      v.category.clone().unwrap_or_else(unset),
+      v.trust_level.clone().unwrap_or_else(unset),
+    ],
+  }
+
+}
+
+/// Verdict fields as JSON, omitting those that are not known
+fn json_fields(verdict: &IpVerdict) -> Vec<(&'static str, Value)> {
+
+  let mut fields = vec![
+    ("noise", Value::Bool(verdict.noise)),
+    ("riot", Value::Bool(verdict.riot)),
+  ];
+
+  let optional = [
+    ("classification", verdict.classification.clone()),
+    ("actor", verdict.actor.clone()),
+    ("name", verdict.name.clone()),
+    ("category", verdict.category.clone()),
+    ("trust_level", verdict.trust_level.clone()),
+  ];
+
+  fields.extend(optional.into_iter().filter_map(|(k, v)| v.map(|v| (k, Value::String(v)))));
+
+  if !verdict.tags.is_empty() {
+    fields.push(("tags", Value::from(verdict.tags.clone())));
+  }
+
+  fields
+
+}
+
+fn routable(ip: &str) -> Option<String> {
+  parse_ip(ip).filter(is_routable).map(|ip| ip.to_string())
+}
+
+/// Read a Zeek or Suricata log from `reader` and write it, enriched, to `writer`.
+///
+/// `ip_field` overrides the field holding the IP to look up (see
+/// [`SensorFormat::default_ip_field`]). Lines that are not valid records for `format` are
+/// passed through unchanged.
+///
+/// # Example
+/// ```rust,no_run
+/// use greynoise::client::Client;
+/// use greynoise::enrich::Enricher;
+/// use greynoise::sensor_logs::{enrich_sensor_log, SensorFormat};
+///
+/// async {
+///  let client = Client::new(Some("API_KEY"));
+///  let enricher = Enricher::new(&client).context(true).riot(true);
+///  let eve = std::io::BufReader::new(std::fs::File::open("/var/log/suricata/eve.json").unwrap());
+///  enrich_sensor_log(&enricher, SensorFormat::SuricataEve, None, eve, std::io::stdout().lock()).await.unwrap();
+/// };
+///```
+///
+/// # Errors
+/// Returns any I/O error, or an error wrapping the [`Error`](crate::error::Error) of a failed lookup.
+pub async fn enrich_sensor_log<R: BufRead, W: Write>(enricher: &Enricher<'_>, format: SensorFormat, ip_field: Option<&str>, reader: R, mut writer: W) -> io::Result<()> {
+
+  let ip_field = ip_field.unwrap_or_else(|| format.default_ip_field());
+  let mut header = Arc::new(ZeekHeader::default());
+  let mut lines = reader.lines();
+
+  loop {
+
+    let mut batch: Vec<Line> = Vec::new();
+
+    for line in lines.by_ref().take(enricher.batch_lines) {
+      let line = line?;
+      batch.push(match format {
+        SensorFormat::ZeekTsv if line.starts_with('#') => {
+          Line::Verbatim(zeek_header_line(&line, Arc::make_mut(&mut header), ip_field))
+        }
+        SensorFormat::ZeekTsv => {
+          let ip = header.ip_column
+            .and_then(|i| line.split(header.separator.as_str()).nth(i))
+            .and_then(routable);
+          Line::ZeekRow { row: line, ip, header: Arc::clone(&header) }
+        }
+        SensorFormat::ZeekJson | SensorFormat::SuricataEve => {
+          match serde_json::from_str::<Map<String, Value>>(&line) {
+            Ok(record) => {
+              let ip = record.get(ip_field).and_then(Value::as_str).and_then(routable);
+              Line::Json { record, ip }
+            }
+            Err(_) => Line::Verbatim(line),
+          }
+        }
+      });
+    }
+
+    if batch.is_empty() {
+      return writer.flush();
+    }
+
+    let mut ips: Vec<String> = batch.iter()
+      .filter_map(|l| match l {
+        Line::ZeekRow { ip, .. } | Line::Json { ip, .. } => ip.clone(),
+        Line::Verbatim(_) => None,
+      })
+      .collect();
+    ips.sort();
+    ips.dedup();
+
+    let verdicts: HashMap<String, IpVerdict> = enricher.verdicts(&ips).await
+      .map_err(|e| io::Error::other(format!("GreyNoise lookup failed: {}", e)))?;
+
+    for line in batch {
+      match line {
+        Line::Verbatim(l) => writeln!(writer, "{}", l)?,
+        Line::ZeekRow { row, ip, header } => {
+          let verdict = ip.and_then(|ip| verdicts.get(&ip));
+          writeln!(writer, "{}{}{}", row, header.separator, zeek_cells(verdict, &header).join(&header.separator))?;
+        }
+        Line::Json { mut record, ip } => {
+          if let Some(verdict) = ip.and_then(|ip| verdicts.get(&ip)) {
+            let fields = json_fields(verdict);
+            match format {
+              SensorFormat::SuricataEve => {
+                record.insert(String::from("greynoise"), Value::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect()));
+              }
+              _ => {
+                for (k, v) in fields {
+                  record.insert(format!("greynoise.{}", k), v);
+                }
+              }
+            }
+          }
+          writeln!(writer, "{}", Value::Object(record))?;
+        }
+      }
+    }
+
+  }
+
+}
@@ -0,0 +1,335 @@
+//! Format GreyNoise results as CEF or LEEF events and forward them over syslog
+//!
+//! [`cef`] and [`leef`] render any [`SiemRecord`] (`QuickCheck`, `Community`, `RIOT`,
+//! `IPContext`) as one ArcSight CEF or QRadar LEEF 1.0 message. The IP goes in `src` and
+//! the GreyNoise fields in vendor extension keys (`GreyNoiseClassification`,
+//! `GreyNoiseActor`, `GreyNoiseTags`, …), escaped as each format requires.
+//!
+//! [`SyslogSender`] wraps those messages in RFC 5424 syslog headers and sends them over UDP,
+//! or over TCP with RFC 6587 octet-counting framing.
+use std::io;
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+
+use crate::community::Community;
+use crate::ip_context::IPContext;
+use crate::quick_check::QuickCheck;
+use crate::riot::RIOT;
+
+#[doc(hidden)]
+const VENDOR: &str = "GreyNoise";
+
+#[doc(hidden)]
+const PRODUCT: &str = "greynoise-rust";
+
+/// A GreyNoise result reduced to what SIEM formats carry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiemEvent {
+  /// Event class, e.g. `quick-check` or `ip-context`
+  pub signature_id: &'static str,
+
+  /// Human-readable event name
+  pub name: String,
+
+  /// Severity from 0 (informational) to 10 (very high)
+  pub severity: u8,
+
+  /// IP address the event is about
+  pub ip: String,
+
+  /// GreyNoise fields as `(extension key, value)`, empty values omitted
+  pub fields: Vec<(&'static str, String)>,
+}
+
+/// Results that can be sent to a SIEM
+pub trait SiemRecord {
+  fn siem_event(&self) -> SiemEvent;
+}
+
+/// Severity of an IP given its GreyNoise classification
+fn severity(noise: bool, classification: Option<&str>) -> u8 {
+  match (noise, classification) {
+    (_, Some("malicious")) => 8,
+    (_, Some("benign")) => 1,
+    (true, _) => 5,
+    (false, _) => 0,
+  }
+}
+
+fn field(key: &'static str, value: impl ToString) -> Option<(&'static str, String)> {
+  Some((key, value.to_string())).filter(|(_, v)| !v.is_empty())
+}
+
+impl SiemRecord for QuickCheck {
+  fn siem_event(&self) -> SiemEvent {
+    SiemEvent {
+      signature_id: "quick-check",
+      name: String::from("GreyNoise Quick Check"),
+      severity: severity(self.noise, None),
+      ip: self.ip.clone(),
+      fields: [
+        field("GreyNoiseNoise", self.noise),
+        field("GreyNoiseRiot", self.riot),
+        field("GreyNoiseCode", &self.code),
+      ].into_iter().flatten().collect(),
+    }
+  }
+}
+
+impl SiemRecord for Community {
+  fn siem_event(&self) -> SiemEvent {
+    SiemEvent {
+      signature_id: "community",
+      name: String::from("GreyNoise Community lookup"),
+      severity: severity(self.noise, Some(&self.classification)),
+      ip: self.ip.clone(),
+      fields: [
+        field("GreyNoiseNoise", self.noise),
+        field("GreyNoiseRiot", self.riot),
+        field("GreyNoiseClassification", &self.classification),
+        field("GreyNoiseName", &self.name),
+        field("GreyNoiseLastSeen", &self.last_seen),
+        field("GreyNoiseLink", &self.link),
+      ].into_iter().flatten().collect(),
+    }
+  }
+}
+
+impl SiemRecord for RIOT {
+  fn siem_event(&self) -> SiemEvent {
+    SiemEvent {
+      signature_id: "riot",
+      name: String::from("GreyNoise RIOT lookup"),
+      severity: if self.riot { 1 } else { 0 },
+      ip: self.ip.clone(),
+      fields: [
+        field("GreyNoiseRiot", self.riot),
+        field("GreyNoiseName", &self.name),
+        field("GreyNoiseCategory", &self.category),
+        field("GreyNoiseTrustLevel", &self.trust_level),
+        field("GreyNoiseLastUpdated", &self.last_updated),
+        field("GreyNoiseReference", &self.reference),
+      ].into_iter().flatten().collect(),
+    }
+  }
+}
+
+impl SiemRecord for IPContext {
+  fn siem_event(&self) -> SiemEvent {
+    SiemEvent {
+      signature_id: "ip-context",
+      name: String::from("GreyNoise IP Context"),
+      severity: severity(self.seen, self.classification.as_deref()),
+      ip: self.ip.clone(),
+      fields: [
+        field("GreyNoiseNoise", self.seen),
+        field("GreyNoiseClassification", self.classification.as_deref().unwrap_or_default()),
+        field("GreyNoiseActor", self.actor.as_deref().unwrap_or_default()),
+        field("GreyNoiseTags", self.tags.as_deref().unwrap_or_default().join(",")),
+        field("GreyNoiseCVE", self.cve.as_deref().unwrap_or_default().join(",")),
+        field("GreyNoiseFirstSeen", self.first_seen.as_deref().unwrap_or_default()),
+        field("GreyNoiseLastSeen", self.last_seen.as_deref().unwrap_or_default()),
+        field("GreyNoiseSpoofable", self.spoofable.map(|s| s.to_string()).unwrap_or_default()),
+        field("GreyNoiseASN", self.metadata.as_ref().and_then(|m| m.asn.as_deref()).unwrap_or_default()),
+        field("GreyNoiseCountry", self.metadata.as_ref().and_then(|m| m.country_code.as_deref()).unwrap_or_default()),
+      ].into_iter().flatten().collect(),
+    }
+  }
+}
+
+/// Escape a CEF header field (`\` and `|`)
+fn cef_header(s: &str) -> String {
+  s.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+/// Escape a CEF extension value (`\`, `=` and line breaks)
+fn cef_value(s: &str) -> String {
+  s.replace('\\', "\\\\").replace('=', "\\=").replace('\r', "\\r").replace('\n', "\\n")
+}
+
+/// Escape a LEEF value (`\`, tabs and line breaks)
+fn leef_value(s: &str) -> String {
+  s.replace('\\', "\\\\").replace('\t', "\\t").replace('\r', "\\r").replace('\n', "\\n")
+}
+
+/// Render `record` as a CEF:0 message.
+///
+/// # Example
+/// ```rust
+/// use greynoise::quick_check::QuickCheck;
+/// use greynoise::siem::cef;
+///
+/// let qc = QuickCheck { ip: String::from("8.8.8.8"), noise: false, riot: true, code: String::from("0x00") };
+/// assert!(cef(&qc).ends_with("|quick-check|GreyNoise Quick Check|0|src=8.8.8.8 GreyNoiseNoise=false GreyNoiseRiot=true GreyNoiseCode=0x00"));
+///```
+pub fn cef<T: SiemRecord>(record: &T) -> String {
+
+  let event = record.siem_event();
+
+  let mut extension = vec![format!("src={}", cef_value(&event.ip))];
+  extension.extend(event.fields.iter().map(|(k, v)| format!("{}={}", k, cef_value(v))));
+
+  format!(
+    "CEF:0|{}|{}|{}|{}|{}|{}|{}",
+    cef_header(VENDOR), cef_header(PRODUCT), cef_header(env!("CARGO_PKG_VERSION")),
+    cef_header(event.signature_id), cef_header(&event.name), event.severity,
+    extension.join(" "),
+  )
+
+}
+
+/// Render `record` as a tab-delimited LEEF:1.0 message
+pub fn leef<T: SiemRecord>(record: &T) -> String {
+
+  let event = record.siem_event();
+
+  let mut attributes = vec![
+    format!("src={}", leef_value(&event.ip)),
+    format!("sev={}", event.severity.max(1)),
+  ];
+  attributes.extend(event.fields.iter().map(|(k, v)| format!("{}={}", k, leef_value(v))));
+
+  format!(
+    "LEEF:1.0|{}|{}|{}|{}|{}",
+    cef_header(VENDOR), cef_header(PRODUCT), cef_header(env!("CARGO_PKG_VERSION")),
+    cef_header(event.signature_id), attributes.join("\t"),
+  )
+
+}
+
+/// Syslog transport
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogProtocol {
+  /// One datagram per message
+  Udp,
+
+  /// A stream of octet-counted messages (RFC 6587)
+  Tcp,
+}
+
+/// Sends messages to a syslog collector with RFC 5424 headers.
+///
+/// # Example
+/// ```rust,no_run
+/// use greynoise::quick_check::QuickCheck;
+/// use greynoise::siem::{cef, SyslogProtocol, SyslogSender};
+///
+/// async {
+///  let qc = QuickCheck { ip: String::from("8.8.8.8"), noise: false, riot: true, code: String::from("0x00") };
+///  let mut sender = SyslogSender::connect("siem.example.com:514", SyslogProtocol::Udp).await.unwrap();
+///  sender.send(&cef(&qc), 6).await.unwrap();
+/// };
+///```
+pub struct SyslogSender {
+  transport: Transport,
+  facility: u8,
+  hostname: String,
+  app_name: String,
+}
+
+enum Transport {
+  Udp(UdpSocket),
+  Tcp(TcpStream),
+}
+
+/// Syslog severity for a record: warning for malicious IPs, informational otherwise
+fn syslog_severity<T: SiemRecord>(record: &T) -> u8 {
+  if record.siem_event().severity >= 8 { 4 } else { 6 }
+}
+
+/// This host's name, or the RFC 5424 nil value if it cannot be found
+fn hostname() -> String {
+  std::env::var("HOSTNAME").ok()
+    .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+    .map(|h| h.trim().to_string())
+    .filter(|h| !h.is_empty())
+    .unwrap_or_else(|| String::from("-"))
+}
+
+impl SyslogSender {
+
+  /// Connect to the collector at `addr` (`host:port`). Messages use the `user` facility
+  /// and the `greynoise` app name unless changed.
+  ///
+  /// # Errors
+  /// Returns any error resolving or connecting to `addr`.
+  pub async fn connect(addr: &str, protocol: SyslogProtocol) -> io::Result<SyslogSender> {
+
+    let transport = match protocol {
+      SyslogProtocol::Udp => {
+        let bind = if addr.starts_with('[') { "[::]:0" } else { "0.0.0.0:0" };
+        let socket = UdpSocket::bind(bind).await?;
+        socket.connect(addr).await?;
+        Transport::Udp(socket)
+      }
+      SyslogProtocol::Tcp => Transport::Tcp(TcpStream::connect(addr).await?),
+    };
+
+    Ok(SyslogSender {
+      transport,
+      facility: 1,
+      hostname: hostname(),
+      app_name: String::from("greynoise"),
+    })
+
+  }
+
+  /// Syslog facility code, 0 to 23 (default 1, `user`)
+  pub fn facility(mut self, facility: u8) -> SyslogSender {
+    self.facility = facility.min(23);
+    self
+  }
+
+  /// APP-NAME header field (default `greynoise`)
+  pub fn app_name(mut self, app_name: &str) -> SyslogSender {
+    self.app_name = app_name.to_string();
+    self
+  }
+
+  /// `message` with an RFC 5424 header at syslog `severity` (0 emergency to 7 debug)
+  pub fn frame(&self, message: &str, severity: u8) -> String {
+    format!(
+      "<{}>1 {} {} {} {} - - {}",
+      self.facility as u16 * 8 + severity.min(7) as u16,
+      chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ"),
+      self.hostname, self.app_name, std::process::id(), message,
+    )
+  }
+
+  /// Send `message` at syslog `severity` (0 emergency to 7 debug)
+  ///
+  /// # Errors
+  /// Returns any error writing to the socket.
+  pub async fn send(&mut self, message: &str, severity: u8) -> io::Result<()> {
+
+    let framed = self.frame(message, severity);
+
+    match &mut self.transport {
+      Transport::Udp(socket) => socket.send(framed.as_bytes()).await.map(|_| ()),
+      Transport::Tcp(stream) => {
+        stream.write_all(format!("{} {}", framed.len(), framed).as_bytes()).await?;
+        stream.flush().await
+      }
+    }
+
+  }
+
+  /// Send `record` as a CEF message, at warning severity for malicious IPs and
+  /// informational otherwise
+  ///
+  /// # Errors
+  /// Returns any error writing to the socket.
+  pub async fn send_cef<T: SiemRecord>(&mut self, record: &T) -> io::Result<()> {
+    self.send(&cef(record), syslog_severity(record)).await
+  }
+
+  /// Send `record` as a LEEF message, at the same severity as [`Self::send_cef`]
+  ///
+  /// # Errors
+  /// Returns any error writing to the socket.
+  pub async fn send_leef<T: SiemRecord>(&mut self, record: &T) -> io::Result<()> {
+    self.send(&leef(record), syslog_severity(record)).await
+  }
+
+}
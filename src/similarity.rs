@@ -0,0 +1,131 @@
+//! Make a request to the GreyNoise IP Similarity API
+//!
+//! Returns IPs whose scanning behaviour (ports, JA3 and HASSH fingerprints, user agents, web
+//! paths, …) resembles that of a given IP, each with a similarity score and the features
+//! that matched: a way to expand from one attacker to the rest of its infrastructure.
+use crate::get;
+
+/// Structure to deserialize a GreyNoise IP Similarity response
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Similarity {
+  /// The IP that was looked up
+  #[serde(rename = "ip")]
+  pub ip: SimilarIp,
+
+  /// IPs behaving like it, most similar first
+  #[serde(rename = "similar_ips", default)]
+  pub similar_ips: Vec<SimilarIp>,
+
+  /// Number of similar IPs GreyNoise knows of, which may exceed the number returned
+  #[serde(rename = "total")]
+  pub total: Option<i64>,
+}
+
+/// Structure to deserialize an IP of a similarity response.
+///
+/// Any values inside `Option` are optional, and you should check if there is `Some` or `None` before using it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SimilarIp {
+  #[serde(rename = "ip")]
+  pub ip: String,
+
+  /// Similarity to the IP that was looked up, from 0 to 1 (absent for that IP itself)
+  #[serde(rename = "score")]
+  pub score: Option<f64>,
+
+  /// Features that matched, e.g. `ports`, `ja3_fp`, `hassh_fp`, `useragents`, `web_paths`
+  #[serde(rename = "features", default)]
+  pub features: Vec<String>,
+
+  #[serde(rename = "classification")]
+  pub classification: Option<String>,
+
+  #[serde(rename = "actor")]
+  pub actor: Option<String>,
+
+  #[serde(rename = "asn")]
+  pub asn: Option<String>,
+
+  #[serde(rename = "organization")]
+  pub organization: Option<String>,
+
+  #[serde(rename = "city")]
+  pub city: Option<String>,
+
+  #[serde(rename = "country")]
+  pub country: Option<String>,
+
+  #[serde(rename = "country_code")]
+  pub country_code: Option<String>,
+
+  #[serde(rename = "first_seen")]
+  pub first_seen: Option<String>,
+
+  #[serde(rename = "last_seen")]
+  pub last_seen: Option<String>,
+}
+
+impl Similarity {
+
+  /// Addresses of the similar IPs scoring at least `min_score`
+  pub fn ips_above(&self, min_score: f64) -> Vec<&str> {
+    self.similar_ips.iter()
+      .filter(|s| s.score.unwrap_or(0.0) >= min_score)
+      .map(|s| s.ip.as_str())
+      .collect()
+  }
+
+}
+
+#[doc(hidden)]
+pub(crate) const SIMILARITY_URL: &str = "https://api.greynoise.io/v3/similarity/ips";
+
+/// Build an IP Similarity URL
+#[doc(hidden)]
+pub(crate) fn similarity_url(ip: &str, min_score: Option<f64>, limit: Option<u32>) -> String {
+
+  let base = format!("{}/{}", SIMILARITY_URL, ip);
+  let mut params: Vec<(&str, String)> = Vec::new();
+
+  if let Some(min_score) = min_score {
+    params.push(("minimum_score", min_score.to_string()));
+  }
+
+  if let Some(limit) = limit {
+    params.push(("limit", limit.to_string()));
+  }
+
+  match url::Url::parse_with_params(&base, &params) {
+    Ok(url) => url.to_string(),
+    Err(_) => base,
+  }
+
+}
+
+/// Function to retrieve IPs behaving like a given IP
+///
+/// Returns at most `limit` IPs (50 by default) whose similarity score is at least
+/// `min_score` (between 0.85 and 1, 0.9 by default).
+///
+/// For more information on the IP Similarity API endpoint check the [API docs](https://docs.greynoise.io/reference/get_v3-similarity-ips-ip).
+///
+/// # Example
+/// ```rust
+/// use greynoise::similarity;
+/// //async {
+///  //let res = similarity::similarity("45.83.66.65", Some(0.95), Some(20), Some(API_KEY)).await;
+///  //let pivots = res.unwrap().ips_above(0.98);
+/// //};
+///```
+///
+/// # Errors
+/// If the call fails, it will return a `Err(StatusCode)`.
+/// To see the possible return values, check the [API docs](https://docs.greynoise.io/reference/get_v3-similarity-ips-ip).
+pub async fn similarity(ip: &str, min_score: Option<f64>, limit: Option<u32>, key: Option<&str>) -> Result<Similarity, http::StatusCode> {
+
+  let url = similarity_url(ip, min_score, limit);
+  let res: Result<Similarity, http::StatusCode> = get::query(url, key).await;
+
+  res
+
+}
@@ -0,0 +1,150 @@
+//! Persistent response cache backend stored in a SQLite database
+//!
+//! Enabled with the `sqlite-cache` feature. Responses are kept as the raw JSON returned by
+//! the API along with the time they were fetched, so they survive between sessions. The
+//! database runs in WAL mode with a busy timeout, which lets several processes share one
+//! cache file.
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::cache::{CacheBackend, CachedResponse, Endpoint};
+
+#[doc(hidden)]
+const DEFAULT_CACHE_PATH: &str = "~/.cache/greynoise/cache.sqlite";
+
+#[doc(hidden)]
+const SCHEMA: &str = "
+  CREATE TABLE IF NOT EXISTS responses (
+    endpoint   TEXT    NOT NULL,
+    ip         TEXT    NOT NULL,
+    status     INTEGER NOT NULL,
+    body       TEXT    NOT NULL,
+    fetched_at INTEGER NOT NULL,
+    PRIMARY KEY (endpoint, ip)
+  );
+  CREATE INDEX IF NOT EXISTS responses_fetched_at ON responses (fetched_at);
+";
+
+/// Cache backend storing responses in a SQLite database file.
+///
+/// # Example
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use greynoise::cache::Cache;
+/// use greynoise::client::Client;
+/// use greynoise::sqlite_cache::SqliteBackend;
+///
+/// let backend = SqliteBackend::open(None).expect("could not open cache");
+/// let cache = Cache::new(backend).stale_while_revalidate(Duration::from_secs(24 * 60 * 60));
+/// let client = Client::new(None).with_cache(cache);
+///```
+pub struct SqliteBackend {
+  conn: Mutex<Connection>,
+}
+
+impl SqliteBackend {
+
+  /// Open (creating if needed) the cache database at `path`, which may start with `~`.
+  ///
+  /// Defaults to `~/.cache/greynoise/cache.sqlite`.
+  ///
+  /// # Errors
+  /// Returns the underlying `rusqlite::Error` if the database cannot be opened or initialized.
+  pub fn open(path: Option<String>) -> rusqlite::Result<SqliteBackend> {
+
+    let path = path.unwrap_or_else(|| String::from(DEFAULT_CACHE_PATH));
+    let path = shellexpand::tilde(&path).to_string();
+
+    if let Some(dir) = std::path::Path::new(&path).parent() {
+      let _ = std::fs::create_dir_all(dir);
+    }
+
+    SqliteBackend::from_connection(Connection::open(path)?)
+
+  }
+
+  /// Create a cache held in an in-memory SQLite database (mostly useful for testing)
+  pub fn in_memory() -> rusqlite::Result<SqliteBackend> {
+    SqliteBackend::from_connection(Connection::open_in_memory()?)
+  }
+
+  fn from_connection(conn: Connection) -> rusqlite::Result<SqliteBackend> {
+
+    conn.busy_timeout(Duration::from_secs(5))?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    conn.execute_batch(SCHEMA)?;
+
+    Ok(SqliteBackend { conn: Mutex::new(conn) })
+
+  }
+
+  /// Number of responses currently stored
+  pub fn len(&self) -> usize {
+    self.conn.lock().unwrap()
+      .query_row("SELECT COUNT(*) FROM responses", [], |row| row.get::<_, i64>(0))
+      .map(|n| n as usize)
+      .unwrap_or(0)
+  }
+
+  /// Returns `true` if no responses are stored
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+}
+
+fn to_unix(t: SystemTime) -> i64 {
+  t.duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+fn from_unix(secs: i64) -> SystemTime {
+  UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64)
+}
+
+impl CacheBackend for SqliteBackend {
+
+  fn get(&self, endpoint: Endpoint, ip: &str) -> Option<CachedResponse> {
+    self.conn.lock().unwrap()
+      .query_row(
+        "SELECT status, body, fetched_at FROM responses WHERE endpoint = ?1 AND ip = ?2",
+        params![endpoint.as_str(), ip],
+        |row| Ok(CachedResponse {
+          status: row.get(0)?,
+          body: row.get(1)?,
+          fetched_at: from_unix(row.get(2)?),
+        }),
+      )
+      .optional()
+      .unwrap_or(None)
+  }
+
+  fn put(&self, endpoint: Endpoint, ip: &str, response: CachedResponse) {
+    let _ = self.conn.lock().unwrap().execute(
+      "INSERT OR REPLACE INTO responses (endpoint, ip, status, body, fetched_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+      params![endpoint.as_str(), ip, response.status, response.body, to_unix(response.fetched_at)],
+    );
+  }
+
+  fn remove(&self, endpoint: Endpoint, ip: &str) {
+    let _ = self.conn.lock().unwrap().execute(
+      "DELETE FROM responses WHERE endpoint = ?1 AND ip = ?2",
+      params![endpoint.as_str(), ip],
+    );
+  }
+
+  fn purge_ip(&self, ip: &str) -> usize {
+    self.conn.lock().unwrap()
+      .execute("DELETE FROM responses WHERE ip = ?1", params![ip])
+      .unwrap_or(0)
+  }
+
+  fn purge_older_than(&self, age: Duration) -> usize {
+    let cutoff = to_unix(SystemTime::now()) - age.as_secs() as i64;
+    self.conn.lock().unwrap()
+      .execute("DELETE FROM responses WHERE fetched_at < ?1", params![cutoff])
+      .unwrap_or(0)
+  }
+
+}
@@ -0,0 +1,323 @@
+//! Export GreyNoise results as STIX 2.1 bundles
+//!
+//! A [`StixBundle`] collects IP lookups (`ip_context::IPContext`, `gnql::Datum`) and tag
+//! metadata (`metadata::Metadatum`) and serializes them as a STIX 2.1 bundle:
+//!
+//! - every IP becomes an `ipv4-addr`/`ipv6-addr` observable and an `indicator` whose pattern
+//!   matches it, linked by a `related-to` relationship
+//! - every tag becomes an object chosen by its [`Category`]: `activity` tags are
+//!   `attack-pattern`s, `worm` tags are `malware`, and `actor`, `search_engine` and `tool`
+//!   tags are `tool`s
+//! - every CVE becomes a `vulnerability`, targeted (or, for malware, exploited) by the tags
+//!   that reference it and related to the indicators of IPs seen exploiting it
+//! - indicators `indicate` the tag objects of the tags their IP carries
+//!
+//! Object IDs are deterministic (UUIDv5), so exporting the same IP or tag twice yields the
+//! same ID and importing overlapping bundles does not create duplicates. Tags on an IP are
+//! matched to tag metadata by name, in whichever order they are added.
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+use crate::facts::{greynoise_uuid, IpFacts};
+use crate::gnql::Datum;
+use crate::ip_context::IPContext;
+use crate::metadata::{Category, Intention, Metadatum};
+
+/// Namespace the STIX 2.1 specification defines for deterministic observable IDs
+#[doc(hidden)]
+const STIX_SCO_NAMESPACE: &str = "00abedb4-aa42-466c-9c01-fed23315a9b7";
+
+/// Builder for a STIX 2.1 bundle.
+///
+/// # Example
+/// ```rust
+/// use greynoise::gnql::Datum;
+/// use greynoise::stix::StixBundle;
+///
+/// let datum: Datum = serde_json::from_value(serde_json::json!({
+///   "ip": "45.83.66.65", "classification": "malicious", "tags": ["Mirai"]
+/// })).unwrap();
+///
+/// let mut bundle = StixBundle::new();
+/// bundle.add_datum(&datum);
+///
+/// let json = bundle.to_value();
+/// assert_eq!(json["type"], "bundle");
+/// assert!(json["objects"].as_array().unwrap().iter().any(|o| o["pattern"] == "[ipv4-addr:value = '45.83.66.65']"));
+///```
+#[derive(Debug, Clone)]
+pub struct StixBundle {
+  timestamp: String,
+  identity: String,
+  objects: Vec<Value>,
+  ids: HashSet<String>,
+  tag_ids: HashMap<String, String>,
+  ip_tags: Vec<(String, Vec<String>)>,
+}
+
+impl Default for StixBundle {
+  fn default() -> StixBundle {
+    StixBundle::new()
+  }
+}
+
+/// Format a STIX timestamp (UTC, millisecond precision)
+fn stix_time(t: chrono::DateTime<chrono::Utc>) -> String {
+  t.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+/// Turn a GreyNoise date (`2021-06-01`) or RFC 3339 timestamp into a STIX timestamp
+fn parse_time(s: &str) -> Option<String> {
+
+  if let Ok(t) = chrono::DateTime::parse_from_rfc3339(s) {
+    return Some(stix_time(t.with_timezone(&chrono::Utc)));
+  }
+
+  chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()
+    .and_then(|d| d.and_hms_opt(0, 0, 0))
+    .map(|t| stix_time(t.and_utc()))
+
+}
+
+/// Deterministic ID for a domain object of type `kind` identified by `key`
+fn sdo_id(kind: &str, key: &str) -> String {
+  format!("{}--{}", kind, greynoise_uuid(&format!("{}:{}", kind, key)))
+}
+
+/// Deterministic ID for an IP observable, as defined by the STIX 2.1 specification
+fn sco_id(kind: &str, value: &str) -> String {
+  let namespace = Uuid::parse_str(STIX_SCO_NAMESPACE).expect("valid STIX namespace");
+  format!("{}--{}", kind, Uuid::new_v5(&namespace, json!({ "value": value }).to_string().as_bytes()))
+}
+
+impl StixBundle {
+
+  /// An empty bundle holding only the GreyNoise `identity` that created its objects
+  pub fn new() -> StixBundle {
+
+    let timestamp = stix_time(chrono::Utc::now());
+    let identity = sdo_id("identity", "GreyNoise Intelligence");
+
+    let mut bundle = StixBundle {
+      timestamp,
+      identity: identity.clone(),
+      objects: Vec::new(),
+      ids: HashSet::new(),
+      tag_ids: HashMap::new(),
+      ip_tags: Vec::new(),
+    };
+
+    bundle.push(json!({
+      "type": "identity",
+      "id": identity,
+      "name": "GreyNoise Intelligence",
+      "identity_class": "organization",
+    }));
+
+    bundle
+
+  }
+
+  /// Add an object unless one with its ID is already in the bundle, filling in the common
+  /// properties
+  fn push(&mut self, object: Value) {
+
+    let mut object: Map<String, Value> = match object {
+      Value::Object(map) => map,
+      _ => return,
+    };
+
+    let id = object["id"].as_str().unwrap_or_default().to_string();
+    if !self.ids.insert(id) {
+      return;
+    }
+
+    object.insert(String::from("spec_version"), Value::from("2.1"));
+
+    // Cyber-observables carry no creation metadata
+    let kind = object["type"].as_str().unwrap_or_default();
+    if !matches!(kind, "ipv4-addr" | "ipv6-addr") {
+      if kind != "identity" {
+        object.insert(String::from("created_by_ref"), Value::from(self.identity.clone()));
+      }
+      let created = object.get("created").cloned().unwrap_or_else(|| Value::from(self.timestamp.clone()));
+      object.insert(String::from("created"), created);
+      object.insert(String::from("modified"), Value::from(self.timestamp.clone()));
+    }
+
+    self.objects.push(Value::Object(object));
+
+  }
+
+  fn relate(&mut self, source: &str, kind: &str, target: &str) {
+    self.push(json!({
+      "type": "relationship",
+      "id": sdo_id("relationship", &format!("{} {} {}", source, kind, target)),
+      "relationship_type": kind,
+      "source_ref": source,
+      "target_ref": target,
+    }));
+  }
+
+  fn vulnerability(&mut self, cve: &str) -> String {
+
+    let id = sdo_id("vulnerability", cve);
+
+    self.push(json!({
+      "type": "vulnerability",
+      "id": id,
+      "name": cve,
+      "external_references": [{ "source_name": "cve", "external_id": cve }],
+    }));
+
+    id
+
+  }
+
+  fn add_ip(&mut self, facts: IpFacts) {
+
+    let kind = match facts.ip.parse::<IpAddr>() {
+      Ok(IpAddr::V4(_)) => "ipv4-addr",
+      Ok(IpAddr::V6(_)) => "ipv6-addr",
+      Err(_) => return,
+    };
+
+    let observable = sco_id(kind, facts.ip);
+    self.push(json!({ "type": kind, "id": observable, "value": facts.ip }));
+
+    let pattern = format!("[{}:value = '{}']", kind, facts.ip);
+    let indicator_type = match facts.classification {
+      Some("malicious") => "malicious-activity",
+      Some("benign") => "benign",
+      _ => "anomalous-activity",
+    };
+
+    let mut description = format!("Observed by GreyNoise scanning the internet; classification: {}", facts.classification.unwrap_or("unknown"));
+    if let Some(actor) = facts.actor.filter(|a| !a.is_empty() && *a != "unknown") {
+      description.push_str(&format!("; actor: {}", actor));
+    }
+
+    let indicator = sdo_id("indicator", &pattern);
+    let mut object = json!({
+      "type": "indicator",
+      "id": indicator,
+      "name": format!("GreyNoise: {}", facts.ip),
+      "description": description,
+      "indicator_types": [indicator_type],
+      "pattern": pattern,
+      "pattern_type": "stix",
+      "valid_from": facts.first_seen.and_then(parse_time).unwrap_or_else(|| self.timestamp.clone()),
+    });
+
+    // STIX forbids empty lists
+    if !facts.tags.is_empty() {
+      object["labels"] = Value::from(facts.tags);
+    }
+
+    self.push(object);
+
+    self.relate(&indicator, "related-to", &observable);
+
+    for cve in facts.cves {
+      let vulnerability = self.vulnerability(cve);
+      self.relate(&indicator, "related-to", &vulnerability);
+    }
+
+    self.ip_tags.push((indicator, facts.tags.to_vec()));
+
+  }
+
+  /// Add an IP Context lookup as an observable and indicator
+  pub fn add_ip_context(&mut self, context: &IPContext) -> &mut StixBundle {
+    self.add_ip(IpFacts::from(context));
+    self
+  }
+
+  /// Add a GNQL result as an observable and indicator
+  pub fn add_datum(&mut self, datum: &Datum) -> &mut StixBundle {
+    self.add_ip(IpFacts::from(datum));
+    self
+  }
+
+  /// Add tag metadata as `attack-pattern`, `malware` or `tool` objects, with their CVEs
+  pub fn add_tags(&mut self, tags: &[Metadatum]) -> &mut StixBundle {
+
+    for tag in tags {
+
+      let kind = match tag.category {
+        Category::Activity => "attack-pattern",
+        Category::Worm => "malware",
+        Category::Actor | Category::SearchEngine | Category::Tool => "tool",
+      };
+
+      let id = sdo_id(kind, &tag.id);
+
+      let mut references = vec![json!({ "source_name": "greynoise", "external_id": tag.slug })];
+      references.extend(tag.references.iter().map(|url| json!({ "source_name": "reference", "url": url })));
+
+      let mut object = json!({
+        "type": kind,
+        "id": id,
+        "name": tag.name,
+        "description": tag.description,
+        "labels": [match tag.intention {
+          Intention::Benign => "benign",
+          Intention::Malicious => "malicious",
+          Intention::Unknown => "unknown",
+        }],
+        "external_references": references,
+      });
+
+      if let Some(created) = parse_time(&tag.created_at) {
+        object["created"] = Value::from(created);
+      }
+
+      if kind == "malware" {
+        object["malware_types"] = json!(["worm"]);
+        object["is_family"] = Value::Bool(true);
+      }
+
+      self.push(object);
+      self.tag_ids.insert(tag.name.clone(), id.clone());
+
+      let verb = if kind == "malware" { "exploits" } else { "targets" };
+      for cve in &tag.cves {
+        let vulnerability = self.vulnerability(cve);
+        self.relate(&id, verb, &vulnerability);
+      }
+
+    }
+
+    self
+
+  }
+
+  /// The bundle as JSON, with `indicates` relationships from each indicator to the
+  /// objects of its tags
+  pub fn to_value(&self) -> Value {
+
+    let mut bundle = self.clone();
+
+    for (indicator, tags) in &self.ip_tags {
+      for tag in tags.iter().filter_map(|t| self.tag_ids.get(t)) {
+        bundle.relate(indicator, "indicates", tag);
+      }
+    }
+
+    json!({
+      "type": "bundle",
+      "id": format!("bundle--{}", Uuid::new_v4()),
+      "objects": bundle.objects,
+    })
+
+  }
+
+  /// The bundle as pretty-printed JSON
+  pub fn to_json(&self) -> String {
+    serde_json::to_string_pretty(&self.to_value()).unwrap_or_default()
+  }
+
+}
@@ -0,0 +1,458 @@
+//! Make requests to the GreyNoise IP Timeline APIs
+//!
+//! The single-field timeline counts the events GreyNoise saw from an IP per time bucket,
+//! broken down by the values of one field (classification, destination port, tag, …). The
+//! hourly summary lists what the IP did in each hour: classification, tags, ports,
+//! protocols, rDNS, user agents and fingerprints.
+use std::fmt;
+use std::str::FromStr;
+
+use crate::get;
+
+/// Field a single-field timeline is broken down by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimelineField {
+  #[default]
+  Classification,
+  DestinationPort,
+  HttpPath,
+  HttpUserAgent,
+  SourceAsn,
+  SourceOrg,
+  SourceRdns,
+  TagIds,
+}
+
+impl TimelineField {
+  /// Name of the field in the API
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      TimelineField::Classification => "classification",
+      TimelineField::DestinationPort => "destination_port",
+      TimelineField::HttpPath => "http_path",
+      TimelineField::HttpUserAgent => "http_user_agent",
+      TimelineField::SourceAsn => "source_asn",
+      TimelineField::SourceOrg => "source_org",
+      TimelineField::SourceRdns => "source_rdns",
+      TimelineField::TagIds => "tag_ids",
+    }
+  }
+}
+
+impl FromStr for TimelineField {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<TimelineField, String> {
+    match s.to_ascii_lowercase().replace('-', "_").as_str() {
+      "classification" => Ok(TimelineField::Classification),
+      "destination_port" | "port" => Ok(TimelineField::DestinationPort),
+      "http_path" => Ok(TimelineField::HttpPath),
+      "http_user_agent" | "user_agent" => Ok(TimelineField::HttpUserAgent),
+      "source_asn" | "asn" => Ok(TimelineField::SourceAsn),
+      "source_org" | "org" => Ok(TimelineField::SourceOrg),
+      "source_rdns" | "rdns" => Ok(TimelineField::SourceRdns),
+      "tag_ids" | "tags" => Ok(TimelineField::TagIds),
+      other => Err(format!(
+        "unknown timeline field '{}' (expected classification, destination_port, http_path, http_user_agent, source_asn, source_org, source_rdns or tag_ids)",
+        other,
+      )),
+    }
+  }
+}
+
+/// Size of a timeline bucket: 1 to 24 hours or 1 to 90 days
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Granularity {
+  Hours(u8),
+  Days(u8),
+}
+
+impl Default for Granularity {
+  fn default() -> Granularity {
+    Granularity::Days(1)
+  }
+}
+
+impl fmt::Display for Granularity {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Granularity::Hours(n) => write!(f, "{}h", n),
+      Granularity::Days(n) => write!(f, "{}d", n),
+    }
+  }
+}
+
+impl FromStr for Granularity {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Granularity, String> {
+
+    let s = s.trim().to_ascii_lowercase();
+    let invalid = || format!("invalid granularity '{}' (expected 1h to 24h or 1d to 90d)", s);
+
+    let (n, unit) = match s.char_indices().next_back() {
+      Some((i, unit)) => (&s[..i], unit),
+      None => return Err(invalid()),
+    };
+    let n: u8 = n.parse().map_err(|_| invalid())?;
+
+    match unit {
+      'h' if (1..=24).contains(&n) => Ok(Granularity::Hours(n)),
+      'd' if (1..=90).contains(&n) => Ok(Granularity::Days(n)),
+      _ => Err(invalid()),
+    }
+
+  }
+}
+
+/// Structure to deserialize a GreyNoise single-field timeline
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Timeline {
+  #[serde(rename = "metadata")]
+  pub metadata: TimelineMetadata,
+
+  #[serde(rename = "results", default)]
+  pub results: Vec<TimelineBucket>,
+}
+
+/// Structure to deserialize the metadata of a single-field timeline.
+///
+/// Any values inside `Option` are optional, and you should check if there is `Some` or `None` before using it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TimelineMetadata {
+  #[serde(rename = "ip")]
+  pub ip: String,
+
+  #[serde(rename = "field")]
+  pub field: Option<String>,
+
+  #[serde(rename = "first_seen")]
+  pub first_seen: Option<String>,
+
+  #[serde(rename = "start")]
+  pub start: Option<String>,
+
+  #[serde(rename = "end")]
+  pub end: Option<String>,
+
+  #[serde(rename = "granularity")]
+  pub granularity: Option<String>,
+
+  #[serde(rename = "metric")]
+  pub metric: Option<String>,
+}
+
+/// Events seen in one time bucket with one value of the timeline's field
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TimelineBucket {
+  /// Start of the bucket (RFC 3339)
+  #[serde(rename = "timestamp")]
+  pub timestamp: String,
+
+  /// Value of the field, e.g. `malicious` for a classification timeline
+  #[serde(rename = "label")]
+  pub label: String,
+
+  /// Number of events
+  #[serde(rename = "data")]
+  pub data: i64,
+}
+
+impl Timeline {
+
+  /// Buckets with events labelled `label`, in the order the API returned them
+  pub fn with_label<'a>(&'a self, label: &'a str) -> impl Iterator<Item = &'a TimelineBucket> + 'a {
+    self.results.iter().filter(move |b| b.data > 0 && b.label.eq_ignore_ascii_case(label))
+  }
+
+  /// First and last bucket timestamps with events labelled `label` — on a classification
+  /// timeline, `span("malicious")` is when the IP started and stopped behaving maliciously
+  pub fn span(&self, label: &str) -> Option<(&str, &str)> {
+
+    let mut timestamps: Vec<&str> = self.results.iter()
+      .filter(|b| b.data > 0 && b.label.eq_ignore_ascii_case(label))
+      .map(|b| b.timestamp.as_str())
+      .collect();
+    timestamps.sort_unstable();
+
+    Some((*timestamps.first()?, *timestamps.last()?))
+
+  }
+
+}
+
+/// Structure to deserialize a GreyNoise hourly activity summary
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HourlySummary {
+  #[serde(rename = "activity", default)]
+  pub activity: Vec<HourlyActivity>,
+
+  #[serde(rename = "metadata")]
+  pub metadata: HourlySummaryMetadata,
+}
+
+/// Structure to deserialize the metadata of an hourly activity summary.
+///
+/// Any values inside `Option` are optional, and you should check if there is `Some` or `None` before using it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HourlySummaryMetadata {
+  #[serde(rename = "ip")]
+  pub ip: String,
+
+  #[serde(rename = "start_time")]
+  pub start_time: Option<String>,
+
+  #[serde(rename = "end_time")]
+  pub end_time: Option<String>,
+
+  #[serde(rename = "limit")]
+  pub limit: Option<i64>,
+
+  /// Pass to [`hourly_summary`] to get the next page
+  #[serde(rename = "next_cursor")]
+  pub next_cursor: Option<String>,
+}
+
+/// Structure to deserialize one hour of activity.
+///
+/// Any values inside `Option` are optional, and you should check if there is `Some` or `None` before using it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HourlyActivity {
+  #[serde(rename = "timestamp")]
+  pub timestamp: String,
+
+  #[serde(rename = "classification")]
+  pub classification: Option<String>,
+
+  #[serde(rename = "rdns")]
+  pub rdns: Option<String>,
+
+  #[serde(rename = "rdns_parent")]
+  pub rdns_parent: Option<String>,
+
+  #[serde(rename = "rdns_validated")]
+  pub rdns_validated: Option<bool>,
+
+  #[serde(rename = "organization")]
+  pub organization: Option<String>,
+
+  #[serde(rename = "asn")]
+  pub asn: Option<String>,
+
+  #[serde(rename = "category")]
+  pub category: Option<String>,
+
+  #[serde(rename = "country")]
+  pub country: Option<String>,
+
+  #[serde(rename = "country_code")]
+  pub country_code: Option<String>,
+
+  #[serde(rename = "region")]
+  pub region: Option<String>,
+
+  #[serde(rename = "city")]
+  pub city: Option<String>,
+
+  #[serde(rename = "spoofable")]
+  pub spoofable: Option<bool>,
+
+  #[serde(rename = "vpn")]
+  pub vpn: Option<bool>,
+
+  #[serde(rename = "vpn_service")]
+  pub vpn_service: Option<String>,
+
+  #[serde(rename = "tor")]
+  pub tor: Option<bool>,
+
+  #[serde(rename = "sensor_count")]
+  pub sensor_count: Option<i64>,
+
+  #[serde(rename = "sensor_hits")]
+  pub sensor_hits: Option<i64>,
+
+  #[serde(rename = "tags", default)]
+  pub tags: Vec<ActivityTag>,
+
+  #[serde(rename = "protocols", default)]
+  pub protocols: Vec<Protocol>,
+
+  #[serde(rename = "destinations", default)]
+  pub destinations: Vec<Destination>,
+
+  #[serde(rename = "http_paths", default)]
+  pub http_paths: Vec<String>,
+
+  #[serde(rename = "http_user_agents", default)]
+  pub http_user_agents: Vec<String>,
+
+  #[serde(rename = "ja3_fingerprints", default)]
+  pub ja3_fingerprints: Vec<Fingerprint>,
+
+  #[serde(rename = "hassh_fingerprints", default)]
+  pub hassh_fingerprints: Vec<Fingerprint>,
+}
+
+/// Structure to deserialize a tag seen during one hour.
+///
+/// Any values inside `Option` are optional, and you should check if there is `Some` or `None` before using it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ActivityTag {
+  #[serde(rename = "id")]
+  pub id: Option<String>,
+
+  #[serde(rename = "name")]
+  pub name: String,
+
+  #[serde(rename = "category")]
+  pub category: Option<String>,
+
+  #[serde(rename = "intention")]
+  pub intention: Option<String>,
+
+  #[serde(rename = "description")]
+  pub description: Option<String>,
+}
+
+/// Structure to deserialize a port and protocol seen during one hour.
+///
+/// Any values inside `Option` are optional, and you should check if there is `Some` or `None` before using it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Protocol {
+  #[serde(rename = "port")]
+  pub port: Option<i64>,
+
+  #[serde(rename = "transport_protocol")]
+  pub transport_protocol: Option<String>,
+
+  #[serde(rename = "app_protocol")]
+  pub app_protocol: Option<String>,
+}
+
+/// Structure to deserialize a destination country seen during one hour.
+///
+/// Any values inside `Option` are optional, and you should check if there is `Some` or `None` before using it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Destination {
+  #[serde(rename = "country")]
+  pub country: Option<String>,
+
+  #[serde(rename = "country_code")]
+  pub country_code: Option<String>,
+}
+
+/// Structure to deserialize a JA3 or HASSH fingerprint seen during one hour.
+///
+/// Any values inside `Option` are optional, and you should check if there is `Some` or `None` before using it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Fingerprint {
+  #[serde(rename = "fingerprint")]
+  pub fingerprint: Option<String>,
+
+  #[serde(rename = "port")]
+  pub port: Option<i64>,
+}
+
+#[doc(hidden)]
+pub(crate) const TIMELINE_URL: &str = "https://api.greynoise.io/v3/noise/ips";
+
+/// Build an IP Timeline URL for `ip` and `endpoint` (`timeline` or `hourly-summary`)
+#[doc(hidden)]
+pub(crate) fn timeline_url(ip: &str, endpoint: &str, params: &[(&str, String)]) -> String {
+
+  let base = format!("{}/{}/{}", TIMELINE_URL, ip, endpoint);
+
+  match url::Url::parse_with_params(&base, params) {
+    Ok(url) => url.to_string(),
+    Err(_) => base,
+  }
+
+}
+
+/// Query parameters of a single-field timeline request
+#[doc(hidden)]
+pub(crate) fn timeline_params(field: TimelineField, days: Option<u32>, granularity: Option<Granularity>) -> Vec<(&'static str, String)> {
+
+  let mut params = vec![("field", field.as_str().to_string())];
+
+  if let Some(days) = days {
+    params.push(("days", days.to_string()));
+  }
+
+  if let Some(granularity) = granularity {
+    params.push(("granularity", granularity.to_string()));
+  }
+
+  params
+
+}
+
+/// Query parameters of an hourly summary request
+#[doc(hidden)]
+pub(crate) fn hourly_summary_params(days: Option<u32>, limit: Option<u32>, cursor: Option<&str>) -> Vec<(&'static str, String)> {
+
+  let mut params = Vec::new();
+
+  if let Some(days) = days {
+    params.push(("days", days.to_string()));
+  }
+
+  if let Some(limit) = limit {
+    params.push(("limit", limit.to_string()));
+  }
+
+  if let Some(cursor) = cursor {
+    params.push(("cursor", cursor.to_string()));
+  }
+
+  params
+
+}
+
+/// Function to retrieve the activity of an IP over time, broken down by one field
+///
+/// Counts the events GreyNoise saw from `ip` over the last `days` days (1 by default), in
+/// buckets of `granularity` (one day by default), per value of `field`.
+///
+/// For more information on the IP Timeline API endpoint check the [API docs](https://docs.greynoise.io/reference/get_v3-noise-ips-ip-timeline).
+///
+/// # Example
+/// ```rust
+/// use greynoise::timeline::{self, Granularity, TimelineField};
+/// //async {
+///  //let res = timeline::timeline("71.6.233.151", TimelineField::Classification, Some(30), Some(Granularity::Days(1)), Some(API_KEY)).await;
+///  //let malicious = res.unwrap().span("malicious");
+/// //};
+///```
+///
+/// # Errors
+/// If the call fails, it will return a `Err(StatusCode)`.
+/// To see the possible return values, check the [API docs](https://docs.greynoise.io/reference/get_v3-noise-ips-ip-timeline).
+pub async fn timeline(ip: &str, field: TimelineField, days: Option<u32>, granularity: Option<Granularity>, key: Option<&str>) -> Result<Timeline, http::StatusCode> {
+
+  let url = timeline_url(ip, "timeline", &timeline_params(field, days, granularity));
+  let res: Result<Timeline, http::StatusCode> = get::query(url, key).await;
+
+  res
+
+}
+
+/// Function to retrieve an hour-by-hour summary of an IP's activity
+///
+/// Covers the last `days` days (1 by default), at most `limit` hours per page. Pass the
+/// `next_cursor` of a page as `cursor` to get the next one.
+///
+/// For more information on the IP Timeline Hourly Summary API endpoint check the [API docs](https://docs.greynoise.io/reference/get_v3-noise-ips-ip-hourly-summary).
+///
+/// # Errors
+/// If the call fails, it will return a `Err(StatusCode)`.
+/// To see the possible return values, check the [API docs](https://docs.greynoise.io/reference/get_v3-noise-ips-ip-hourly-summary).
+pub async fn hourly_summary(ip: &str, days: Option<u32>, limit: Option<u32>, cursor: Option<&str>, key: Option<&str>) -> Result<HourlySummary, http::StatusCode> {
+
+  let url = timeline_url(ip, "hourly-summary", &hourly_summary_params(days, limit, cursor));
+  let res: Result<HourlySummary, http::StatusCode> = get::query(url, key).await;
+
+  res
+
+}
@@ -0,0 +1,439 @@
+//! Pluggable HTTP transport and middleware
+//!
+//! Every request a [`crate::client::Client`] makes is described by an [`HttpRequest`] and
+//! handed to an [`HttpTransport`]: `ReqwestTransport` by default (the `reqwest` feature), or
+//! any implementation given to [`crate::client::Client::with_transport`] (an in-process fake
+//! for tests, a transport built on another HTTP stack, …).
+//!
+//! The module-level functions (`quick_check::quick_check`, `riot::riot`, …) send their
+//! requests through a process-wide default pipeline instead, configured with
+//! [`set_default_transport`] and [`add_default_middleware`]; clients created afterwards start
+//! from it too. Without the `reqwest` feature there is no built-in transport, and requests
+//! fail with `501 Not Implemented` until one is set.
+//!
+//! Requests pass through a chain of [`Middleware`] on the way, in the order they were added
+//! with [`crate::client::Client::with_middleware`]. Each middleware can change the request,
+//! answer it itself, or hand it on with [`Next::run`] and inspect the response. [`Headers`],
+//! [`Logging`] and [`Metrics`] are provided; [`crate::cassette::Cassette`] is a middleware
+//! too.
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+use http::StatusCode;
+
+/// A boxed, sendable future, as returned by transports and middleware
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// HTTP methods used by the GreyNoise API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HttpMethod {
+  Get,
+  Post,
+}
+
+impl HttpMethod {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      HttpMethod::Get => "GET",
+      HttpMethod::Post => "POST",
+    }
+  }
+}
+
+/// A request to the GreyNoise API
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+  pub method: HttpMethod,
+  pub url: String,
+
+  /// Header names and values; the API key travels in the `key` header
+  pub headers: Vec<(String, String)>,
+
+  /// JSON body, for POST requests
+  pub body: Option<String>,
+}
+
+impl HttpRequest {
+
+  /// A GET request for `url`
+  pub fn get(url: String) -> HttpRequest {
+    HttpRequest { method: HttpMethod::Get, url, headers: Vec::new(), body: None }
+  }
+
+  /// A POST request for `url` with a JSON `body`
+  pub fn post(url: String, body: String) -> HttpRequest {
+    HttpRequest {
+      method: HttpMethod::Post,
+      url,
+      headers: vec![(String::from("content-type"), String::from("application/json"))],
+      body: Some(body),
+    }
+  }
+
+  /// Add a header
+  pub fn header(mut self, name: &str, value: &str) -> HttpRequest {
+    self.headers.push((name.to_string(), value.to_string()));
+    self
+  }
+
+  /// Add the `key` header if there is a key
+  pub fn key(self, key: Option<&str>) -> HttpRequest {
+    match key {
+      Some(k) => self.header("key", k),
+      None => self,
+    }
+  }
+
+  /// Value of the first header named `name` (case-insensitive)
+  pub fn header_value(&self, name: &str) -> Option<&str> {
+    self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+  }
+
+}
+
+/// Status and unparsed body of an API response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+  pub status: StatusCode,
+  pub body: String,
+}
+
+/// Sends requests to the API.
+///
+/// # Errors
+/// Implementations return `Err(StatusCode)` only when no response was received (connection
+/// failures, timeouts); error responses from the API are returned as `Ok` with their status.
+///
+/// # Example
+/// ```rust
+/// use greynoise::client::Client;
+/// use greynoise::transport::{BoxFuture, HttpRequest, HttpResponse, HttpTransport};
+///
+/// /// Answers every request from memory
+/// struct Canned;
+///
+/// impl HttpTransport for Canned {
+///   fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, http::StatusCode>> {
+///     Box::pin(async move {
+///       let ip = request.url.rsplit('/').next().unwrap_or_default().to_string();
+///       let body = format!(r#"{{"ip":"{}","noise":false,"riot":true,"code":"0x00"}}"#, ip);
+///       Ok(HttpResponse { status: http::StatusCode::OK, body })
+///     })
+///   }
+/// }
+///
+/// let client = Client::new(None).with_transport(Canned);
+/// async {
+///  assert!(client.quick_check("8.8.8.8").await.unwrap().riot);
+/// };
+///```
+pub trait HttpTransport: Send + Sync {
+  fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, StatusCode>>;
+}
+
+/// The default transport, sending requests with `reqwest`
+#[cfg(feature = "reqwest")]
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+  client: reqwest::Client,
+}
+
+#[cfg(feature = "reqwest")]
+impl ReqwestTransport {
+
+  pub fn new() -> ReqwestTransport {
+    ReqwestTransport::default()
+  }
+
+  /// Send requests with an already configured `reqwest` client (proxies, timeouts, TLS…)
+  pub fn with_client(client: reqwest::Client) -> ReqwestTransport {
+    ReqwestTransport { client }
+  }
+
+}
+
+#[cfg(feature = "reqwest")]
+impl HttpTransport for ReqwestTransport {
+  fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, StatusCode>> {
+    Box::pin(async move {
+
+      let mut call = match request.method {
+        HttpMethod::Get => self.client.get(&request.url),
+        HttpMethod::Post => self.client.post(&request.url),
+      };
+
+      for (name, value) in &request.headers {
+        call = call.header(name.as_str(), value.as_str());
+      }
+
+      if let Some(body) = request.body {
+        call = call.body(body);
+      }
+
+      let resp = match call.send().await {
+        Ok(r) => r,
+        Err(e) => return Err(e.status().unwrap_or(StatusCode::BAD_REQUEST)),
+      };
+
+      let status = resp.status();
+
+      match resp.text().await {
+        Ok(body) => Ok(HttpResponse { status, body }),
+        Err(e) => Err(e.status().unwrap_or(StatusCode::BAD_REQUEST)),
+      }
+
+    })
+  }
+}
+
+/// A step in the request pipeline
+pub trait Middleware: Send + Sync {
+  /// Handle `request`, usually by passing it (possibly changed) to `next`
+  fn handle<'a>(&'a self, request: HttpRequest, next: Next<'a>) -> BoxFuture<'a, Result<HttpResponse, StatusCode>>;
+}
+
+/// The rest of the pipeline after a middleware
+pub struct Next<'a> {
+  transport: &'a dyn HttpTransport,
+  chain: &'a [Arc<dyn Middleware>],
+}
+
+impl<'a> Next<'a> {
+
+  /// Send `request` through the remaining middleware and the transport
+  pub fn run(self, request: HttpRequest) -> BoxFuture<'a, Result<HttpResponse, StatusCode>> {
+    match self.chain.split_first() {
+      Some((first, rest)) => first.handle(request, Next { transport: self.transport, chain: rest }),
+      None => self.transport.send(request),
+    }
+  }
+
+}
+
+/// A transport and its middleware chain
+#[doc(hidden)]
+#[derive(Clone)]
+pub(crate) struct Pipeline {
+  transport: Arc<dyn HttpTransport>,
+  chain: Vec<Arc<dyn Middleware>>,
+}
+
+/// Stands in for a transport when the `reqwest` feature is off and none was set
+#[cfg(not(feature = "reqwest"))]
+struct NoTransport;
+
+#[cfg(not(feature = "reqwest"))]
+impl HttpTransport for NoTransport {
+  fn send(&self, _request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, StatusCode>> {
+    Box::pin(async { Err(StatusCode::NOT_IMPLEMENTED) })
+  }
+}
+
+impl Default for Pipeline {
+  #[cfg(feature = "reqwest")]
+  fn default() -> Pipeline {
+    Pipeline { transport: Arc::new(ReqwestTransport::new()), chain: Vec::new() }
+  }
+
+  #[cfg(not(feature = "reqwest"))]
+  fn default() -> Pipeline {
+    Pipeline { transport: Arc::new(NoTransport), chain: Vec::new() }
+  }
+}
+
+impl Pipeline {
+
+  pub(crate) fn transport(&mut self, transport: Arc<dyn HttpTransport>) {
+    self.transport = transport;
+  }
+
+  pub(crate) fn push(&mut self, middleware: Arc<dyn Middleware>) {
+    self.chain.push(middleware);
+  }
+
+  pub(crate) async fn send(&self, request: HttpRequest) -> Result<HttpResponse, StatusCode> {
+    Next { transport: self.transport.as_ref(), chain: &self.chain }.run(request).await
+  }
+
+}
+
+#[doc(hidden)]
+static DEFAULT_PIPELINE: OnceLock<RwLock<Pipeline>> = OnceLock::new();
+
+fn default_lock() -> &'static RwLock<Pipeline> {
+  DEFAULT_PIPELINE.get_or_init(|| RwLock::new(Pipeline::default()))
+}
+
+/// The pipeline the module-level functions send through, and new clients start from
+#[doc(hidden)]
+pub(crate) fn default_pipeline() -> Pipeline {
+  default_lock().read().unwrap().clone()
+}
+
+/// Send the module-level functions' requests, and those of clients created afterwards, with
+/// `transport`
+///
+/// # Example
+/// ```rust
+/// use greynoise::transport::{self, BoxFuture, HttpRequest, HttpResponse, HttpTransport};
+///
+/// /// Refuses every request
+/// struct Offline;
+///
+/// impl HttpTransport for Offline {
+///   fn send(&self, _request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, http::StatusCode>> {
+///     Box::pin(async { Err(http::StatusCode::SERVICE_UNAVAILABLE) })
+///   }
+/// }
+///
+/// transport::set_default_transport(Offline);
+/// async {
+///  assert!(greynoise::quick_check::quick_check("8.8.8.8", None).await.is_err());
+/// };
+///```
+pub fn set_default_transport<T: HttpTransport + 'static>(transport: T) {
+  default_lock().write().unwrap().transport(Arc::new(transport));
+}
+
+/// Add `middleware` to the end of the default pipeline's chain
+pub fn add_default_middleware<M: Middleware + 'static>(middleware: M) {
+  default_lock().write().unwrap().push(Arc::new(middleware));
+}
+
+/// Go back to the built-in transport, with no middleware
+pub fn reset_default_pipeline() {
+  *default_lock().write().unwrap() = Pipeline::default();
+}
+
+/// Adds fixed headers (a `User-Agent`, a tracing ID…) to every request
+#[derive(Debug, Clone, Default)]
+pub struct Headers {
+  headers: Vec<(String, String)>,
+}
+
+impl Headers {
+
+  pub fn new() -> Headers {
+    Headers::default()
+  }
+
+  /// Add `name: value` to every request
+  pub fn header(mut self, name: &str, value: &str) -> Headers {
+    self.headers.push((name.to_string(), value.to_string()));
+    self
+  }
+
+}
+
+impl Middleware for Headers {
+  fn handle<'a>(&'a self, mut request: HttpRequest, next: Next<'a>) -> BoxFuture<'a, Result<HttpResponse, StatusCode>> {
+    request.headers.extend(self.headers.iter().cloned());
+    next.run(request)
+  }
+}
+
+/// Logs each request's method, URL, status and duration (never its headers, so the API
+/// key stays out of logs)
+pub struct Logging {
+  sink: Box<dyn Fn(&str) + Send + Sync>,
+}
+
+impl Default for Logging {
+  fn default() -> Logging {
+    Logging::new()
+  }
+}
+
+impl Logging {
+
+  /// Log to stderr
+  pub fn new() -> Logging {
+    Logging { sink: Box::new(|line| eprintln!("{}", line)) }
+  }
+
+  /// Hand each log line to `sink`
+  pub fn to<F: Fn(&str) + Send + Sync + 'static>(sink: F) -> Logging {
+    Logging { sink: Box::new(sink) }
+  }
+
+}
+
+impl Middleware for Logging {
+  fn handle<'a>(&'a self, request: HttpRequest, next: Next<'a>) -> BoxFuture<'a, Result<HttpResponse, StatusCode>> {
+    Box::pin(async move {
+
+      let (method, url) = (request.method, request.url.clone());
+      let started = Instant::now();
+      let res = next.run(request).await;
+
+      let outcome = match &res {
+        Ok(resp) => resp.status.to_string(),
+        Err(status) => format!("failed ({})", status),
+      };
+      (self.sink)(&format!("{} {} -> {} in {:?}", method.as_str(), url, outcome, started.elapsed()));
+
+      res
+
+    })
+  }
+}
+
+/// Counters collected by [`Metrics`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+  /// Requests sent
+  pub requests: u64,
+
+  /// Requests that got no response
+  pub failures: u64,
+
+  /// Responses by status code
+  pub statuses: HashMap<u16, u64>,
+
+  /// Time spent waiting for responses
+  pub total_latency: Duration,
+}
+
+/// Counts requests, responses by status and latency. Clones share their counters, so keep
+/// a clone to read them after adding one to a client.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+  counters: Arc<Mutex<MetricsSnapshot>>,
+}
+
+impl Metrics {
+
+  pub fn new() -> Metrics {
+    Metrics::default()
+  }
+
+  /// The counters so far
+  pub fn snapshot(&self) -> MetricsSnapshot {
+    self.counters.lock().unwrap().clone()
+  }
+
+}
+
+impl Middleware for Metrics {
+  fn handle<'a>(&'a self, request: HttpRequest, next: Next<'a>) -> BoxFuture<'a, Result<HttpResponse, StatusCode>> {
+    Box::pin(async move {
+
+      let started = Instant::now();
+      let res = next.run(request).await;
+
+      let mut counters = self.counters.lock().unwrap();
+      counters.requests += 1;
+      counters.total_latency += started.elapsed();
+      match &res {
+        Ok(resp) => *counters.statuses.entry(resp.status.as_u16()).or_insert(0) += 1,
+        Err(_) => counters.failures += 1,
+      }
+
+      res
+
+    })
+  }
+}
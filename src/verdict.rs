@@ -0,0 +1,225 @@
+//! One normalized verdict per IP from Quick Check, RIOT and IP Context
+//!
+//! [`verdict`] asks Quick Check first and only deepens when the answer calls for it: a RIOT
+//! lookup for RIOT hits and an IP Context lookup for noise hits, so an IP GreyNoise has
+//! never seen costs one call. The responses are folded into a [`Disposition`], a
+//! [`Confidence`] and the [`Reason`]s behind them, and kept alongside for anything the
+//! verdict does not cover.
+use std::fmt;
+
+use crate::ip_context::{self, IPContext};
+use crate::quick_check::{self, QuickCheck};
+use crate::riot::{self, RIOT};
+
+/// What an IP is, as far as GreyNoise can tell
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Disposition {
+  /// A known benign service or organization (RIOT)
+  BenignService,
+
+  /// Scanning the internet, classified benign (search engines, researchers)
+  KnownScanner,
+
+  /// Scanning the internet, classified malicious
+  Malicious,
+
+  /// Scanning the internet, intent not classified
+  Unknown,
+
+  /// Neither seen scanning nor a known service
+  NotSeen,
+}
+
+impl Disposition {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Disposition::BenignService => "benign_service",
+      Disposition::KnownScanner => "known_scanner",
+      Disposition::Malicious => "malicious",
+      Disposition::Unknown => "unknown",
+      Disposition::NotSeen => "not_seen",
+    }
+  }
+}
+
+impl fmt::Display for Disposition {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// How firmly the evidence supports the disposition
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Confidence {
+  Low,
+  Medium,
+  High,
+}
+
+/// A piece of evidence behind a verdict
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Reason {
+  /// Listed in RIOT; the details are missing if the RIOT lookup found nothing
+  Riot {
+    name: Option<String>,
+    category: Option<String>,
+    trust_level: Option<String>,
+  },
+
+  /// IP Context classification
+  Classification { classification: String },
+
+  /// Actor attributed by IP Context
+  Actor { name: String },
+
+  /// Tag carried in IP Context
+  Tag { name: String },
+
+  /// IP Context says the traffic could have been spoofed
+  Spoofable,
+
+  /// Quick Check saw no scanning and no RIOT listing
+  NotObserved,
+}
+
+impl fmt::Display for Reason {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Reason::Riot { name, category, trust_level } => {
+        write!(f, "RIOT: {}", name.as_deref().unwrap_or("listed"))?;
+        if let Some(category) = category {
+          write!(f, " ({})", category)?;
+        }
+        match trust_level {
+          Some(level) => write!(f, ", trust level {}", level),
+          None => Ok(()),
+        }
+      }
+      Reason::Classification { classification } => write!(f, "classified {}", classification),
+      Reason::Actor { name } => write!(f, "actor: {}", name),
+      Reason::Tag { name } => write!(f, "tag: {}", name),
+      Reason::Spoofable => f.write_str("traffic may be spoofed"),
+      Reason::NotObserved => f.write_str("not observed scanning and not a known service"),
+    }
+  }
+}
+
+/// Verdict for one IP, with the responses it was drawn from
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Verdict {
+  pub ip: String,
+  pub disposition: Disposition,
+  pub confidence: Confidence,
+  pub reasons: Vec<Reason>,
+
+  /// Quick Check response
+  pub quick_check: QuickCheck,
+
+  /// RIOT response, when the IP is a RIOT hit
+  pub riot: Option<RIOT>,
+
+  /// IP Context response, when the IP is noise
+  pub context: Option<IPContext>,
+}
+
+impl Verdict {
+
+  /// Fold the responses for `quick_check.ip` into a verdict. `riot` and `context` are only
+  /// consulted when Quick Check flagged the IP as RIOT and noise respectively.
+  pub fn from_responses(quick_check: QuickCheck, riot: Option<RIOT>, context: Option<IPContext>) -> Verdict {
+
+    let mut reasons = Vec::new();
+
+    if quick_check.riot {
+      reasons.push(Reason::Riot {
+        name: riot.as_ref().map(|r| r.name.clone()),
+        category: riot.as_ref().map(|r| r.category.clone()),
+        trust_level: riot.as_ref().map(|r| r.trust_level.clone()),
+      });
+    }
+
+    let classification = context.as_ref()
+      .filter(|_| quick_check.noise)
+      .and_then(|c| c.classification.clone())
+      .map(|c| c.to_ascii_lowercase());
+
+    if let Some(ctx) = context.as_ref().filter(|_| quick_check.noise) {
+      if let Some(classification) = &classification {
+        reasons.push(Reason::Classification { classification: classification.clone() });
+      }
+      if let Some(actor) = ctx.actor.as_ref().filter(|a| !a.is_empty() && !a.eq_ignore_ascii_case("unknown")) {
+        reasons.push(Reason::Actor { name: actor.clone() });
+      }
+      for tag in ctx.tags.iter().flatten() {
+        reasons.push(Reason::Tag { name: tag.clone() });
+      }
+      if ctx.spoofable == Some(true) {
+        reasons.push(Reason::Spoofable);
+      }
+    }
+
+    let spoofable = reasons.contains(&Reason::Spoofable);
+    let has_actor = reasons.iter().any(|r| matches!(r, Reason::Actor { .. }));
+
+    let (disposition, confidence) = match (quick_check.riot, quick_check.noise, classification.as_deref()) {
+      (_, true, Some("malicious")) if spoofable => (Disposition::Malicious, Confidence::Medium),
+      (_, true, Some("malicious")) => (Disposition::Malicious, Confidence::High),
+      (true, _, _) => {
+        let confidence = match riot.as_ref().map(|r| r.trust_level.as_str()) {
+          Some("1") => Confidence::High,
+          _ => Confidence::Medium,
+        };
+        (Disposition::BenignService, confidence)
+      }
+      (false, true, Some("benign")) if has_actor => (Disposition::KnownScanner, Confidence::High),
+      (false, true, Some("benign")) => (Disposition::KnownScanner, Confidence::Medium),
+      (false, true, Some(_)) => (Disposition::Unknown, Confidence::Medium),
+      (false, true, None) => (Disposition::Unknown, Confidence::Low),
+      (false, false, _) => {
+        reasons.push(Reason::NotObserved);
+        (Disposition::NotSeen, Confidence::High)
+      }
+    };
+
+    Verdict { ip: quick_check.ip.clone(), disposition, confidence, reasons, quick_check, riot, context }
+
+  }
+
+}
+
+/// Function to retrieve a single verdict for an IP address
+///
+/// Calls Quick Check, then RIOT if the IP is a RIOT hit and IP Context if it is noise, and
+/// folds the answers into a [`Verdict`]. An IP GreyNoise has not seen costs one call.
+///
+/// # Example
+/// ```rust
+/// use greynoise::verdict::{self, Disposition};
+/// //async {
+///  //let res = verdict::verdict("45.83.66.65", Some(API_KEY)).await.unwrap();
+///  //if res.disposition == Disposition::Malicious { ... }
+/// //};
+///```
+///
+/// # Errors
+/// If any of the calls fails, it will return its `Err(StatusCode)`.
+pub async fn verdict(ip: &str, key: Option<&str>) -> Result<Verdict, http::StatusCode> {
+
+  let qc = quick_check::quick_check(ip, key).await?;
+
+  let riot = match qc.riot {
+    true => riot::riot(ip, key).await?.found(),
+    false => None,
+  };
+
+  let context = match qc.noise {
+    true => Some(ip_context::ip_context(ip, key).await?),
+    false => None,
+  };
+
+  Ok(Verdict::from_responses(qc, riot, context))
+
+}